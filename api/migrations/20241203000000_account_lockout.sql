@@ -0,0 +1,10 @@
+-- Failed login tracking and temporary account lockout
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS failed_login_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_failed_login_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locked_until TIMESTAMP WITH TIME ZONE;
+
+-- Locked users get an unlock link, issued as an action token
+ALTER TABLE action_tokens DROP CONSTRAINT IF EXISTS action_tokens_purpose_check;
+ALTER TABLE action_tokens ADD CONSTRAINT action_tokens_purpose_check
+    CHECK (purpose IN ('email_verification', 'password_reset', 'account_unlock'));
\ No newline at end of file
@@ -0,0 +1,90 @@
+-- Role-based access control: roles, their permissions and role grants
+
+CREATE TABLE IF NOT EXISTS roles (
+    name VARCHAR(50) PRIMARY KEY,
+    description TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS permissions (
+    name VARCHAR(100) PRIMARY KEY,
+    description TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS role_permissions (
+    role VARCHAR(50) NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+    permission VARCHAR(100) NOT NULL REFERENCES permissions(name) ON DELETE CASCADE,
+    PRIMARY KEY (role, permission)
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(50) NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+    granted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (user_id, role)
+);
+
+INSERT INTO roles (name, description) VALUES
+    ('customer', 'Retail customer managing their own accounts'),
+    ('corporate_admin', 'Administrator of a corporate customer'),
+    ('corporate_member', 'Member of a corporate customer with limited rights'),
+    ('support', 'Customer support staff'),
+    ('compliance', 'Compliance and audit staff'),
+    ('admin', 'Platform administrator')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO permissions (name, description) VALUES
+    ('accounts:read', 'View own accounts and balances'),
+    ('accounts:write', 'Open accounts'),
+    ('cards:read', 'View own cards'),
+    ('cards:write', 'Issue cards'),
+    ('transactions:read', 'View own transactions'),
+    ('transactions:write', 'Send and transfer money'),
+    ('beneficiaries:read', 'View saved beneficiaries'),
+    ('beneficiaries:write', 'Add and remove beneficiaries'),
+    ('corporate:members:manage', 'Manage the members of a corporate customer'),
+    ('users:read', 'Look up any user'),
+    ('audit:read', 'Read the audit trail'),
+    ('roles:manage', 'Grant and revoke roles')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role, permission) VALUES
+    ('customer', 'accounts:read'),
+    ('customer', 'accounts:write'),
+    ('customer', 'cards:read'),
+    ('customer', 'cards:write'),
+    ('customer', 'transactions:read'),
+    ('customer', 'transactions:write'),
+    ('customer', 'beneficiaries:read'),
+    ('customer', 'beneficiaries:write'),
+    ('corporate_admin', 'accounts:read'),
+    ('corporate_admin', 'accounts:write'),
+    ('corporate_admin', 'cards:read'),
+    ('corporate_admin', 'cards:write'),
+    ('corporate_admin', 'transactions:read'),
+    ('corporate_admin', 'transactions:write'),
+    ('corporate_admin', 'beneficiaries:read'),
+    ('corporate_admin', 'beneficiaries:write'),
+    ('corporate_admin', 'corporate:members:manage'),
+    ('corporate_member', 'accounts:read'),
+    ('corporate_member', 'cards:read'),
+    ('corporate_member', 'transactions:read'),
+    ('corporate_member', 'transactions:write'),
+    ('corporate_member', 'beneficiaries:read'),
+    ('support', 'users:read'),
+    ('compliance', 'users:read'),
+    ('compliance', 'audit:read')
+ON CONFLICT DO NOTHING;
+
+-- Administrators hold every permission
+INSERT INTO role_permissions (role, permission)
+SELECT 'admin', name FROM permissions
+ON CONFLICT DO NOTHING;
+
+-- Existing users keep access to their own banking features
+INSERT INTO user_roles (user_id, role)
+SELECT id, CASE WHEN user_type = 'corporate' THEN 'corporate_admin' ELSE 'customer' END FROM users
+ON CONFLICT DO NOTHING;
+
+CREATE INDEX IF NOT EXISTS idx_user_roles_role ON user_roles(role);
\ No newline at end of file
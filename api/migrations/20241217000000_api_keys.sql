@@ -0,0 +1,16 @@
+-- API keys: hashed secrets looked up by a public prefix, with scopes and usage tracking
+
+-- Secrets are checked against their SHA-256 hash. They are also kept in
+-- encrypted_secret, encrypted with ENCRYPTION_KEY, as the HMAC key of signed
+-- partner requests, so anyone holding that key can recover them. Keys issued
+-- before request signing have no encrypted secret and can't sign.
+ALTER TABLE api_keys ALTER COLUMN encrypted_secret DROP NOT NULL;
+
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS prefix VARCHAR(16);
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS secret_hash VARCHAR(64);
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS scopes TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS last_used_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS expires_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS revoked_at TIMESTAMP WITH TIME ZONE;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_prefix ON api_keys(prefix);
\ No newline at end of file
@@ -0,0 +1,80 @@
+-- Tamper-evident audit trail. Each entry stores the SHA-256 of the previous
+-- entry's hash followed by its own canonical form, so editing, inserting or
+-- deleting an entry breaks every later link. Signed checkpoints of the chain
+-- end catch a chain recomputed from scratch.
+
+-- Entries keep the actor's id after the user is deleted: rewriting them would
+-- break the chain, and regulators need the id anyway
+ALTER TABLE audit_logs DROP CONSTRAINT IF EXISTS audit_logs_user_id_fkey;
+
+ALTER TABLE audit_logs ADD COLUMN IF NOT EXISTS sequence BIGINT UNIQUE;
+ALTER TABLE audit_logs ADD COLUMN IF NOT EXISTS prev_hash VARCHAR(64);
+ALTER TABLE audit_logs ADD COLUMN IF NOT EXISTS hash VARCHAR(64);
+
+-- The canonical form hashed for an entry. The verifier hashes this same text
+-- itself rather than trusting audit_entry_hash.
+CREATE OR REPLACE FUNCTION audit_entry_canonical(
+    sequence BIGINT, id UUID, user_id UUID, action TEXT, resource_type TEXT, resource_id UUID,
+    details JSONB, ip_address INET, user_agent TEXT, created_at TIMESTAMPTZ
+) RETURNS TEXT LANGUAGE sql IMMUTABLE AS $$
+    SELECT jsonb_build_array(
+        sequence, id, user_id, action, resource_type, resource_id, details,
+        host(ip_address), user_agent, (extract(epoch FROM created_at) * 1000000)::BIGINT
+    )::TEXT
+$$;
+
+CREATE OR REPLACE FUNCTION audit_entry_hash(
+    prev_hash TEXT, sequence BIGINT, id UUID, user_id UUID, action TEXT, resource_type TEXT, resource_id UUID,
+    details JSONB, ip_address INET, user_agent TEXT, created_at TIMESTAMPTZ
+) RETURNS TEXT LANGUAGE sql IMMUTABLE AS $$
+    SELECT encode(sha256(convert_to(
+        prev_hash || audit_entry_canonical(sequence, id, user_id, action, resource_type, resource_id, details, ip_address, user_agent, created_at),
+        'UTF8'
+    )), 'hex')
+$$;
+
+-- The end of the chain. Appends update this single row, whose lock orders them.
+CREATE TABLE IF NOT EXISTS audit_chain_head (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    sequence BIGINT NOT NULL,
+    prev_hash VARCHAR(64) NOT NULL,
+    hash VARCHAR(64) NOT NULL
+);
+
+INSERT INTO audit_chain_head (sequence, prev_hash, hash)
+VALUES (0, repeat('0', 64), repeat('0', 64))
+ON CONFLICT DO NOTHING;
+
+-- Chain the entries written before this migration, oldest first
+DO $$
+DECLARE
+    entry RECORD;
+    head RECORD;
+BEGIN
+    FOR entry IN SELECT * FROM audit_logs WHERE sequence IS NULL ORDER BY created_at, id LOOP
+        UPDATE audit_chain_head SET
+            sequence = sequence + 1,
+            prev_hash = hash,
+            hash = audit_entry_hash(hash, sequence + 1, entry.id, entry.user_id, entry.action, entry.resource_type,
+                entry.resource_id, entry.details, entry.ip_address, entry.user_agent, entry.created_at)
+        WHERE id
+        RETURNING * INTO head;
+
+        UPDATE audit_logs SET sequence = head.sequence, prev_hash = head.prev_hash, hash = head.hash
+        WHERE id = entry.id;
+    END LOOP;
+END
+$$;
+
+ALTER TABLE audit_logs ALTER COLUMN sequence SET NOT NULL;
+ALTER TABLE audit_logs ALTER COLUMN prev_hash SET NOT NULL;
+ALTER TABLE audit_logs ALTER COLUMN hash SET NOT NULL;
+ALTER TABLE audit_logs ALTER COLUMN created_at SET NOT NULL;
+
+-- Chain ends signed with the JWT signing key, verifiable with the public JWKS
+CREATE TABLE IF NOT EXISTS audit_checkpoints (
+    sequence BIGINT PRIMARY KEY,
+    hash VARCHAR(64) NOT NULL,
+    signature TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
\ No newline at end of file
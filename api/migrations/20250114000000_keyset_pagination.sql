@@ -0,0 +1,4 @@
+-- Listings are paginated by (created_at, id), newest first
+CREATE INDEX IF NOT EXISTS idx_transactions_account_keyset ON transactions(account_id, created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_cards_account_keyset ON cards(account_id, created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_beneficiaries_user_keyset ON beneficiaries(user_id, created_at DESC, id DESC);
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Full-text search of transaction descriptions; queries must use the same expression
+CREATE INDEX IF NOT EXISTS idx_transactions_description_search ON transactions USING GIN (to_tsvector('simple', COALESCE(description, '')));
+CREATE INDEX IF NOT EXISTS idx_transactions_beneficiary_iban ON transactions(beneficiary_iban);
\ No newline at end of file
@@ -0,0 +1,107 @@
+-- Outbound webhooks. Events are written to webhook_events; a trigger queues
+-- one delivery per subscription that asked for the event's type, and the
+-- delivery worker posts them with retries.
+
+INSERT INTO permissions (name, description) VALUES
+    ('webhooks:manage', 'Subscribe to webhooks and read their deliveries')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role, permission) VALUES
+    ('customer', 'webhooks:manage'),
+    ('corporate_admin', 'webhooks:manage'),
+    ('admin', 'webhooks:manage')
+ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS webhook_subscriptions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- OAuth client that subscribed, which alone manages the subscription;
+    -- NULL for the bank's own apps and API keys
+    client_id VARCHAR(64) REFERENCES oauth_clients(client_id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    description VARCHAR(255),
+    event_types TEXT[] NOT NULL,
+    -- Signing secret, encrypted with ENCRYPTION_KEY
+    encrypted_secret TEXT NOT NULL,
+    -- Failed attempts since the last successful one, and when they started
+    consecutive_failures INT NOT NULL DEFAULT 0,
+    failing_since TIMESTAMP WITH TIME ZONE,
+    disabled_at TIMESTAMP WITH TIME ZONE,
+    disabled_reason TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_subscriptions_user_id ON webhook_subscriptions(user_id);
+
+CREATE TABLE IF NOT EXISTS webhook_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    event_type VARCHAR(100) NOT NULL,
+    data JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    subscription_id UUID NOT NULL REFERENCES webhook_subscriptions(id) ON DELETE CASCADE,
+    event_id UUID NOT NULL REFERENCES webhook_events(id) ON DELETE CASCADE,
+    -- pending, delivered or failed once out of attempts
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- Outcome of the last attempt
+    response_status INT,
+    last_error TEXT,
+    last_attempt_at TIMESTAMP WITH TIME ZONE,
+    -- Delivery this one manually resends
+    redelivery_of UUID REFERENCES webhook_deliveries(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_subscription_keyset ON webhook_deliveries(subscription_id, created_at DESC, id DESC);
+
+CREATE OR REPLACE FUNCTION webhook_fan_out() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+    INSERT INTO webhook_deliveries (subscription_id, event_id)
+    SELECT id, NEW.id FROM webhook_subscriptions
+    WHERE user_id = NEW.user_id AND disabled_at IS NULL AND NEW.event_type = ANY(event_types);
+    RETURN NEW;
+END
+$$;
+
+DROP TRIGGER IF EXISTS webhook_events_fan_out ON webhook_events;
+CREATE TRIGGER webhook_events_fan_out AFTER INSERT ON webhook_events
+    FOR EACH ROW EXECUTE FUNCTION webhook_fan_out();
+
+-- Statuses of transactions and cards are also moved by the payment and card
+-- processors, so their changes are caught here rather than in the API. The
+-- statuses are written like in API responses.
+CREATE OR REPLACE FUNCTION webhook_status_changed() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+DECLARE
+    owner UUID;
+BEGIN
+    SELECT user_id INTO owner FROM accounts WHERE id = NEW.account_id;
+    IF owner IS NOT NULL THEN
+        INSERT INTO webhook_events (user_id, event_type, data)
+        VALUES (owner, TG_ARGV[0], jsonb_build_object(
+            'id', NEW.id,
+            'account_id', NEW.account_id,
+            'status', initcap(NEW.status),
+            'previous_status', initcap(OLD.status)
+        ));
+    END IF;
+    RETURN NEW;
+END
+$$;
+
+DROP TRIGGER IF EXISTS transactions_status_webhook ON transactions;
+CREATE TRIGGER transactions_status_webhook AFTER UPDATE OF status ON transactions
+    FOR EACH ROW WHEN (OLD.status IS DISTINCT FROM NEW.status)
+    EXECUTE FUNCTION webhook_status_changed('transaction.status_changed');
+
+DROP TRIGGER IF EXISTS cards_status_webhook ON cards;
+CREATE TRIGGER cards_status_webhook AFTER UPDATE OF status ON cards
+    FOR EACH ROW WHEN (OLD.status IS DISTINCT FROM NEW.status)
+    EXECUTE FUNCTION webhook_status_changed('card.status_changed');
\ No newline at end of file
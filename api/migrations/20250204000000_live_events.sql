@@ -0,0 +1,48 @@
+-- Notifies the live event listener of balance and transaction changes,
+-- whoever makes them. Notifications are sent on commit only.
+
+CREATE OR REPLACE FUNCTION live_balance_changed() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+    IF NEW.user_id IS NOT NULL THEN
+        PERFORM pg_notify('live_events', jsonb_build_object(
+            'type', 'balance',
+            'user_id', NEW.user_id,
+            'account_id', NEW.id,
+            'balance', NEW.balance,
+            'currency', NEW.currency
+        )::text);
+    END IF;
+    RETURN NEW;
+END
+$$;
+
+DROP TRIGGER IF EXISTS accounts_live_balance ON accounts;
+CREATE TRIGGER accounts_live_balance AFTER UPDATE OF balance ON accounts
+    FOR EACH ROW WHEN (OLD.balance IS DISTINCT FROM NEW.balance)
+    EXECUTE FUNCTION live_balance_changed();
+
+CREATE OR REPLACE FUNCTION live_transaction_changed() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+DECLARE
+    owner UUID;
+BEGIN
+    SELECT user_id INTO owner FROM accounts WHERE id = NEW.account_id;
+    IF owner IS NOT NULL THEN
+        PERFORM pg_notify('live_events', jsonb_build_object(
+            'type', 'transaction',
+            'user_id', owner,
+            'transaction_id', NEW.id,
+            'created', TG_OP = 'INSERT'
+        )::text);
+    END IF;
+    RETURN NEW;
+END
+$$;
+
+DROP TRIGGER IF EXISTS transactions_live_created ON transactions;
+CREATE TRIGGER transactions_live_created AFTER INSERT ON transactions
+    FOR EACH ROW EXECUTE FUNCTION live_transaction_changed();
+
+DROP TRIGGER IF EXISTS transactions_live_status ON transactions;
+CREATE TRIGGER transactions_live_status AFTER UPDATE OF status ON transactions
+    FOR EACH ROW WHEN (OLD.status IS DISTINCT FROM NEW.status)
+    EXECUTE FUNCTION live_transaction_changed();
\ No newline at end of file
@@ -0,0 +1,63 @@
+-- Transactional outbox. Services record domain events in the transaction of
+-- the change they report; the relay worker hands each event to every
+-- registered handler until all of them took it.
+
+CREATE TABLE IF NOT EXISTS outbox_events (
+    -- Same on every attempt, for handlers to drop duplicates
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    event_type VARCHAR(100) NOT NULL,
+    data JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- Failed relays, and the error of the last one
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- Set once every handler took the event
+    dispatched_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_outbox_events_due ON outbox_events(next_attempt_at) WHERE dispatched_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_outbox_events_dispatched_at ON outbox_events(dispatched_at);
+
+-- Handlers that took an event, so a retry only goes to the ones that failed
+CREATE TABLE IF NOT EXISTS outbox_dispatches (
+    event_id UUID NOT NULL REFERENCES outbox_events(id) ON DELETE CASCADE,
+    handler VARCHAR(100) NOT NULL,
+    dispatched_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (event_id, handler)
+);
+
+-- Status changes now go through the outbox like every other event, with the
+-- same data as before.
+CREATE OR REPLACE FUNCTION outbox_status_changed() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+DECLARE
+    owner UUID;
+BEGIN
+    SELECT user_id INTO owner FROM accounts WHERE id = NEW.account_id;
+    IF owner IS NOT NULL THEN
+        INSERT INTO outbox_events (user_id, event_type, data)
+        VALUES (owner, TG_ARGV[0], jsonb_build_object(
+            'id', NEW.id,
+            'account_id', NEW.account_id,
+            'status', initcap(NEW.status),
+            'previous_status', initcap(OLD.status)
+        ));
+    END IF;
+    RETURN NEW;
+END
+$$;
+
+DROP TRIGGER IF EXISTS transactions_status_webhook ON transactions;
+DROP TRIGGER IF EXISTS transactions_status_outbox ON transactions;
+CREATE TRIGGER transactions_status_outbox AFTER UPDATE OF status ON transactions
+    FOR EACH ROW WHEN (OLD.status IS DISTINCT FROM NEW.status)
+    EXECUTE FUNCTION outbox_status_changed('transaction.status_changed');
+
+DROP TRIGGER IF EXISTS cards_status_webhook ON cards;
+DROP TRIGGER IF EXISTS cards_status_outbox ON cards;
+CREATE TRIGGER cards_status_outbox AFTER UPDATE OF status ON cards
+    FOR EACH ROW WHEN (OLD.status IS DISTINCT FROM NEW.status)
+    EXECUTE FUNCTION outbox_status_changed('card.status_changed');
+
+DROP FUNCTION IF EXISTS webhook_status_changed();
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Step-up ceremonies: a signed-in user proves a passkey again before removing
+-- a second factor. Kept apart from login ceremonies so their assertions can't
+-- open a session.
+ALTER TABLE webauthn_challenges DROP CONSTRAINT IF EXISTS webauthn_challenges_ceremony_check;
+ALTER TABLE webauthn_challenges ADD CONSTRAINT webauthn_challenges_ceremony_check
+    CHECK (ceremony IN ('registration', 'authentication', 'step_up'));
\ No newline at end of file
@@ -0,0 +1,11 @@
+-- Audit entries are written unchained, in the transaction of the change they
+-- record, and a single background appender links them into the chain. Linking
+-- them on write updated audit_chain_head in that transaction, so every audited
+-- write waited for the transactions that wrote an entry before it to end.
+
+-- NULL until the appender chains the entry
+ALTER TABLE audit_logs ALTER COLUMN sequence DROP NOT NULL;
+ALTER TABLE audit_logs ALTER COLUMN prev_hash DROP NOT NULL;
+ALTER TABLE audit_logs ALTER COLUMN hash DROP NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_audit_logs_unchained ON audit_logs(created_at, id) WHERE sequence IS NULL;
\ No newline at end of file
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+/// Path of the configuration file when `--config` isn't given.
+pub const CONFIG_FILE_ENV: &str = "VAELIX_CONFIG";
+
+/// Settings of the TOML file as `(section, key, environment variable)`. Every
+/// setting can be overridden by its environment variable.
+pub const SETTINGS: &[(&str, &str, &str)] = &[
+    ("server", "env", "NODE_ENV"),
+    ("server", "bind_address", "BIND_ADDRESS"),
+    ("server", "port", "PORT"),
+    ("server", "app_base_url", "APP_BASE_URL"),
+    ("server", "trusted_proxies", "TRUSTED_PROXIES"),
+    ("database", "url", "DATABASE_URL"),
+    ("database", "max_connections", "DATABASE_MAX_CONNECTIONS"),
+    ("database", "min_connections", "DATABASE_MIN_CONNECTIONS"),
+    ("database", "acquire_timeout_seconds", "DATABASE_ACQUIRE_TIMEOUT_SECONDS"),
+    ("database", "idle_timeout_seconds", "DATABASE_IDLE_TIMEOUT_SECONDS"),
+    ("auth", "jwt_secret", "JWT_SECRET"),
+    ("auth", "jwt_keys_dir", "JWT_KEYS_DIR"),
+    ("auth", "jwt_signing_kid", "JWT_SIGNING_KID"),
+    ("auth", "jwt_issuer", "JWT_ISSUER"),
+    ("auth", "jwt_audience", "JWT_AUDIENCE"),
+    ("auth", "argon2_memory_kib", "ARGON2_MEMORY_KIB"),
+    ("auth", "argon2_iterations", "ARGON2_ITERATIONS"),
+    ("auth", "argon2_parallelism", "ARGON2_PARALLELISM"),
+    ("auth", "webauthn_rp_id", "WEBAUTHN_RP_ID"),
+    ("auth", "webauthn_rp_name", "WEBAUTHN_RP_NAME"),
+    ("auth", "webauthn_origins", "WEBAUTHN_ORIGINS"),
+    ("rate_limits", "requests", "RATE_LIMIT_REQUESTS"),
+    ("rate_limits", "window_seconds", "RATE_LIMIT_WINDOW_SECONDS"),
+    ("encryption", "key", "ENCRYPTION_KEY"),
+    ("audit", "checkpoint_interval_seconds", "AUDIT_CHECKPOINT_INTERVAL_SECONDS"),
+    ("api", "v1_deprecation", "API_V1_DEPRECATION"),
+    ("api", "v1_sunset", "API_V1_SUNSET"),
+    ("mail", "sender", "MAIL_SENDER"),
+    ("mail", "from", "MAIL_FROM"),
+    ("mail", "outbox_dir", "MAIL_OUTBOX_DIR"),
+    ("webhooks", "sender", "WEBHOOK_SENDER"),
+    ("webhooks", "timeout_seconds", "WEBHOOK_TIMEOUT_SECONDS"),
+    ("webhooks", "allow_private_targets", "WEBHOOK_ALLOW_PRIVATE_TARGETS"),
+    ("features", "webauthn", "FEATURE_WEBAUTHN"),
+    ("features", "oauth", "FEATURE_OAUTH"),
+];
+
+/// Where settings are read from: the environment first, then the file.
+#[derive(Debug, Default)]
+pub struct Source {
+    file: HashMap<&'static str, String>,
+}
+
+impl Source {
+    /// Reads the file at `path`, or `VAELIX_CONFIG` if not given, recording
+    /// every unreadable or unknown setting in `problems`. Without either,
+    /// only the environment is used.
+    pub fn load(path: Option<&Path>, problems: &mut Vec<String>) -> Self {
+        let path = match path.map(Path::to_path_buf).or_else(|| env::var(CONFIG_FILE_ENV).ok().map(Into::into)) {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents, problems),
+            Err(e) => {
+                problems.push(format!("cannot read {}: {}", path.display(), e));
+                Self::default()
+            }
+        }
+    }
+
+    pub fn parse(contents: &str, problems: &mut Vec<String>) -> Self {
+        let table: toml::Table = match contents.parse() {
+            Ok(table) => table,
+            Err(e) => {
+                problems.push(format!("invalid configuration file: {}", e));
+                return Self::default();
+            }
+        };
+
+        let mut file = HashMap::new();
+        for (section, values) in &table {
+            let Some(values) = values.as_table() else {
+                problems.push(format!("[{}] must be a section", section));
+                continue;
+            };
+
+            for (key, value) in values {
+                let Some(&(_, _, name)) = SETTINGS.iter().find(|(s, k, _)| s == section && k == key) else {
+                    problems.push(format!("unknown setting {}.{}", section, key));
+                    continue;
+                };
+
+                match scalar(value) {
+                    Some(value) => {
+                        file.insert(name, value);
+                    }
+                    None => problems.push(format!("{}.{} must be a string, number, boolean or list of strings", section, key)),
+                }
+            }
+        }
+
+        Self { file }
+    }
+
+    pub fn var(&self, name: &str) -> Option<String> {
+        env::var(name).ok().or_else(|| self.file.get(name).cloned())
+    }
+}
+
+/// Renders a file value the way it would be written in its environment
+/// variable; lists are comma-separated.
+fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        _ => None,
+    }
+}
+
+/// The configuration file named by `--config <path>` or `--config=<path>`.
+pub fn config_path(args: &[String]) -> Option<&str> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--config" {
+            return iter.next().map(String::as_str);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_maps_sections_to_variables() {
+        let mut problems = Vec::new();
+        let source = Source::parse(
+            r#"
+            [server]
+            port = 9000
+
+            [auth]
+            webauthn_origins = ["http://localhost:3000", "capacitor://localhost"]
+            jwt_issuer = { nested = true }
+
+            [features]
+            oauth = false
+            colour = "blue"
+            "#,
+            &mut problems,
+        );
+
+        assert_eq!(source.file.get("PORT").map(String::as_str), Some("9000"));
+        assert_eq!(source.file.get("WEBAUTHN_ORIGINS").map(String::as_str), Some("http://localhost:3000,capacitor://localhost"));
+        assert_eq!(source.file.get("FEATURE_OAUTH").map(String::as_str), Some("false"));
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+
+        assert_eq!(config_path(&["--print-config".to_string(), "--config=a.toml".to_string()]), Some("a.toml"));
+        assert_eq!(config_path(&["--config".to_string(), "b.toml".to_string()]), Some("b.toml"));
+    }
+}
\ No newline at end of file
@@ -1,2 +1,3 @@
 // Configuration module
-pub mod app_config;
\ No newline at end of file
+pub mod app_config;
+pub mod file;
\ No newline at end of file
@@ -1,36 +1,53 @@
-use axum::{Json, http::StatusCode, extract::Path, Extension};
+use axum::{Json, Extension};
 use serde::Serialize;
-use uuid::Uuid;
+use utoipa::ToSchema;
 use crate::services::account_service::{self, CreateAccountRequest, AccountResponse};
 use crate::services::database::DbPool;
+use crate::middleware::ownership::OwnedAccount;
+use crate::utils::validation::ValidatedJson;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct IbanResponse {
     pub iban: String,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/accounts",
+    tag = "accounts",
+    request_body = CreateAccountRequest,
+    responses((status = 200, description = "Account created", body = AccountResponse)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn create_account(
     Extension(pool): Extension<DbPool>,
-    Json(payload): Json<CreateAccountRequest>,
-) -> Result<Json<AccountResponse>, StatusCode> {
-    // TODO: Get user_id from authentication
-    let user_id = Uuid::new_v4(); // Mock user_id for now
+    Extension(claims): Extension<Claims>,
+    ValidatedJson(payload): ValidatedJson<CreateAccountRequest>,
+) -> Result<Json<AccountResponse>, ApiError> {
+    let user_id = claims.user_id()?;
 
-    match account_service::create_account(&pool, user_id, payload).await {
-        Ok(account) => Ok(Json(account)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    let account = account_service::create_account(&pool, user_id, payload).await?;
+    Ok(Json(account))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/accounts/{id}/iban",
+    tag = "accounts",
+    params(("id" = Uuid, Path, description = "Account id")),
+    responses((status = 200, description = "IBAN of the account", body = IbanResponse)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn get_iban(
     Extension(pool): Extension<DbPool>,
-    Path(account_id): Path<Uuid>,
-) -> Result<Json<IbanResponse>, StatusCode> {
-    match account_service::get_account_iban(&pool, account_id).await {
-        Ok(Some(iban)) => Ok(Json(IbanResponse { iban })),
-        Ok(None) => Err(StatusCode::NOT_FOUND),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    OwnedAccount(account_id): OwnedAccount,
+) -> Result<Json<IbanResponse>, ApiError> {
+    match account_service::get_account_iban(&pool, account_id).await? {
+        Some(iban) => Ok(Json(IbanResponse { iban })),
+        None => Err(ApiError::NotFound),
     }
 }
\ No newline at end of file
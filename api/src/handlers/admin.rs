@@ -0,0 +1,145 @@
+use axum::{Json, http::{HeaderMap, StatusCode}, extract::{Path, Query}, Extension};
+use serde_json::json;
+use uuid::Uuid;
+use crate::services::{audit_service, rbac_service};
+use crate::services::database::DbPool;
+use crate::config::app_config::AppConfig;
+use crate::models::audit::{AuditLogPage, AuditLogQuery, ChainVerification};
+use crate::models::role::{AssignRoleRequest, RoleGrant};
+use crate::utils::client;
+use crate::utils::error::{ApiError, FieldError};
+use crate::utils::jwt::Claims;
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/users/{id}/roles",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "User id")),
+    responses((status = 200, description = "Roles granted to the user", body = [RoleGrant])),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn get_user_roles(
+    Extension(pool): Extension<DbPool>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<RoleGrant>>, ApiError> {
+    let grants = rbac_service::get_role_grants(&pool, user_id).await?;
+    Ok(Json(grants))
+}
+
+/// Grants a role. It applies to access tokens issued from the next login on.
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{id}/roles",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "User id")),
+    request_body = AssignRoleRequest,
+    responses((status = 204, description = "Role granted")),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn assign_role(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    Path(user_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(payload): Json<AssignRoleRequest>,
+) -> Result<StatusCode, ApiError> {
+    let admin_id = claims.user_id()?;
+
+    match rbac_service::assign_role(&pool, user_id, &payload.role, Some(admin_id)).await {
+        Ok(()) => {}
+        // Unknown user or role
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => return Err(ApiError::NotFound),
+        Err(e) => return Err(e.into()),
+    }
+
+    audit_service::record(
+        &pool,
+        Some(admin_id),
+        "role_granted",
+        audit_service::RESOURCE_USER,
+        Some(user_id),
+        json!({ "role": payload.role }),
+        client::client_ip(&headers).as_deref(),
+        client::user_agent(&headers).as_deref(),
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/users/{id}/roles/{role}",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "User id"), ("role" = String, Path, description = "Role name")),
+    responses((status = 204, description = "Role revoked")),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn revoke_role(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    Path((user_id, role)): Path<(Uuid, String)>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    let admin_id = claims.user_id()?;
+
+    if !rbac_service::revoke_role(&pool, user_id, &role).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    audit_service::record(
+        &pool,
+        Some(admin_id),
+        "role_revoked",
+        audit_service::RESOURCE_USER,
+        Some(user_id),
+        json!({ "role": role }),
+        client::client_ip(&headers).as_deref(),
+        client::user_agent(&headers).as_deref(),
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Searches the audit trail, newest first. See `AuditLogQuery` for the filters.
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit-logs",
+    tag = "admin",
+    params(AuditLogQuery),
+    responses((status = 200, description = "Matching entries, newest first", body = AuditLogPage)),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn get_audit_logs(
+    Extension(pool): Extension<DbPool>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<AuditLogPage>, ApiError> {
+    if query.ip_address.as_deref().is_some_and(|ip| ip.parse::<std::net::IpAddr>().is_err()) {
+        return Err(ApiError::Validation(vec![FieldError::new("ip_address", "ip_address", "must be an IPv4 or IPv6 address")]));
+    }
+
+    let page = audit_service::search(&pool, &query).await?;
+    Ok(Json(page))
+}
+
+/// Walks the hash chain of the audit trail and reports the first broken link.
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit-logs/verify",
+    tag = "admin",
+    responses((status = 200, description = "Outcome of the verification", body = ChainVerification)),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn verify_audit_logs(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+) -> Result<Json<ChainVerification>, ApiError> {
+    let verification = audit_service::verify_chain(&pool, &config.jwt_keys).await?;
+    Ok(Json(verification))
+}
\ No newline at end of file
@@ -0,0 +1,113 @@
+use axum::{Json, http::{HeaderMap, StatusCode}, extract::Path, Extension};
+use serde_json::json;
+use uuid::Uuid;
+use crate::services::{api_key_service, audit_service};
+use crate::services::database::DbPool;
+use crate::services::encryption_service::EncryptionService;
+use crate::services::event_bus::EventBus;
+use crate::models::api_key::{ApiKeyResponse, CreateApiKeyRequest, CreatedApiKeyResponse};
+use crate::models::live_event::SecurityAlertKind;
+use crate::utils::client;
+use crate::utils::validation::ValidatedJson;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+
+/// Issues a key for the caller. The full key is only in this response.
+#[utoipa::path(
+    post,
+    path = "/api/api-keys",
+    tag = "api-keys",
+    request_body = CreateApiKeyRequest,
+    responses((status = 201, description = "Key issued", body = CreatedApiKeyResponse)),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn create_api_key(
+    Extension(pool): Extension<DbPool>,
+    Extension(encryption): Extension<EncryptionService>,
+    Extension(events): Extension<EventBus>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedApiKeyResponse>), ApiError> {
+    let user_id = claims.user_id()?;
+
+    // A key can't grant more than its issuer holds
+    if !payload.scopes.iter().all(|scope| claims.has_permission(scope)) {
+        return Err(ApiError::Forbidden);
+    }
+
+    let (key, api_key) = api_key_service::create_api_key(&pool, &encryption, user_id, payload).await?;
+
+    audit_service::record(
+        &pool,
+        Some(user_id),
+        "api_key_created",
+        audit_service::RESOURCE_API_KEY,
+        Some(key.id),
+        json!({ "name": key.name, "prefix": key.prefix, "scopes": key.scopes }),
+        client::client_ip(&headers).as_deref(),
+        client::user_agent(&headers).as_deref(),
+    )
+    .await?;
+
+    events
+        .alert(user_id, SecurityAlertKind::ApiKeyCreated, client::client_ip(&headers), client::user_agent(&headers))
+        .await;
+
+    Ok((StatusCode::CREATED, Json(CreatedApiKeyResponse { key: key.into(), api_key })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/api-keys",
+    tag = "api-keys",
+    responses((status = 200, description = "Keys of the caller", body = [ApiKeyResponse])),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn get_api_keys(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<ApiKeyResponse>>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    let keys = api_key_service::get_api_keys_by_user(&pool, user_id).await?;
+    Ok(Json(keys.into_iter().map(ApiKeyResponse::from).collect()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/api-keys/{id}",
+    tag = "api-keys",
+    params(("id" = Uuid, Path, description = "Key id")),
+    responses((status = 204, description = "Key revoked")),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn revoke_api_key(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    let user_id = claims.user_id()?;
+
+    if !api_key_service::revoke_api_key(&pool, id, user_id).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    audit_service::record(
+        &pool,
+        Some(user_id),
+        "api_key_revoked",
+        audit_service::RESOURCE_API_KEY,
+        Some(id),
+        json!({}),
+        client::client_ip(&headers).as_deref(),
+        client::user_agent(&headers).as_deref(),
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
\ No newline at end of file
@@ -1,60 +1,286 @@
-use axum::{Json, http::StatusCode, Extension};
+use axum::{Json, http::{header, HeaderMap, StatusCode}, extract::Path, response::IntoResponse, Extension};
 use serde::{Deserialize, Serialize};
-use crate::services::auth_service;
+use utoipa::ToSchema;
+use serde_json::json;
+use uuid::Uuid;
+use chrono::Utc;
+use crate::services::{audit_service, auth_service, lockout_service, mfa_service, rbac_service, session_service};
+use crate::services::auth_service::AuthOutcome;
 use crate::services::database::DbPool;
+use crate::services::event_bus::EventBus;
 use crate::config::app_config::AppConfig;
+use crate::middleware::auth::SessionCache;
+use crate::middleware::rate_limit::LoginThrottle;
+use crate::models::live_event::SecurityAlertKind;
+use crate::models::session::SessionResponse;
+use crate::models::user::User;
+use crate::utils::client;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::{self, Claims};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct LoginResponse {
     pub token: String,
     pub refresh_token: String,
     pub user: UserResponse,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub methods: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum LoginOutcome {
+    Authenticated(LoginResponse),
+    MfaRequired(MfaChallengeResponse),
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = SessionUser)]
 pub struct UserResponse {
     pub id: String,
     pub email: String,
     pub name: String,
     pub user_type: String,
+    pub roles: Vec<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses((status = 200, description = "Tokens, or a second factor challenge", body = LoginOutcome))
+)]
 pub async fn login(
     Extension(pool): Extension<DbPool>,
     Extension(config): Extension<AppConfig>,
+    Extension(login_throttle): Extension<LoginThrottle>,
+    Extension(events): Extension<EventBus>,
+    headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
+) -> Result<Json<LoginOutcome>, ApiError> {
+    let ip_address = client::client_ip(&headers);
+
+    if let Some(wait) = login_throttle.retry_after(&payload.email, ip_address.as_deref()).await {
+        return Err(ApiError::TooManyRequests { retry_after: Some(wait) });
+    }
+
     // Authenticate user
-    let user = match auth_service::authenticate_user(&pool, &payload.email, &payload.password).await {
-        Ok(Some(user)) => user,
-        Ok(None) => return Err(StatusCode::UNAUTHORIZED),
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    let user = match auth_service::authenticate_user(&pool, &payload.email, &payload.password, &config.password_params).await {
+        Ok(AuthOutcome::Authenticated(user)) => user,
+        Ok(AuthOutcome::InvalidCredentials { user_id }) => {
+            login_throttle.record_failure(&payload.email, ip_address.as_deref()).await;
+
+            audit_service::record(
+                &pool,
+                user_id,
+                "login_failed",
+                audit_service::RESOURCE_USER,
+                user_id,
+                json!({ "email": payload.email }),
+                ip_address.as_deref(),
+                client::user_agent(&headers).as_deref(),
+            )
+            .await?;
+
+            if let Some(user_id) = user_id {
+                let locked_until = lockout_service::record_failure(
+                    &pool,
+                    user_id,
+                    ip_address.as_deref(),
+                    client::user_agent(&headers).as_deref(),
+                    &config.jwt_secret,
+                    &config.app_base_url,
+                )
+                .await?;
+
+                // Answered like any wrong password: the owner learns of the
+                // lock by email, or once they give the right password
+                if locked_until.is_some() {
+                    events.alert(user_id, SecurityAlertKind::AccountLocked, ip_address, client::user_agent(&headers)).await;
+                }
+            }
+
+            return Err(ApiError::Unauthorized);
+        }
+        Ok(AuthOutcome::Locked { until }) => {
+            let retry_after = (until - Utc::now()).to_std().unwrap_or_default();
+            return Err(ApiError::Locked { retry_after });
+        }
+        Err(e) => return Err(e.into()),
     };
 
+    // Users with a second factor get a challenge instead of tokens. Their
+    // failures are only forgiven once it is answered, so wrong codes add up
+    // with wrong passwords.
+    let methods = mfa_service::second_factor_methods(&pool, user.id).await?;
+
+    if !methods.is_empty() {
+        let challenge_id = mfa_service::create_challenge(&pool, user.id).await?;
+        let mfa_token = jwt::create_mfa_challenge_token(&user.id.to_string(), &challenge_id.to_string(), &config.jwt_keys)?;
+
+        return Ok(Json(LoginOutcome::MfaRequired(MfaChallengeResponse {
+            mfa_required: true,
+            mfa_token,
+            methods,
+        })));
+    }
+
+    login_throttle.record_success(&payload.email).await;
+    lockout_service::reset_failures(&pool, user.id).await?;
+
+    let response = start_session(&pool, &config, &events, &headers, user).await?;
+    Ok(Json(LoginOutcome::Authenticated(response)))
+}
+
+/// Opens a session for the device in `headers` and issues its token pair.
+/// Called once every required authentication factor has been checked.
+pub async fn start_session(
+    pool: &DbPool,
+    config: &AppConfig,
+    events: &EventBus,
+    headers: &HeaderMap,
+    user: User,
+) -> Result<LoginResponse, ApiError> {
+    let session_id = session_service::create_session(
+        pool,
+        user.id,
+        client::user_agent(headers).as_deref(),
+        client::client_ip(headers).as_deref(),
+    )
+    .await?;
+
+    audit_service::record(
+        pool,
+        Some(user.id),
+        "login",
+        audit_service::RESOURCE_SESSION,
+        Some(session_id),
+        json!({ "email": user.email }),
+        client::client_ip(headers).as_deref(),
+        client::user_agent(headers).as_deref(),
+    )
+    .await?;
+
+    events
+        .alert(user.id, SecurityAlertKind::NewSignIn, client::client_ip(headers), client::user_agent(headers))
+        .await;
+
+    let access = rbac_service::get_user_access(pool, user.id).await?;
+
     // Generate tokens
-    let (token, refresh_token) = match auth_service::generate_tokens(&user, &config.jwt_secret, &config.jwt_refresh_secret) {
-        Ok(tokens) => tokens,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-    };
+    let (token, refresh_token) = auth_service::generate_tokens(&user, session_id, &access, &config.jwt_keys)?;
 
     let user_response = UserResponse {
         id: user.id.to_string(),
         email: user.email,
         name: user.name,
         user_type: format!("{:?}", user.user_type),
+        roles: access.roles,
     };
 
-    let response = LoginResponse {
+    Ok(LoginResponse {
         token,
         refresh_token,
         user: user_response,
-    };
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    tag = "auth",
+    responses((status = 204, description = "Session revoked")),
+    security(("bearer" = []))
+)]
+pub async fn logout(
+    Extension(pool): Extension<DbPool>,
+    Extension(session_cache): Extension<SessionCache>,
+    Extension(claims): Extension<Claims>,
+) -> Result<StatusCode, ApiError> {
+    let user_id = claims.user_id()?;
+    let session_id = Uuid::parse_str(&claims.sid)
+        .map_err(|_| ApiError::Unauthorized)?;
+
+    session_service::revoke_session(&pool, session_id, user_id).await?;
+    session_cache.revoke(session_id).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/sessions",
+    tag = "auth",
+    responses((status = 200, description = "Active sessions of the caller", body = [SessionResponse])),
+    security(("bearer" = []))
+)]
+pub async fn list_sessions(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<SessionResponse>>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    let sessions = session_service::get_sessions_by_user(&pool, user_id).await?;
+
+    let response = sessions
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: session.id.to_string() == claims.sid,
+            id: session.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            expires_at: session.expires_at,
+        })
+        .collect();
 
     Ok(Json(response))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/auth/sessions/{id}",
+    tag = "auth",
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses((status = 204, description = "Session revoked")),
+    security(("bearer" = []))
+)]
+pub async fn revoke_session(
+    Extension(pool): Extension<DbPool>,
+    Extension(session_cache): Extension<SessionCache>,
+    Extension(claims): Extension<Claims>,
+    Path(session_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let user_id = claims.user_id()?;
+
+    if !session_service::revoke_session(&pool, session_id, user_id).await? {
+        return Err(ApiError::NotFound);
+    }
+    session_cache.revoke(session_id).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Public keys verifying our access tokens, for other services.
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "auth",
+    responses((status = 200, description = "JSON Web Key Set", body = Object))
+)]
+pub async fn jwks(
+    Extension(config): Extension<AppConfig>,
+) -> impl IntoResponse {
+    ([(header::CACHE_CONTROL, "public, max-age=300")], Json(config.jwt_keys.jwks().clone()))
 }
\ No newline at end of file
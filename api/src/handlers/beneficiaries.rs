@@ -1,67 +1,102 @@
-use axum::{Json, http::StatusCode, extract::Path, Extension};
+use axum::{Json, http::StatusCode, extract::{Path, Query}, response::{IntoResponse, Response}, Extension};
 use uuid::Uuid;
 use crate::services::beneficiary_service;
 use crate::services::database::DbPool;
+use crate::middleware::versioning::ApiVersion;
 use crate::models::beneficiary::{CreateBeneficiaryRequest, BeneficiaryResponse};
+use crate::models::pagination::{Page, PageQuery};
+use crate::utils::validation::ValidatedJson;
+use crate::utils::error::ApiError;
 use crate::utils::jwt::Claims;
+use crate::utils::pagination::{page_size, Cursor, Order};
 
+#[utoipa::path(
+    post,
+    path = "/api/beneficiaries",
+    tag = "beneficiaries",
+    request_body = CreateBeneficiaryRequest,
+    responses((status = 200, description = "Beneficiary added", body = BeneficiaryResponse)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn create_beneficiary(
     Extension(pool): Extension<DbPool>,
     Extension(claims): Extension<Claims>,
-    Json(payload): Json<CreateBeneficiaryRequest>,
-) -> Result<Json<BeneficiaryResponse>, StatusCode> {
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    ValidatedJson(payload): ValidatedJson<CreateBeneficiaryRequest>,
+) -> Result<Json<BeneficiaryResponse>, ApiError> {
+    let user_id = claims.user_id()?;
 
-    match beneficiary_service::create_beneficiary(&pool, user_id, payload).await {
-        Ok(beneficiary) => Ok(Json(beneficiary)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    let beneficiary = beneficiary_service::create_beneficiary(&pool, user_id, payload).await?;
+    Ok(Json(beneficiary))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/beneficiaries",
+    tag = "beneficiaries",
+    params(PageQuery),
+    responses((status = 200, description = "Beneficiaries of the caller, newest first", body = Page<BeneficiaryResponse>)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn get_beneficiaries(
     Extension(pool): Extension<DbPool>,
     Extension(claims): Extension<Claims>,
-) -> Result<Json<Vec<BeneficiaryResponse>>, StatusCode> {
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    Extension(version): Extension<ApiVersion>,
+    Query(query): Query<PageQuery>,
+) -> Result<Response, ApiError> {
+    let user_id = claims.user_id()?;
 
-    match beneficiary_service::get_beneficiaries_by_user(&pool, user_id).await {
-        Ok(beneficiaries) => Ok(Json(beneficiaries)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    if version == ApiVersion::V1 {
+        let beneficiaries = beneficiary_service::get_beneficiaries_by_user(&pool, user_id).await?;
+        return Ok(Json(beneficiaries).into_response());
     }
+
+    let cursor = query.cursor.as_deref().map(|cursor| Cursor::decode(cursor, Order::NEWEST)).transpose()?;
+    let page = beneficiary_service::list_beneficiaries(&pool, user_id, cursor.as_ref(), page_size(query.limit)).await?;
+    Ok(Json(page).into_response())
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/beneficiaries/{id}",
+    tag = "beneficiaries",
+    params(("id" = Uuid, Path, description = "Beneficiary id")),
+    responses((status = 200, description = "The beneficiary", body = BeneficiaryResponse)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn get_beneficiary(
     Extension(pool): Extension<DbPool>,
     Extension(claims): Extension<Claims>,
     Path(beneficiary_id): Path<Uuid>,
-) -> Result<Json<BeneficiaryResponse>, StatusCode> {
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+) -> Result<Json<BeneficiaryResponse>, ApiError> {
+    let user_id = claims.user_id()?;
 
-    match beneficiary_service::get_beneficiary(&pool, beneficiary_id, user_id).await {
-        Ok(Some(beneficiary)) => Ok(Json(beneficiary)),
-        Ok(None) => Err(StatusCode::NOT_FOUND),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    match beneficiary_service::get_beneficiary(&pool, beneficiary_id, user_id).await? {
+        Some(beneficiary) => Ok(Json(beneficiary)),
+        None => Err(ApiError::NotFound),
     }
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/beneficiaries/{id}",
+    tag = "beneficiaries",
+    params(("id" = Uuid, Path, description = "Beneficiary id")),
+    responses((status = 204, description = "Beneficiary deleted")),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn delete_beneficiary(
     Extension(pool): Extension<DbPool>,
     Extension(claims): Extension<Claims>,
     Path(beneficiary_id): Path<Uuid>,
-) -> Result<StatusCode, StatusCode> {
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+) -> Result<StatusCode, ApiError> {
+    let user_id = claims.user_id()?;
 
-    match beneficiary_service::delete_beneficiary(&pool, beneficiary_id, user_id).await {
-        Ok(true) => Ok(StatusCode::NO_CONTENT),
-        Ok(false) => Err(StatusCode::NOT_FOUND),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    match beneficiary_service::delete_beneficiary(&pool, beneficiary_id, user_id).await? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(ApiError::NotFound),
     }
 }
\ No newline at end of file
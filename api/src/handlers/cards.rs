@@ -1,66 +1,107 @@
-use axum::{Json, http::StatusCode, extract::Path, Extension};
-use uuid::Uuid;
+use axum::{Json, extract::Query, response::{IntoResponse, Response}, Extension};
 use crate::services::card_service;
 use crate::services::database::DbPool;
+use crate::middleware::ownership::{ensure_account_owned, OwnedAccount, OwnedCard};
+use crate::middleware::versioning::ApiVersion;
+use crate::utils::validation::ValidatedJson;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+use crate::utils::pagination::{page_size, Cursor, Order};
 use crate::models::card::{CreateCardRequest, CardResponse, CardDetailsResponse};
+use crate::models::pagination::{Page, PageQuery};
 
+#[utoipa::path(
+    post,
+    path = "/api/cards",
+    tag = "cards",
+    request_body = CreateCardRequest,
+    responses((status = 200, description = "Card issued", body = CardResponse)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn create_card(
     Extension(pool): Extension<DbPool>,
-    Json(payload): Json<CreateCardRequest>,
-) -> Result<Json<CardResponse>, StatusCode> {
-    match card_service::create_card(&pool, payload).await {
-        Ok(card) => Ok(Json(card)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Extension(claims): Extension<Claims>,
+    ValidatedJson(payload): ValidatedJson<CreateCardRequest>,
+) -> Result<Json<CardResponse>, ApiError> {
+    let user_id = claims.user_id()?;
+    ensure_account_owned(&pool, payload.account_id, user_id).await?;
+
+    let card = card_service::create_card(&pool, user_id, payload).await?;
+    Ok(Json(card))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/cards/{id}",
+    tag = "cards",
+    params(("id" = Uuid, Path, description = "Card id")),
+    responses((status = 200, description = "Card, with a masked number", body = CardResponse)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn get_card(
     Extension(pool): Extension<DbPool>,
-    Path(card_id): Path<Uuid>,
-) -> Result<Json<CardResponse>, StatusCode> {
-    match card_service::get_card(&pool, card_id).await {
-        Ok(Some(card)) => {
-            // Convert Card to CardResponse
-            let card_number = card.card_number_encrypted.unwrap_or_else(|| "4111111111111111".to_string());
-            let response = CardResponse {
-                id: card.id,
-                account_id: card.account_id,
-                card_type: card.card_type,
-                friendly_name: card.friendly_name,
-                masked_card_number: card_service::mask_card_number(&card_number),
-                expiry_month: card.expiry_month,
-                expiry_year: card.expiry_year,
-                status: card.status,
-                created_at: card.created_at,
-            };
-            Ok(Json(response))
-        }
-        Ok(None) => Err(StatusCode::NOT_FOUND),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    OwnedCard(card_id): OwnedCard,
+) -> Result<Json<CardResponse>, ApiError> {
+    let card = card_service::get_card(&pool, card_id).await?.ok_or(ApiError::NotFound)?;
+
+    // Convert Card to CardResponse
+    let card_number = card.card_number_encrypted.unwrap_or_else(|| "4111111111111111".to_string());
+    let response = CardResponse {
+        id: card.id,
+        account_id: card.account_id,
+        card_type: card.card_type,
+        friendly_name: card.friendly_name,
+        masked_card_number: card_service::mask_card_number(&card_number),
+        expiry_month: card.expiry_month,
+        expiry_year: card.expiry_year,
+        status: card.status,
+        created_at: card.created_at,
+    };
+    Ok(Json(response))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/cards/{id}/details",
+    tag = "cards",
+    params(("id" = Uuid, Path, description = "Card id")),
+    responses((status = 200, description = "Card with its full number and CVV", body = CardDetailsResponse)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn get_card_details(
     Extension(pool): Extension<DbPool>,
-    Path(card_id): Path<Uuid>,
-) -> Result<Json<CardDetailsResponse>, StatusCode> {
-    match card_service::get_card_details(&pool, card_id).await {
-        Ok(Some(card_details)) => Ok(Json(card_details)),
-        Ok(None) => Err(StatusCode::NOT_FOUND),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    OwnedCard(card_id): OwnedCard,
+) -> Result<Json<CardDetailsResponse>, ApiError> {
+    match card_service::get_card_details(&pool, card_id).await? {
+        Some(card_details) => Ok(Json(card_details)),
+        None => Err(ApiError::NotFound),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/accounts/{account_id}/cards",
+    tag = "cards",
+    params(("account_id" = Uuid, Path, description = "Account id"), PageQuery),
+    responses((status = 200, description = "Cards of the account, newest first", body = Page<CardResponse>)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn get_cards_by_account(
     Extension(pool): Extension<DbPool>,
-    Path(account_id): Path<Uuid>,
-) -> Result<Json<Vec<CardResponse>>, StatusCode> {
-    match card_service::get_cards_by_account(&pool, account_id).await {
-        Ok(cards) => Ok(Json(cards)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    Extension(version): Extension<ApiVersion>,
+    OwnedAccount(account_id): OwnedAccount,
+    Query(query): Query<PageQuery>,
+) -> Result<Response, ApiError> {
+    if version == ApiVersion::V1 {
+        let cards = card_service::get_cards_by_account(&pool, account_id).await?;
+        return Ok(Json(cards).into_response());
     }
+
+    let cursor = query.cursor.as_deref().map(|cursor| Cursor::decode(cursor, Order::NEWEST)).transpose()?;
+    let page = card_service::list_cards(&pool, account_id, cursor.as_ref(), page_size(query.limit)).await?;
+    Ok(Json(page).into_response())
 }
\ No newline at end of file
@@ -1,20 +1,23 @@
-use axum::{Json, http::StatusCode, Extension};
+use axum::{Json, Extension};
 use serde::Serialize;
+use utoipa::ToSchema;
 use uuid::Uuid;
 use sqlx::Row;
 use crate::services::database::DbPool;
-use crate::services::transaction_service;
+use crate::models::transaction::TransactionQuery;
+use crate::services::transaction_service::{self, TransactionScope};
+use crate::utils::error::ApiError;
 use crate::utils::jwt::Claims;
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct DashboardResponse {
     pub accounts: Vec<AccountSummary>,
     pub recent_transactions: Vec<TransactionSummary>,
     pub total_balance: f64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AccountSummary {
     pub id: Uuid,
     pub friendly_name: String,
@@ -23,7 +26,7 @@ pub struct AccountSummary {
     pub cards_count: i64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct TransactionSummary {
     pub id: Uuid,
     pub transaction_type: String,
@@ -32,19 +35,22 @@ pub struct TransactionSummary {
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/dashboard",
+    tag = "accounts",
+    responses((status = 200, description = "Accounts, balance and recent transactions", body = DashboardResponse)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn get_dashboard(
     Extension(pool): Extension<DbPool>,
     Extension(claims): Extension<Claims>,
-) -> Result<Json<DashboardResponse>, StatusCode> {
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+) -> Result<Json<DashboardResponse>, ApiError> {
+    let user_id = claims.user_id()?;
 
     // Get user accounts
-    let accounts = match get_user_accounts(&pool, user_id).await {
-        Ok(accounts) => accounts,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-    };
+    let accounts = get_user_accounts(&pool, user_id).await?;
 
     // Get recent transactions (from all user accounts)
     let mut recent_transactions = Vec::new();
@@ -53,7 +59,8 @@ pub async fn get_dashboard(
     for account in &accounts {
         total_balance += account.balance;
 
-        if let Ok(transactions) = transaction_service::get_transactions_by_account(&pool, account.id, Some(5), Some(0)).await {
+        let latest = TransactionQuery { limit: Some(5), ..Default::default() };
+        if let Ok(transactions) = transaction_service::list_transactions_by_offset(&pool, TransactionScope::Account(account.id), &latest).await {
             for transaction in transactions {
                 recent_transactions.push(TransactionSummary {
                     id: transaction.id,
@@ -67,7 +74,7 @@ pub async fn get_dashboard(
     }
 
     // Sort transactions by date (most recent first) and take top 10
-    recent_transactions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    recent_transactions.sort_by_key(|t| std::cmp::Reverse(t.created_at));
     recent_transactions.truncate(10);
 
     let response = DashboardResponse {
@@ -81,7 +88,7 @@ pub async fn get_dashboard(
 
 async fn get_user_accounts(pool: &DbPool, user_id: Uuid) -> Result<Vec<AccountSummary>, sqlx::Error> {
     let rows = sqlx::query(
-        "SELECT id, friendly_name, iban, balance FROM accounts WHERE user_id = $1"
+        "SELECT id, friendly_name, iban, balance::FLOAT8 AS balance FROM accounts WHERE user_id = $1"
     )
     .bind(user_id)
     .fetch_all(pool)
@@ -0,0 +1,269 @@
+use utoipa::openapi::response::ResponseBuilder;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::path::{Operation, PathItem};
+use utoipa::openapi::schema::{Array, Schema};
+use utoipa::openapi::{Content, Deprecated, Ref, RefOr};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+use crate::config::app_config::AppConfig;
+use crate::handlers;
+use crate::middleware::versioning::ApiVersion;
+use crate::utils::error::{Problem, PROBLEM_CONTENT_TYPE};
+
+pub const UI_PATH: &str = "/api/docs";
+
+/// Where utoipa names `Page<T>` once its type parameter is filled in.
+const PAGE_SCHEMA_PREFIX: &str = "#/components/schemas/Page_";
+
+/// Listings added after API v2, which are paginated in v1 as well.
+const PAGINATED_IN_V1: &[&str] = &["list_webhook_deliveries"];
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Vaelix Bank API", description = "Banking API: accounts, cards, payments and the identity around them."),
+    paths(
+        crate::root,
+        crate::health_check,
+        handlers::auth::jwks,
+        handlers::auth::login,
+        handlers::auth::logout,
+        handlers::auth::list_sessions,
+        handlers::auth::revoke_session,
+        handlers::mfa::verify,
+        handlers::mfa::enroll_totp,
+        handlers::mfa::disable_totp,
+        handlers::mfa::confirm_totp,
+        handlers::verification::request_email_verification,
+        handlers::verification::verify_email,
+        handlers::verification::forgot_password,
+        handlers::verification::reset_password,
+        handlers::verification::unlock_account,
+        handlers::admin::get_user_roles,
+        handlers::admin::assign_role,
+        handlers::admin::revoke_role,
+        handlers::admin::get_audit_logs,
+        handlers::admin::verify_audit_logs,
+        handlers::api_keys::create_api_key,
+        handlers::api_keys::get_api_keys,
+        handlers::api_keys::revoke_api_key,
+        handlers::users::create_consumer,
+        handlers::users::create_corporate,
+        handlers::accounts::create_account,
+        handlers::accounts::get_iban,
+        handlers::cards::create_card,
+        handlers::cards::get_card,
+        handlers::cards::get_card_details,
+        handlers::cards::get_cards_by_account,
+        handlers::transactions::send_money,
+        handlers::transactions::transfer_money,
+        handlers::transactions::get_transactions,
+        handlers::transactions::search_transactions,
+        handlers::transactions::get_transaction,
+        handlers::beneficiaries::create_beneficiary,
+        handlers::beneficiaries::get_beneficiaries,
+        handlers::beneficiaries::get_beneficiary,
+        handlers::beneficiaries::delete_beneficiary,
+        handlers::dashboard::get_dashboard,
+        handlers::events::stream_events,
+        handlers::webhooks::create_webhook,
+        handlers::webhooks::get_webhooks,
+        handlers::webhooks::delete_webhook,
+        handlers::webhooks::enable_webhook,
+        handlers::webhooks::list_webhook_deliveries,
+        handlers::webhooks::redeliver_webhook,
+        handlers::webauthn::registration_options,
+        handlers::webauthn::register,
+        handlers::webauthn::list_credentials,
+        handlers::webauthn::delete_credential,
+        handlers::webauthn::step_up_options,
+        handlers::webauthn::authentication_options,
+        handlers::webauthn::authenticate,
+        handlers::oauth::register_client,
+        handlers::oauth::get_clients,
+        handlers::oauth::delete_client,
+        handlers::oauth::authorization_details,
+        handlers::oauth::authorize,
+        handlers::oauth::token,
+        handlers::oauth::introspect,
+        handlers::oauth::revoke,
+    ),
+    components(schemas(Problem)),
+    modifiers(&Security, &Problems),
+)]
+pub struct ApiDoc;
+
+/// How callers authenticate; operations name the schemes they accept.
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "Authorization",
+                "`ApiKey <key>`, acting for the user who issued the key within its scopes",
+            ))),
+        );
+        components.add_security_scheme(
+            "signature",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-Vaelix-Signature",
+                "`keyId=<key prefix>,ts=<unix seconds>,nonce=<nonce>,sig=<hex>`, an HMAC-SHA256 of the request with the API key secret",
+            ))),
+        );
+        components.add_security_scheme(
+            "client",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Basic).description(Some("OAuth client id and secret")).build()),
+        );
+    }
+}
+
+/// Every operation may fail with a problem details body, so it is declared
+/// once as the default response instead of on each handler.
+struct Problems;
+
+impl Modify for Problems {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let response = ResponseBuilder::new()
+            .description("Error, as RFC 7807 problem details")
+            .content(PROBLEM_CONTENT_TYPE, Content::new(Some(Ref::from_schema_name("Problem"))))
+            .build();
+
+        for item in openapi.paths.paths.values_mut() {
+            for operation in operations(item) {
+                operation.responses.responses.entry("default".to_string()).or_insert_with(|| RefOr::T(response.clone()));
+            }
+        }
+    }
+}
+
+/// The document of `version` for this configuration, without the routes of
+/// disabled features. Operations are written with their unversioned path,
+/// and moved under the version's prefix here.
+pub fn spec(config: &AppConfig, version: ApiVersion) -> utoipa::openapi::OpenApi {
+    let mut openapi = ApiDoc::openapi();
+    openapi.info.version = version.number().to_string();
+
+    let paths = std::mem::take(&mut openapi.paths.paths);
+    for (path, mut item) in paths {
+        if (!config.webauthn_enabled && path.starts_with("/api/auth/webauthn/"))
+            || (!config.oauth_enabled && path.starts_with("/api/oauth/"))
+        {
+            continue;
+        }
+
+        let path = match path.strip_prefix("/api") {
+            Some(rest) => {
+                for operation in operations(&mut item) {
+                    if version.retirement(config).is_some() {
+                        operation.deprecated = Some(Deprecated::True);
+                    }
+                    if version == ApiVersion::V1 {
+                        unpaginate(operation);
+                    }
+                }
+                format!("{}{}", version.prefix(), rest)
+            }
+            None => path,
+        };
+        openapi.paths.paths.insert(path, item);
+    }
+
+    openapi
+}
+
+/// API v1 answers the listings paginated by cursor with a bare array of
+/// their items, but for `PAGINATED_IN_V1`.
+fn unpaginate(operation: &mut Operation) {
+    if operation.operation_id.as_deref().is_some_and(|id| PAGINATED_IN_V1.contains(&id)) {
+        return;
+    }
+    let Some(RefOr::T(response)) = operation.responses.responses.get_mut("200") else {
+        return;
+    };
+    for content in response.content.values_mut() {
+        let item = match &content.schema {
+            Some(RefOr::Ref(page)) => page.ref_location.strip_prefix(PAGE_SCHEMA_PREFIX).map(str::to_string),
+            _ => None,
+        };
+        if let Some(item) = item {
+            content.schema = Some(RefOr::T(Schema::Array(Array::new(Ref::from_schema_name(item)))));
+        }
+    }
+}
+
+fn operations(item: &mut PathItem) -> impl Iterator<Item = &mut Operation> {
+    [
+        &mut item.get, &mut item.put, &mut item.post, &mut item.delete,
+        &mut item.options, &mut item.head, &mut item.patch, &mut item.trace,
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Where the document of `version` is served. Unversioned clients get theirs
+/// at `/api/openapi.json`, like any other route.
+pub fn spec_path(version: ApiVersion) -> String {
+    format!("{}/openapi.json", version.prefix())
+}
+
+/// Serves the document of every version and a Swagger UI to browse them.
+pub fn swagger_ui(config: &AppConfig) -> SwaggerUi {
+    ApiVersion::ALL
+        .into_iter()
+        .fold(SwaggerUi::new(UI_PATH), |ui, version| ui.url(spec_path(version), spec(config, version)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    /// Every route registered in `app`, as `(method, path)` with axum's
+    /// `:param` segments written `{param}` like in the document. Routes of
+    /// `api_routes` are given their unversioned `/api` prefix.
+    fn registered_routes() -> Vec<(String, String)> {
+        let route = Regex::new(r#"\.route\("([^"]+)",\s*(?:axum::routing::)?(\w+)\("#).unwrap();
+        let param = Regex::new(r":(\w+)").unwrap();
+        let (app, api) = include_str!("../main.rs").split_once("fn api_routes(").unwrap();
+
+        let mut routes = Vec::new();
+        for (source, prefix) in [(app, ""), (api, "/api")] {
+            for captures in route.captures_iter(source) {
+                let path = format!("{}{}", prefix, &captures[1]);
+                routes.push((captures[2].to_string(), param.replace_all(&path, "{$1}").into_owned()));
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn test_spec_documents_every_route() {
+        let routes = registered_routes();
+        assert!(routes.len() > 50, "routes not found in main.rs: {:?}", routes);
+
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3.1"));
+
+        let missing: Vec<_> = routes
+            .iter()
+            .filter(|(method, path)| spec["paths"][path][method].is_null())
+            .collect();
+        assert!(missing.is_empty(), "routes missing from the OpenAPI document: {:?}", missing);
+
+        // And nothing documented that isn't served
+        let documented = spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, item)| item.as_object().unwrap().keys().map(move |method| (method.clone(), path.clone())))
+            .filter(|(method, _)| method != "parameters");
+        let unknown: Vec<_> = documented.filter(|operation| !routes.contains(operation)).collect();
+        assert!(unknown.is_empty(), "operations documented but not routed: {:?}", unknown);
+    }
+}
\ No newline at end of file
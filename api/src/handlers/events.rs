@@ -0,0 +1,92 @@
+use axum::{response::sse::{Event, KeepAlive, Sse}, Extension};
+use futures_util::stream::{self, Stream};
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use uuid::Uuid;
+use crate::middleware::auth::{session_active, SessionCache};
+use crate::models::live_event::LiveEvent;
+use crate::services::database::DbPool;
+use crate::services::event_bus::EventBus;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+
+/// How often an open stream checks that its session is still active.
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// An open stream, with what it takes to end it when its token would no
+/// longer be accepted.
+struct LiveStream {
+    events: Receiver<LiveEvent>,
+    pool: DbPool,
+    session_cache: SessionCache,
+    session_id: Uuid,
+    expires_at: i64,
+    checks: tokio::time::Interval,
+}
+
+impl LiveStream {
+    async fn next(&mut self) -> Option<Event> {
+        loop {
+            tokio::select! {
+                received = self.events.recv() => {
+                    return match received {
+                        Ok(event) => Some(to_sse(&event)),
+                        // Events were dropped: the client refetches what it shows
+                        Err(RecvError::Lagged(_)) => Some(Event::default().event("resync").data("{}")),
+                        Err(RecvError::Closed) => None,
+                    };
+                }
+                _ = self.checks.tick() => {
+                    let active = session_active(&self.pool, &self.session_cache, self.session_id).await.unwrap_or(false);
+                    if !active || chrono::Utc::now().timestamp() >= self.expires_at {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn to_sse(event: &LiveEvent) -> Event {
+    let value = serde_json::to_value(event).expect("live events serialize to JSON");
+    Event::default().event(event.name()).data(value["data"].to_string())
+}
+
+/// Pushes the caller's live events as Server-Sent Events: balance changes,
+/// transactions made or updated, card authorizations and security alerts.
+/// Events are not replayed, so clients load the dashboard once connected.
+/// The stream ends when the token expires or its session is revoked.
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    tag = "events",
+    responses(
+        (status = 200, description = "Event stream; each event is named after `type` and carries `data`. `resync` asks to reload after events were dropped", body = LiveEvent, content_type = "text/event-stream"),
+        (status = 429, description = "Too many streams open for the user"),
+    ),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn stream_events(
+    Extension(pool): Extension<DbPool>,
+    Extension(bus): Extension<EventBus>,
+    Extension(session_cache): Extension<SessionCache>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let user_id = claims.user_id()?;
+    let session_id = Uuid::parse_str(&claims.sid).map_err(|_| ApiError::Unauthorized)?;
+
+    let Some(events) = bus.subscribe(user_id).await else {
+        return Err(ApiError::TooManyRequests { retry_after: None });
+    };
+
+    let mut checks = tokio::time::interval(SESSION_CHECK_INTERVAL);
+    checks.reset();
+    let live = LiveStream { events, pool, session_cache, session_id, expires_at: claims.exp as i64, checks };
+
+    let stream = stream::unfold(live, |mut live| async move {
+        live.next().await.map(|event| (Ok(event), live))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
\ No newline at end of file
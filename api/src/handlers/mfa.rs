@@ -0,0 +1,222 @@
+use axum::{Json, http::{HeaderMap, StatusCode}, Extension};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use chrono::Utc;
+use crate::services::{lockout_service, mfa_service, user_service};
+use crate::services::database::DbPool;
+use crate::services::encryption_service::EncryptionService;
+use crate::services::event_bus::EventBus;
+use crate::config::app_config::AppConfig;
+use crate::handlers::auth::{start_session, LoginResponse};
+use crate::middleware::rate_limit::LoginThrottle;
+use crate::models::live_event::SecurityAlertKind;
+use crate::utils::client;
+use crate::utils::error::{ApiError, FieldError};
+use crate::utils::jwt::{self, Claims};
+use crate::utils::totp;
+
+const TOTP_ISSUER: &str = "Vaelix Bank";
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TotpEnrollmentResponse {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MfaVerifyRequest {
+    pub mfa_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+fn invalid_code() -> ApiError {
+    ApiError::Validation(vec![FieldError::new("code", "invalid_code", "the code is wrong or has expired")])
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/mfa/totp",
+    tag = "mfa",
+    responses((status = 200, description = "Secret to confirm with a first code", body = TotpEnrollmentResponse)),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn enroll_totp(
+    Extension(pool): Extension<DbPool>,
+    Extension(encryption): Extension<EncryptionService>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<TotpEnrollmentResponse>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    match mfa_service::begin_totp_enrollment(&pool, &encryption, user_id).await? {
+        Some(secret) => Ok(Json(TotpEnrollmentResponse {
+            secret: totp::encode_secret(&secret),
+            provisioning_uri: totp::provisioning_uri(&secret, TOTP_ISSUER, &claims.email),
+        })),
+        None => Err(ApiError::Conflict("TOTP is already enabled".to_string())),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/mfa/totp/confirm",
+    tag = "mfa",
+    request_body = TotpCodeRequest,
+    responses((status = 200, description = "TOTP enabled; recovery codes are only shown once", body = RecoveryCodesResponse)),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn confirm_totp(
+    Extension(pool): Extension<DbPool>,
+    Extension(encryption): Extension<EncryptionService>,
+    Extension(events): Extension<EventBus>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<TotpCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    match mfa_service::confirm_totp_enrollment(&pool, &encryption, user_id, &payload.code).await? {
+        Some(recovery_codes) => {
+            events
+                .alert(user_id, SecurityAlertKind::TwoFactorEnabled, client::client_ip(&headers), client::user_agent(&headers))
+                .await;
+            Ok(Json(RecoveryCodesResponse { recovery_codes }))
+        }
+        None => Err(invalid_code()),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/auth/mfa/totp",
+    tag = "mfa",
+    request_body = TotpCodeRequest,
+    responses((status = 204, description = "TOTP disabled")),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn disable_totp(
+    Extension(pool): Extension<DbPool>,
+    Extension(encryption): Extension<EncryptionService>,
+    Extension(events): Extension<EventBus>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<TotpCodeRequest>,
+) -> Result<StatusCode, ApiError> {
+    let user_id = claims.user_id()?;
+
+    // Require a current code so a hijacked session can't silently drop the second factor
+    if !mfa_service::verify_totp(&pool, &encryption, user_id, &payload.code, true).await? {
+        return Err(invalid_code());
+    }
+
+    mfa_service::disable_totp(&pool, user_id).await?;
+    events
+        .alert(user_id, SecurityAlertKind::TwoFactorDisabled, client::client_ip(&headers), client::user_agent(&headers))
+        .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Second step of login: exchanges the challenge token from `login` plus a
+/// TOTP or recovery code for a session. Wrong codes count towards the same
+/// delays and lockout as wrong passwords, and a challenge is spent by the
+/// session it opens or after `MAX_CHALLENGE_FAILURES` wrong codes.
+#[utoipa::path(
+    post,
+    path = "/api/auth/mfa/verify",
+    tag = "mfa",
+    request_body = MfaVerifyRequest,
+    responses(
+        (status = 200, description = "Session opened", body = LoginResponse),
+        (status = 401, description = "Wrong code, or the challenge is spent or expired"),
+        (status = 423, description = "Account locked after too many failures"),
+        (status = 429, description = "Too many recent failures"),
+    )
+)]
+#[axum::debug_handler]
+pub async fn verify(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(encryption): Extension<EncryptionService>,
+    Extension(login_throttle): Extension<LoginThrottle>,
+    Extension(events): Extension<EventBus>,
+    headers: HeaderMap,
+    Json(payload): Json<MfaVerifyRequest>,
+) -> Result<Json<LoginResponse>, ApiError> {
+    let challenge = jwt::verify_mfa_challenge_token(&payload.mfa_token, &config.jwt_keys)
+        .map_err(|_| ApiError::Unauthorized)?;
+    let user_id = Uuid::parse_str(&challenge.sub)
+        .map_err(|_| ApiError::Unauthorized)?;
+    let challenge_id = Uuid::parse_str(&challenge.jti)
+        .map_err(|_| ApiError::Unauthorized)?;
+
+    let user = user_service::get_user_by_id(&pool, user_id)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+    let ip_address = client::client_ip(&headers);
+
+    if let Some(wait) = login_throttle.retry_after(&user.email, ip_address.as_deref()).await {
+        return Err(ApiError::TooManyRequests { retry_after: Some(wait) });
+    }
+    if let Some(until) = lockout_service::locked_until(&pool, user_id).await? {
+        let retry_after = (until - Utc::now()).to_std().unwrap_or_default();
+        return Err(ApiError::Locked { retry_after });
+    }
+    if !mfa_service::challenge_open(&pool, challenge_id, user_id).await? {
+        return Err(ApiError::Unauthorized);
+    }
+
+    let verified = match (&payload.code, &payload.recovery_code) {
+        (Some(code), _) => mfa_service::verify_totp(&pool, &encryption, user_id, code, true).await?,
+        (None, Some(recovery_code)) => mfa_service::use_recovery_code(&pool, user_id, recovery_code).await?,
+        (None, None) => return Err(ApiError::BadRequest("either code or recovery_code is required".to_string())),
+    };
+
+    if !verified {
+        login_throttle.record_failure(&user.email, ip_address.as_deref()).await;
+        mfa_service::fail_challenge(&pool, challenge_id).await?;
+
+        let locked_until = lockout_service::record_failure(
+            &pool,
+            user_id,
+            ip_address.as_deref(),
+            client::user_agent(&headers).as_deref(),
+            &config.jwt_secret,
+            &config.app_base_url,
+        )
+        .await?;
+
+        if let Some(until) = locked_until {
+            mfa_service::spend_challenge(&pool, challenge_id).await?;
+            events.alert(user_id, SecurityAlertKind::AccountLocked, ip_address, client::user_agent(&headers)).await;
+            let retry_after = (until - Utc::now()).to_std().unwrap_or_default();
+            return Err(ApiError::Locked { retry_after });
+        }
+
+        return Err(ApiError::Unauthorized);
+    }
+
+    // Concurrent answers to the same challenge open one session
+    if !mfa_service::spend_challenge(&pool, challenge_id).await? {
+        return Err(ApiError::Unauthorized);
+    }
+
+    login_throttle.record_success(&user.email).await;
+    lockout_service::reset_failures(&pool, user_id).await?;
+
+    let response = start_session(&pool, &config, &events, &headers, user).await?;
+    Ok(Json(response))
+}
\ No newline at end of file
@@ -5,4 +5,13 @@ pub mod accounts;
 pub mod cards;
 pub mod transactions;
 pub mod beneficiaries;
-pub mod dashboard;
\ No newline at end of file
+pub mod dashboard;
+pub mod mfa;
+pub mod webauthn;
+pub mod verification;
+pub mod admin;
+pub mod api_keys;
+pub mod oauth;
+pub mod docs;
+pub mod webhooks;
+pub mod events;
\ No newline at end of file
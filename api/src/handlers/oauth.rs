@@ -0,0 +1,475 @@
+use axum::{
+    Json, Form,
+    http::{header, HeaderMap, StatusCode},
+    extract::{Path, Query},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde_json::json;
+use url::Url;
+use uuid::Uuid;
+use crate::services::{audit_service, oauth_service};
+use crate::services::database::DbPool;
+use crate::config::app_config::AppConfig;
+use crate::middleware::auth::SessionCache;
+use crate::models::oauth::{
+    AuthorizationDetails, AuthorizeRequest, ConsentRequest, ConsentResponse, IntrospectionResponse, OAuthClient,
+    OAuthErrorResponse, RegisterClientRequest, RegisteredClientResponse, TokenActionRequest, TokenRequest, TokenResponse,
+    GRANT_AUTHORIZATION_CODE, GRANT_CLIENT_CREDENTIALS,
+};
+use crate::utils::client;
+use crate::utils::validation::ValidatedJson;
+use crate::utils::error::{ApiError, FieldError};
+use crate::utils::jwt::{self, Claims};
+
+fn oauth_error(status: StatusCode, error: &'static str, description: &str) -> Response {
+    let body = OAuthErrorResponse {
+        error,
+        error_description: Some(description.to_string()),
+    };
+    (status, Json(body)).into_response()
+}
+
+fn invalid_field(field: &str, message: &str) -> ApiError {
+    ApiError::Validation(vec![FieldError::new(field, "invalid", message)])
+}
+
+/// Redirect URIs must be absolute https URLs without a fragment; plain http is
+/// only allowed on loopback, for native apps and development.
+fn valid_redirect_uri(uri: &str) -> bool {
+    let url = match Url::parse(uri) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+
+    let loopback = matches!(url.host_str(), Some("localhost") | Some("127.0.0.1") | Some("[::1]"));
+    url.fragment().is_none() && (url.scheme() == "https" || (url.scheme() == "http" && loopback))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/oauth/clients",
+    tag = "oauth",
+    request_body = RegisterClientRequest,
+    responses((status = 201, description = "Client registered", body = RegisteredClientResponse)),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn register_client(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<RegisterClientRequest>,
+) -> Result<(StatusCode, Json<RegisteredClientResponse>), ApiError> {
+    let owner_id = claims.user_id()?;
+
+    let known_grants = payload
+        .grant_types
+        .iter()
+        .all(|g| g == GRANT_AUTHORIZATION_CODE || g == GRANT_CLIENT_CREDENTIALS);
+    let uses_code = payload.grant_types.iter().any(|g| g == GRANT_AUTHORIZATION_CODE);
+    let uses_client_credentials = payload.grant_types.iter().any(|g| g == GRANT_CLIENT_CREDENTIALS);
+
+    // Public clients have no secret to authenticate a client credentials grant
+    if !known_grants || (uses_client_credentials && !payload.confidential) {
+        return Err(invalid_field("grant_types", "unknown grant type, or client credentials for a public client"));
+    }
+    if uses_code && payload.redirect_uris.is_empty() {
+        return Err(invalid_field("redirect_uris", "required for the authorization code grant"));
+    }
+    if !payload.redirect_uris.iter().all(|uri| valid_redirect_uri(uri)) {
+        return Err(invalid_field("redirect_uris", "must be https URLs without a fragment, or http on loopback"));
+    }
+
+    if !oauth_service::scopes_exist(&pool, &payload.scopes).await? {
+        return Err(invalid_field("scopes", "contains an unknown scope"));
+    }
+
+    let (client, client_secret) = oauth_service::register_client(&pool, owner_id, payload).await?;
+
+    audit_service::record(
+        &pool,
+        Some(owner_id),
+        "oauth_client_registered",
+        audit_service::RESOURCE_OAUTH_CLIENT,
+        Some(client.id),
+        json!({ "client_id": client.client_id, "name": client.name, "scopes": client.scopes, "grant_types": client.grant_types }),
+        client::client_ip(&headers).as_deref(),
+        client::user_agent(&headers).as_deref(),
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(RegisteredClientResponse { client, client_secret })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/oauth/clients",
+    tag = "oauth",
+    responses((status = 200, description = "Clients owned by the caller", body = [OAuthClient])),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn get_clients(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<OAuthClient>>, ApiError> {
+    let owner_id = claims.user_id()?;
+
+    let clients = oauth_service::get_clients_by_owner(&pool, owner_id).await?;
+    Ok(Json(clients))
+}
+
+/// Deletes a client and revokes every token it holds.
+#[utoipa::path(
+    delete,
+    path = "/api/oauth/clients/{id}",
+    tag = "oauth",
+    params(("id" = Uuid, Path, description = "Client id")),
+    responses((status = 204, description = "Client deleted and its tokens revoked")),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn delete_client(
+    Extension(pool): Extension<DbPool>,
+    Extension(session_cache): Extension<SessionCache>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    let owner_id = claims.user_id()?;
+
+    let revoked_sessions = oauth_service::delete_client(&pool, id, owner_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+
+    for session_id in revoked_sessions {
+        session_cache.revoke(session_id).await;
+    }
+
+    audit_service::record(
+        &pool,
+        Some(owner_id),
+        "oauth_client_deleted",
+        audit_service::RESOURCE_OAUTH_CLIENT,
+        Some(id),
+        json!({}),
+        client::client_ip(&headers).as_deref(),
+        client::user_agent(&headers).as_deref(),
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Checks an authorization request, returning the client and the requested
+/// scopes (all the client's scopes if none were asked for). PKCE with S256
+/// is required from every client.
+async fn validate_authorization(
+    pool: &DbPool,
+    request: &AuthorizeRequest,
+) -> Result<(OAuthClient, Vec<String>), Response> {
+    if request.response_type != "code" {
+        return Err(oauth_error(StatusCode::BAD_REQUEST, "unsupported_response_type", "only the code response type is supported"));
+    }
+
+    let client = match oauth_service::find_client(pool, &request.client_id).await {
+        Ok(Some(client)) if client.allows_grant(GRANT_AUTHORIZATION_CODE) => client,
+        Ok(_) => return Err(oauth_error(StatusCode::BAD_REQUEST, "unauthorized_client", "unknown client or authorization code grant not allowed")),
+        Err(e) => return Err(ApiError::from(e).into_response()),
+    };
+
+    // Never redirect to an address the client didn't register
+    if !client.redirect_uris.contains(&request.redirect_uri) {
+        return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_request", "redirect_uri is not registered for this client"));
+    }
+
+    if request.code_challenge.is_none() || request.code_challenge_method.as_deref() != Some("S256") {
+        return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_request", "a PKCE code_challenge with method S256 is required"));
+    }
+
+    let scopes = match request.scope.as_deref() {
+        Some(scope) => oauth_service::parse_scope(scope),
+        None => client.scopes.clone(),
+    };
+    if scopes.is_empty() || !scopes.iter().all(|scope| client.scopes.contains(scope)) {
+        return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_scope", "scope exceeds what the client was registered for"));
+    }
+
+    Ok((client, scopes))
+}
+
+/// Validates an authorization request and describes it for the consent screen.
+#[utoipa::path(
+    get,
+    path = "/api/oauth/authorize",
+    tag = "oauth",
+    params(AuthorizeRequest),
+    responses(
+        (status = 200, description = "What to show on the consent screen", body = AuthorizationDetails),
+        (status = "4XX", description = "OAuth error (RFC 6749)", body = OAuthErrorResponse),
+    ),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn authorization_details(
+    Extension(pool): Extension<DbPool>,
+    Query(request): Query<AuthorizeRequest>,
+) -> Result<Json<AuthorizationDetails>, Response> {
+    let (client, scopes) = validate_authorization(&pool, &request).await?;
+
+    Ok(Json(AuthorizationDetails {
+        client_id: client.client_id,
+        client_name: client.name,
+        redirect_uri: request.redirect_uri,
+        scopes,
+    }))
+}
+
+/// Records the signed-in user's decision and returns where to redirect the
+/// browser: back to the client with a code, or with `access_denied`.
+#[utoipa::path(
+    post,
+    path = "/api/oauth/authorize",
+    tag = "oauth",
+    request_body = ConsentRequest,
+    responses(
+        (status = 200, description = "Where to redirect the browser", body = ConsentResponse),
+        (status = "4XX", description = "OAuth error (RFC 6749)", body = OAuthErrorResponse),
+    ),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn authorize(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<ConsentRequest>,
+) -> Result<Json<ConsentResponse>, Response> {
+    let user_id = claims.user_id().map_err(IntoResponse::into_response)?;
+
+    let request = payload.authorization;
+    let (client, scopes) = validate_authorization(&pool, &request).await?;
+
+    let mut redirect = Url::parse(&request.redirect_uri)
+        .map_err(|_| oauth_error(StatusCode::BAD_REQUEST, "invalid_request", "redirect_uri is not a valid URL"))?;
+
+    if payload.approve {
+        let code_challenge = request.code_challenge.as_deref().unwrap_or_default();
+        let code = oauth_service::create_authorization_code(&pool, &client, user_id, &request.redirect_uri, &scopes, code_challenge)
+            .await
+            .map_err(|e| ApiError::from(e).into_response())?;
+
+        audit_service::record(
+            &pool,
+            Some(user_id),
+            "oauth_consent_granted",
+            audit_service::RESOURCE_OAUTH_CLIENT,
+            Some(client.id),
+            json!({ "client_id": client.client_id, "scopes": scopes }),
+            client::client_ip(&headers).as_deref(),
+            client::user_agent(&headers).as_deref(),
+        )
+        .await
+        .map_err(|e| ApiError::from(e).into_response())?;
+
+        redirect.query_pairs_mut().append_pair("code", &code);
+    } else {
+        redirect.query_pairs_mut().append_pair("error", "access_denied");
+    }
+
+    if let Some(state) = &request.state {
+        redirect.query_pairs_mut().append_pair("state", state);
+    }
+
+    Ok(Json(ConsentResponse { redirect_to: redirect.into() }))
+}
+
+/// Client id and secret from HTTP Basic authentication, or else from the form.
+fn client_authentication(
+    headers: &HeaderMap,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+) -> Option<(String, Option<String>)> {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+
+    if let Some(basic) = basic {
+        let (id, secret) = basic.split_once(':')?;
+        return Some((id.to_string(), Some(secret.to_string())));
+    }
+
+    Some((client_id?, client_secret))
+}
+
+async fn authenticated_client(
+    pool: &DbPool,
+    headers: &HeaderMap,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+) -> Result<OAuthClient, Response> {
+    let (client_id, client_secret) = client_authentication(headers, client_id, client_secret)
+        .ok_or_else(|| oauth_error(StatusCode::UNAUTHORIZED, "invalid_client", "client authentication required"))?;
+
+    match oauth_service::authenticate_client(pool, &client_id, client_secret.as_deref()).await {
+        Ok(Some(client)) => Ok(client),
+        Ok(None) => Err(oauth_error(StatusCode::UNAUTHORIZED, "invalid_client", "client authentication failed")),
+        Err(e) => Err(ApiError::from(e).into_response()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/oauth/token",
+    tag = "oauth",
+    request_body(content = TokenRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Access token", body = TokenResponse),
+        (status = "4XX", description = "OAuth error (RFC 6749)", body = OAuthErrorResponse),
+    ),
+    security(("client" = []), ())
+)]
+#[axum::debug_handler]
+pub async fn token(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(session_cache): Extension<SessionCache>,
+    headers: HeaderMap,
+    Form(payload): Form<TokenRequest>,
+) -> Result<([(header::HeaderName, &'static str); 2], Json<TokenResponse>), Response> {
+    let client = authenticated_client(&pool, &headers, payload.client_id, payload.client_secret).await?;
+
+    if !client.allows_grant(&payload.grant_type) {
+        return Err(oauth_error(StatusCode::BAD_REQUEST, "unauthorized_client", "grant type not allowed for this client"));
+    }
+
+    let token = match payload.grant_type.as_str() {
+        GRANT_AUTHORIZATION_CODE => {
+            let (code, redirect_uri, code_verifier) = match (payload.code, payload.redirect_uri, payload.code_verifier) {
+                (Some(code), Some(redirect_uri), Some(code_verifier)) => (code, redirect_uri, code_verifier),
+                _ => return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_request", "code, redirect_uri and code_verifier are required")),
+            };
+
+            match oauth_service::exchange_code(&pool, &client, &code, &redirect_uri, &code_verifier, &config.jwt_keys).await {
+                Ok(Ok(token)) => token,
+                Ok(Err(replayed_session)) => {
+                    if let Some(session_id) = replayed_session {
+                        session_cache.revoke(session_id).await;
+                    }
+                    return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_grant", "invalid, expired or already used authorization code"));
+                }
+                Err(e) => return Err(ApiError::from(e).into_response()),
+            }
+        }
+        GRANT_CLIENT_CREDENTIALS => {
+            let scopes = match payload.scope.as_deref() {
+                Some(scope) => oauth_service::parse_scope(scope),
+                None => client.scopes.clone(),
+            };
+            if scopes.is_empty() || !scopes.iter().all(|scope| client.scopes.contains(scope)) {
+                return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_scope", "scope exceeds what the client was registered for"));
+            }
+
+            oauth_service::client_credentials(&pool, &client, &scopes, &config.jwt_keys)
+                .await
+                .map_err(|e| ApiError::from(e).into_response())?
+        }
+        _ => return Err(oauth_error(StatusCode::BAD_REQUEST, "unsupported_grant_type", "unsupported grant type")),
+    };
+
+    Ok(([(header::CACHE_CONTROL, "no-store"), (header::PRAGMA, "no-cache")], Json(token)))
+}
+
+/// Token introspection (RFC 7662). A client can only introspect its own
+/// tokens; any other token is reported inactive.
+#[utoipa::path(
+    post,
+    path = "/api/oauth/introspect",
+    tag = "oauth",
+    request_body(content = TokenActionRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "State of the token", body = IntrospectionResponse),
+        (status = "4XX", description = "OAuth error (RFC 6749)", body = OAuthErrorResponse),
+    ),
+    security(("client" = []), ())
+)]
+#[axum::debug_handler]
+pub async fn introspect(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+    Form(payload): Form<TokenActionRequest>,
+) -> Result<Json<IntrospectionResponse>, Response> {
+    let client = authenticated_client(&pool, &headers, payload.client_id, payload.client_secret).await?;
+
+    let claims = match jwt::verify_token(&payload.token, &config.jwt_keys) {
+        Ok(claims) if claims.client_id.as_deref() == Some(client.client_id.as_str()) => claims,
+        _ => return Ok(Json(IntrospectionResponse::default())),
+    };
+
+    let active = match Uuid::parse_str(&claims.sid) {
+        Ok(session_id) => oauth_service::grant_active(&pool, &client, session_id)
+            .await
+            .map_err(|e| ApiError::from(e).into_response())?,
+        Err(_) => false,
+    };
+
+    if !active {
+        return Ok(Json(IntrospectionResponse::default()));
+    }
+
+    Ok(Json(IntrospectionResponse {
+        active: true,
+        scope: Some(claims.permissions.join(" ")),
+        client_id: claims.client_id,
+        sub: Some(claims.sub),
+        token_type: Some("Bearer"),
+        exp: Some(claims.exp),
+        iat: Some(claims.iat),
+    }))
+}
+
+/// Token revocation (RFC 7009). Answers 200 whether or not the token was
+/// valid, so the response says nothing about it.
+#[utoipa::path(
+    post,
+    path = "/api/oauth/revoke",
+    tag = "oauth",
+    request_body(content = TokenActionRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token revoked, or unknown"),
+        (status = "4XX", description = "OAuth error (RFC 6749)", body = OAuthErrorResponse),
+    ),
+    security(("client" = []), ())
+)]
+#[axum::debug_handler]
+pub async fn revoke(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(session_cache): Extension<SessionCache>,
+    headers: HeaderMap,
+    Form(payload): Form<TokenActionRequest>,
+) -> Result<StatusCode, Response> {
+    let client = authenticated_client(&pool, &headers, payload.client_id, payload.client_secret).await?;
+
+    let claims = match jwt::verify_token(&payload.token, &config.jwt_keys) {
+        Ok(claims) if claims.client_id.as_deref() == Some(client.client_id.as_str()) => claims,
+        _ => return Ok(StatusCode::OK),
+    };
+
+    if let Ok(session_id) = Uuid::parse_str(&claims.sid) {
+        let revoked = oauth_service::revoke_grant(&pool, &client, session_id)
+            .await
+            .map_err(|e| ApiError::from(e).into_response())?;
+        if revoked {
+            session_cache.revoke(session_id).await;
+        }
+    }
+
+    Ok(StatusCode::OK)
+}
\ No newline at end of file
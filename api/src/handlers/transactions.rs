@@ -1,58 +1,128 @@
-use axum::{Json, http::StatusCode, extract::{Path, Query}, Extension};
-use uuid::Uuid;
-use serde::Deserialize;
-use crate::services::transaction_service;
+use axum::{Json, extract::Query, response::{IntoResponse, Response}, Extension};
+use crate::services::transaction_service::{self, TransactionScope};
 use crate::services::database::DbPool;
-use crate::models::transaction::{SendMoneyRequest, TransferRequest, TransactionResponse};
-
-#[derive(Debug, Deserialize)]
-pub struct TransactionQuery {
-    pub limit: Option<i64>,
-    pub offset: Option<i64>,
-}
+use crate::middleware::ownership::{ensure_account_owned, OwnedAccount, OwnedTransaction};
+use crate::middleware::versioning::ApiVersion;
+use crate::utils::validation::ValidatedJson;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+use crate::utils::pagination::{page_size, Cursor};
+use crate::models::pagination::Page;
+use crate::models::transaction::{SendMoneyRequest, TransferRequest, TransactionQuery, TransactionResponse};
+use validator::Validate;
 
+#[utoipa::path(
+    post,
+    path = "/api/transactions/sends",
+    tag = "transactions",
+    request_body = SendMoneyRequest,
+    responses((status = 200, description = "Payment created", body = TransactionResponse)),
+    security(("bearer" = []), ("signature" = []))
+)]
 #[axum::debug_handler]
 pub async fn send_money(
     Extension(pool): Extension<DbPool>,
-    Json(payload): Json<SendMoneyRequest>,
-) -> Result<Json<TransactionResponse>, StatusCode> {
-    match transaction_service::send_money(&pool, payload).await {
-        Ok(transaction) => Ok(Json(transaction)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Extension(claims): Extension<Claims>,
+    ValidatedJson(payload): ValidatedJson<SendMoneyRequest>,
+) -> Result<Json<TransactionResponse>, ApiError> {
+    let user_id = claims.user_id()?;
+    ensure_account_owned(&pool, payload.account_id, user_id).await?;
+
+    let transaction = transaction_service::send_money(&pool, user_id, payload).await?;
+    Ok(Json(transaction))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/transactions/transfers",
+    tag = "transactions",
+    request_body = TransferRequest,
+    responses((status = 200, description = "Transfer created", body = TransactionResponse)),
+    security(("bearer" = []), ("signature" = []))
+)]
 #[axum::debug_handler]
 pub async fn transfer_money(
     Extension(pool): Extension<DbPool>,
-    Json(payload): Json<TransferRequest>,
-) -> Result<Json<TransactionResponse>, StatusCode> {
-    match transaction_service::transfer_money(&pool, payload).await {
-        Ok(transaction) => Ok(Json(transaction)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Extension(claims): Extension<Claims>,
+    ValidatedJson(payload): ValidatedJson<TransferRequest>,
+) -> Result<Json<TransactionResponse>, ApiError> {
+    let user_id = claims.user_id()?;
+    // Transfers move money between the caller's own accounts
+    ensure_account_owned(&pool, payload.from_account_id, user_id).await?;
+    ensure_account_owned(&pool, payload.to_account_id, user_id).await?;
+
+    let transaction = transaction_service::transfer_money(&pool, user_id, payload).await?;
+    Ok(Json(transaction))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/accounts/{account_id}/transactions",
+    tag = "transactions",
+    params(("account_id" = Uuid, Path, description = "Account id"), TransactionQuery),
+    responses((status = 200, description = "Transactions of the account matching the filters", body = Page<TransactionResponse>)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn get_transactions(
     Extension(pool): Extension<DbPool>,
-    Path(account_id): Path<Uuid>,
+    Extension(version): Extension<ApiVersion>,
+    OwnedAccount(account_id): OwnedAccount,
     Query(query): Query<TransactionQuery>,
-) -> Result<Json<Vec<TransactionResponse>>, StatusCode> {
-    match transaction_service::get_transactions_by_account(&pool, account_id, query.limit, query.offset).await {
-        Ok(transactions) => Ok(Json(transactions)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+) -> Result<Response, ApiError> {
+    list(&pool, version, TransactionScope::Account(account_id), query).await
+}
+
+/// Searches the transactions of every account of the caller.
+#[utoipa::path(
+    get,
+    path = "/api/transactions",
+    tag = "transactions",
+    params(TransactionQuery),
+    responses((status = 200, description = "Transactions of the caller's accounts matching the filters", body = Page<TransactionResponse>)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[axum::debug_handler]
+pub async fn search_transactions(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    Extension(version): Extension<ApiVersion>,
+    Query(query): Query<TransactionQuery>,
+) -> Result<Response, ApiError> {
+    let user_id = claims.user_id()?;
+    list(&pool, version, TransactionScope::User(user_id), query).await
+}
+
+/// API v1 answers with a bare array paged by offset, v2 with a page at a cursor.
+async fn list(pool: &DbPool, version: ApiVersion, scope: TransactionScope, query: TransactionQuery) -> Result<Response, ApiError> {
+    query.validate()?;
+
+    if version == ApiVersion::V1 {
+        let transactions = transaction_service::list_transactions_by_offset(pool, scope, &query).await?;
+        return Ok(Json(transactions).into_response());
     }
+
+    let order = query.sort.unwrap_or_default().order();
+    let cursor = query.cursor.as_deref().map(|cursor| Cursor::decode(cursor, order)).transpose()?;
+    let page = transaction_service::list_transactions(pool, scope, &query, cursor.as_ref(), page_size(query.limit)).await?;
+    Ok(Json(page).into_response())
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/transactions/{id}",
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "Transaction id")),
+    responses((status = 200, description = "The transaction", body = TransactionResponse)),
+    security(("bearer" = []), ("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn get_transaction(
     Extension(pool): Extension<DbPool>,
-    Path(transaction_id): Path<Uuid>,
-) -> Result<Json<TransactionResponse>, StatusCode> {
-    match transaction_service::get_transaction(&pool, transaction_id).await {
-        Ok(Some(transaction)) => Ok(Json(transaction)),
-        Ok(None) => Err(StatusCode::NOT_FOUND),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    OwnedTransaction(transaction_id): OwnedTransaction,
+) -> Result<Json<TransactionResponse>, ApiError> {
+    match transaction_service::get_transaction(&pool, transaction_id).await? {
+        Some(transaction) => Ok(Json(transaction)),
+        None => Err(ApiError::NotFound),
     }
 }
\ No newline at end of file
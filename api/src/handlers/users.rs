@@ -1,28 +1,43 @@
-use axum::{Json, http::StatusCode, Extension};
+use axum::{Json, Extension};
 use crate::models::user::{CreateUserRequest, UserResponse, UserType};
 use crate::services::user_service;
 use crate::services::database::DbPool;
+use crate::config::app_config::AppConfig;
+use crate::utils::validation::ValidatedJson;
+use crate::utils::error::ApiError;
 
+#[utoipa::path(
+    post,
+    path = "/api/consumers",
+    tag = "users",
+    request_body = CreateUserRequest,
+    responses((status = 200, description = "Consumer created", body = UserResponse))
+)]
 pub async fn create_consumer(
     Extension(pool): Extension<DbPool>,
-    Json(mut payload): Json<CreateUserRequest>,
-) -> Result<Json<UserResponse>, StatusCode> {
+    Extension(config): Extension<AppConfig>,
+    ValidatedJson(mut payload): ValidatedJson<CreateUserRequest>,
+) -> Result<Json<UserResponse>, ApiError> {
     payload.user_type = UserType::Consumer;
 
-    match user_service::create_user(&pool, payload).await {
-        Ok(user) => Ok(Json(user)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    let user = user_service::create_user(&pool, payload, &config.password_params).await?;
+    Ok(Json(user))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/corporates",
+    tag = "users",
+    request_body = CreateUserRequest,
+    responses((status = 200, description = "Corporate created", body = UserResponse))
+)]
 pub async fn create_corporate(
     Extension(pool): Extension<DbPool>,
-    Json(mut payload): Json<CreateUserRequest>,
-) -> Result<Json<UserResponse>, StatusCode> {
+    Extension(config): Extension<AppConfig>,
+    ValidatedJson(mut payload): ValidatedJson<CreateUserRequest>,
+) -> Result<Json<UserResponse>, ApiError> {
     payload.user_type = UserType::Corporate;
 
-    match user_service::create_user(&pool, payload).await {
-        Ok(user) => Ok(Json(user)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    let user = user_service::create_user(&pool, payload, &config.password_params).await?;
+    Ok(Json(user))
 }
\ No newline at end of file
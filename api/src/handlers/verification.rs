@@ -0,0 +1,154 @@
+use axum::{Json, http::{HeaderMap, StatusCode}, Extension};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use validator::Validate;
+use crate::services::{auth_service, lockout_service, verification_service};
+use crate::services::database::DbPool;
+use crate::config::app_config::AppConfig;
+use crate::middleware::auth::SessionCache;
+use crate::utils::client;
+use crate::utils::validation::ValidatedJson;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(as = VerificationTokenRequest)]
+pub struct TokenRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ForgotPasswordRequest {
+    #[validate(email)]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    #[validate(length(min = 8, max = 128))]
+    pub new_password: String,
+}
+
+fn invalid_token() -> ApiError {
+    ApiError::BadRequest("the token is invalid, expired or already used".to_string())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/email/verification",
+    tag = "auth",
+    responses((status = 202, description = "Verification email sent")),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn request_email_verification(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(claims): Extension<Claims>,
+) -> Result<StatusCode, ApiError> {
+    let user_id = claims.user_id()?;
+
+    match verification_service::send_email_verification(&pool, user_id, &config.jwt_secret, &config.app_base_url).await? {
+        true => Ok(StatusCode::ACCEPTED),
+        false => Err(ApiError::Conflict("email address is already verified".to_string())),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/email/verify",
+    tag = "auth",
+    request_body = TokenRequest,
+    responses((status = 204, description = "Email address verified"))
+)]
+#[axum::debug_handler]
+pub async fn verify_email(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Json(payload): Json<TokenRequest>,
+) -> Result<StatusCode, ApiError> {
+    match verification_service::verify_email(&pool, &payload.token, &config.jwt_secret).await? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(invalid_token()),
+    }
+}
+
+/// Always answers 202 so the endpoint can't be used to probe for accounts.
+#[utoipa::path(
+    post,
+    path = "/api/auth/password/forgot",
+    tag = "auth",
+    request_body = ForgotPasswordRequest,
+    responses((status = 202, description = "Reset email sent if the account exists"))
+)]
+#[axum::debug_handler]
+pub async fn forgot_password(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    ValidatedJson(payload): ValidatedJson<ForgotPasswordRequest>,
+) -> Result<StatusCode, ApiError> {
+    verification_service::send_password_reset(&pool, &payload.email, &config.jwt_secret, &config.app_base_url).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/password/reset",
+    tag = "auth",
+    request_body = ResetPasswordRequest,
+    responses((status = 204, description = "Password changed and sessions revoked"))
+)]
+#[axum::debug_handler]
+pub async fn reset_password(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(session_cache): Extension<SessionCache>,
+    ValidatedJson(payload): ValidatedJson<ResetPasswordRequest>,
+) -> Result<StatusCode, ApiError> {
+    // Reject bad tokens before paying for a hash
+    if verification_service::parse_token(&payload.token, verification_service::PURPOSE_PASSWORD_RESET, &config.jwt_secret).is_none() {
+        return Err(invalid_token());
+    }
+
+    let hashed_password = auth_service::hash_password(&payload.new_password, &config.password_params).await?;
+
+    match verification_service::reset_password(&pool, &payload.token, &hashed_password, &config.jwt_secret).await? {
+        Some(revoked_sessions) => {
+            for session_id in revoked_sessions {
+                session_cache.revoke(session_id).await;
+            }
+            Ok(StatusCode::NO_CONTENT)
+        }
+        None => Err(invalid_token()),
+    }
+}
+
+/// Lifts a brute-force lockout with the token from the lockout email.
+#[utoipa::path(
+    post,
+    path = "/api/auth/unlock",
+    tag = "auth",
+    request_body = TokenRequest,
+    responses((status = 204, description = "Account unlocked"))
+)]
+#[axum::debug_handler]
+pub async fn unlock_account(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    headers: HeaderMap,
+    Json(payload): Json<TokenRequest>,
+) -> Result<StatusCode, ApiError> {
+    match lockout_service::unlock_account(
+        &pool,
+        &payload.token,
+        &config.jwt_secret,
+        client::client_ip(&headers).as_deref(),
+        client::user_agent(&headers).as_deref(),
+    )
+    .await?
+    {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(invalid_token()),
+    }
+}
\ No newline at end of file
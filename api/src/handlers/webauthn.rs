@@ -0,0 +1,418 @@
+use axum::{Json, http::{HeaderMap, StatusCode}, extract::Path, Extension};
+use uuid::Uuid;
+use crate::services::{lockout_service, mfa_service, user_service, webauthn_service};
+use crate::services::database::DbPool;
+use crate::services::encryption_service::EncryptionService;
+use crate::services::event_bus::EventBus;
+use crate::config::app_config::AppConfig;
+use crate::handlers::auth::{start_session, LoginResponse};
+use crate::middleware::rate_limit::LoginThrottle;
+use crate::models::live_event::SecurityAlertKind;
+use crate::models::webauthn::{
+    AuthenticateRequest, AuthenticationOptionsRequest, AuthenticationOptionsResponse, AuthenticatorSelection,
+    CreationOptions, CredentialDescriptor, CredentialParameter, DeleteCredentialRequest, RegisterCredentialRequest,
+    RegistrationOptionsResponse, RelyingPartyEntity, RequestOptions, UserEntity, WebauthnCredential,
+    WebauthnCredentialResponse,
+};
+use crate::utils::client;
+use crate::utils::error::{ApiError, FieldError};
+use crate::utils::jwt::{self, Claims};
+use crate::utils::webauthn::{self, RelyingParty};
+
+fn relying_party(config: &AppConfig) -> RelyingParty<'_> {
+    RelyingParty {
+        id: &config.webauthn_rp_id,
+        origins: &config.webauthn_origins,
+    }
+}
+
+fn descriptor(credential_id: String) -> CredentialDescriptor {
+    CredentialDescriptor {
+        credential_type: "public-key".to_string(),
+        id: credential_id,
+    }
+}
+
+fn malformed(field: &str) -> ApiError {
+    ApiError::BadRequest(format!("{} is not valid base64url", field))
+}
+
+/// Checks an assertion answering a `ceremony` ceremony and records the use
+/// of its credential. Returns the user the ceremony was started for, if any,
+/// and the credential that signed.
+async fn check_assertion(
+    pool: &DbPool,
+    config: &AppConfig,
+    payload: &AuthenticateRequest,
+    ceremony: &str,
+) -> Result<(Option<Uuid>, WebauthnCredential), ApiError> {
+    let (bound_user, challenge) = webauthn_service::take_challenge(pool, payload.ceremony_id, ceremony)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+
+    let credential = webauthn_service::get_credential(pool, &payload.credential_id)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+
+    // Ceremonies started for a user only accept that user's passkeys
+    if bound_user.is_some_and(|user_id| user_id != credential.user_id) {
+        return Err(ApiError::Unauthorized);
+    }
+
+    if let Some(user_handle) = &payload.response.user_handle {
+        let user_handle = webauthn::decode(user_handle).map_err(|_| malformed("userHandle"))?;
+        if user_handle != credential.user_id.as_bytes() {
+            return Err(ApiError::Unauthorized);
+        }
+    }
+
+    let client_data_json = webauthn::decode(&payload.response.client_data_json)
+        .map_err(|_| malformed("clientDataJSON"))?;
+    let authenticator_data = webauthn::decode(&payload.response.authenticator_data)
+        .map_err(|_| malformed("authenticatorData"))?;
+    let signature = webauthn::decode(&payload.response.signature)
+        .map_err(|_| malformed("signature"))?;
+
+    let sign_count = webauthn::verify_assertion(
+        &relying_party(config),
+        &challenge,
+        &client_data_json,
+        &authenticator_data,
+        &signature,
+        &credential.public_key,
+        credential.sign_count as u32,
+        bound_user.is_none(),
+    )
+    .map_err(|_| ApiError::Unauthorized)?;
+
+    if !webauthn_service::record_use(pool, credential.id, credential.sign_count, sign_count as i64).await? {
+        return Err(ApiError::Unauthorized);
+    }
+
+    Ok((bound_user, credential))
+}
+
+/// Options for a request ceremony over the passkeys of `user_id`, or any
+/// discoverable one without a user.
+async fn request_options(
+    pool: &DbPool,
+    config: &AppConfig,
+    user_id: Option<Uuid>,
+    ceremony: &str,
+) -> Result<AuthenticationOptionsResponse, ApiError> {
+    let allow_credentials = match user_id {
+        Some(user_id) => webauthn_service::get_credentials_by_user(pool, user_id)
+            .await?
+            .into_iter()
+            .map(|c| descriptor(c.credential_id))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let challenge = webauthn::generate_challenge();
+    let ceremony_id = webauthn_service::create_challenge(pool, user_id, ceremony, &challenge).await?;
+
+    Ok(AuthenticationOptionsResponse {
+        ceremony_id,
+        public_key: RequestOptions {
+            challenge,
+            rp_id: config.webauthn_rp_id.clone(),
+            timeout: webauthn_service::CEREMONY_TIMEOUT_SECONDS as u64 * 1000,
+            allow_credentials,
+            // A passkey standing in for the password must prove user verification
+            user_verification: if user_id.is_some() { "preferred" } else { "required" }.to_string(),
+        },
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/webauthn/register/options",
+    tag = "webauthn",
+    responses((status = 200, description = "Options for navigator.credentials.create", body = RegistrationOptionsResponse)),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn registration_options(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<RegistrationOptionsResponse>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    let user = user_service::get_user_by_id(&pool, user_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+
+    let existing = webauthn_service::get_credentials_by_user(&pool, user_id).await?;
+
+    let challenge = webauthn::generate_challenge();
+    let ceremony_id = webauthn_service::create_challenge(&pool, Some(user_id), webauthn_service::CEREMONY_REGISTRATION, &challenge).await?;
+
+    Ok(Json(RegistrationOptionsResponse {
+        ceremony_id,
+        public_key: CreationOptions {
+            challenge,
+            rp: RelyingPartyEntity {
+                id: config.webauthn_rp_id.clone(),
+                name: config.webauthn_rp_name.clone(),
+            },
+            // The user handle is the raw user id, so discoverable logins can be matched back
+            user: UserEntity {
+                id: webauthn::encode(user.id.as_bytes()),
+                name: user.email,
+                display_name: user.name,
+            },
+            pub_key_cred_params: webauthn::SUPPORTED_ALGORITHMS
+                .iter()
+                .map(|&alg| CredentialParameter { credential_type: "public-key".to_string(), alg })
+                .collect(),
+            timeout: webauthn_service::CEREMONY_TIMEOUT_SECONDS as u64 * 1000,
+            attestation: "none".to_string(),
+            exclude_credentials: existing.into_iter().map(|c| descriptor(c.credential_id)).collect(),
+            authenticator_selection: AuthenticatorSelection {
+                resident_key: "preferred".to_string(),
+                user_verification: "preferred".to_string(),
+            },
+        },
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/webauthn/register",
+    tag = "webauthn",
+    request_body = RegisterCredentialRequest,
+    responses((status = 200, description = "Passkey registered", body = WebauthnCredentialResponse)),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn register(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(claims): Extension<Claims>,
+    Json(payload): Json<RegisterCredentialRequest>,
+) -> Result<Json<WebauthnCredentialResponse>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    let challenge = match webauthn_service::take_challenge(&pool, payload.ceremony_id, webauthn_service::CEREMONY_REGISTRATION).await? {
+        Some((Some(owner), challenge)) if owner == user_id => challenge,
+        _ => return Err(ApiError::BadRequest("unknown or expired ceremony".to_string())),
+    };
+
+    let client_data_json = webauthn::decode(&payload.response.client_data_json)
+        .map_err(|_| malformed("clientDataJSON"))?;
+    let attestation_object = webauthn::decode(&payload.response.attestation_object)
+        .map_err(|_| malformed("attestationObject"))?;
+
+    let registered = webauthn::verify_registration(
+        &relying_party(&config),
+        &challenge,
+        &client_data_json,
+        &attestation_object,
+        false,
+    )
+    .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let name = payload.name.unwrap_or_else(|| "Passkey".to_string());
+
+    match webauthn_service::add_credential(
+        &pool,
+        user_id,
+        &webauthn::encode(&registered.credential_id),
+        &registered.public_key,
+        registered.sign_count as i64,
+        &name,
+    )
+    .await
+    {
+        Ok(credential) => Ok(Json(WebauthnCredentialResponse {
+            id: credential.id,
+            credential_id: credential.credential_id,
+            name: credential.name,
+            created_at: credential.created_at,
+            last_used_at: credential.last_used_at,
+        })),
+        // Credential ids are globally unique; a duplicate means it is already registered
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(ApiError::Conflict("credential is already registered".to_string())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/webauthn/credentials",
+    tag = "webauthn",
+    responses((status = 200, description = "Passkeys of the caller", body = [WebauthnCredentialResponse])),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn list_credentials(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<WebauthnCredentialResponse>>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    let credentials = webauthn_service::get_credentials_by_user(&pool, user_id).await?;
+    Ok(Json(
+        credentials
+            .into_iter()
+            .map(|credential| WebauthnCredentialResponse {
+                id: credential.id,
+                credential_id: credential.credential_id,
+                name: credential.name,
+                created_at: credential.created_at,
+                last_used_at: credential.last_used_at,
+            })
+            .collect(),
+    ))
+}
+
+/// Starts a step-up ceremony over the caller's passkeys, whose assertion
+/// proves a second factor before one is removed.
+#[utoipa::path(
+    post,
+    path = "/api/auth/webauthn/step-up/options",
+    tag = "webauthn",
+    responses((status = 200, description = "Options for navigator.credentials.get", body = AuthenticationOptionsResponse)),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+pub async fn step_up_options(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<AuthenticationOptionsResponse>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    let options = request_options(&pool, &config, Some(user_id), webauthn_service::CEREMONY_STEP_UP).await?;
+    Ok(Json(options))
+}
+
+/// Removes a passkey once the caller proved a second factor again, so a
+/// stolen session can't strip the account of its second factors.
+#[utoipa::path(
+    delete,
+    path = "/api/auth/webauthn/credentials/{id}",
+    tag = "webauthn",
+    params(("id" = Uuid, Path, description = "Credential id")),
+    request_body = DeleteCredentialRequest,
+    responses(
+        (status = 204, description = "Passkey deleted"),
+        (status = 422, description = "Missing or wrong code or assertion"),
+    ),
+    security(("bearer" = []))
+)]
+#[axum::debug_handler]
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_credential(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(encryption): Extension<EncryptionService>,
+    Extension(events): Extension<EventBus>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<DeleteCredentialRequest>,
+) -> Result<StatusCode, ApiError> {
+    let user_id = claims.user_id()?;
+
+    let verified = match (&payload.code, &payload.recovery_code, &payload.assertion) {
+        (Some(code), _, _) => mfa_service::verify_totp(&pool, &encryption, user_id, code, true).await?,
+        (None, Some(recovery_code), _) => mfa_service::use_recovery_code(&pool, user_id, recovery_code).await?,
+        (None, None, Some(assertion)) => {
+            match check_assertion(&pool, &config, assertion, webauthn_service::CEREMONY_STEP_UP).await {
+                Ok((bound_user, _)) => bound_user == Some(user_id),
+                Err(ApiError::Unauthorized) => false,
+                Err(e) => return Err(e),
+            }
+        }
+        (None, None, None) => false,
+    };
+    if !verified {
+        return Err(ApiError::Validation(vec![FieldError::new(
+            "code",
+            "step_up_required",
+            "a current TOTP or recovery code, or a step-up passkey assertion, is required",
+        )]));
+    }
+
+    if !webauthn_service::delete_credential(&pool, id, user_id).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    if mfa_service::second_factor_methods(&pool, user_id).await?.is_empty() {
+        events
+            .alert(user_id, SecurityAlertKind::TwoFactorDisabled, client::client_ip(&headers), client::user_agent(&headers))
+            .await;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Starts an authentication ceremony. Without `mfa_token` this is a
+/// passwordless login with a discoverable credential; with it, the passkey
+/// completes a password login as the second factor.
+#[utoipa::path(
+    post,
+    path = "/api/auth/webauthn/login/options",
+    tag = "webauthn",
+    request_body = AuthenticationOptionsRequest,
+    responses((status = 200, description = "Options for navigator.credentials.get", body = AuthenticationOptionsResponse))
+)]
+#[axum::debug_handler]
+pub async fn authentication_options(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Json(payload): Json<AuthenticationOptionsRequest>,
+) -> Result<Json<AuthenticationOptionsResponse>, ApiError> {
+    let user_id = match &payload.mfa_token {
+        Some(token) => {
+            let challenge = jwt::verify_mfa_challenge_token(token, &config.jwt_keys)
+                .map_err(|_| ApiError::Unauthorized)?;
+            let user_id = Uuid::parse_str(&challenge.sub).map_err(|_| ApiError::Unauthorized)?;
+            let challenge_id = Uuid::parse_str(&challenge.jti).map_err(|_| ApiError::Unauthorized)?;
+            if !mfa_service::challenge_open(&pool, challenge_id, user_id).await? {
+                return Err(ApiError::Unauthorized);
+            }
+            Some(user_id)
+        }
+        None => None,
+    };
+
+    let options = request_options(&pool, &config, user_id, webauthn_service::CEREMONY_AUTHENTICATION).await?;
+    Ok(Json(options))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/webauthn/login",
+    tag = "webauthn",
+    request_body = AuthenticateRequest,
+    responses((status = 200, description = "Session opened", body = LoginResponse))
+)]
+#[axum::debug_handler]
+pub async fn authenticate(
+    Extension(pool): Extension<DbPool>,
+    Extension(config): Extension<AppConfig>,
+    Extension(login_throttle): Extension<LoginThrottle>,
+    Extension(events): Extension<EventBus>,
+    headers: HeaderMap,
+    Json(payload): Json<AuthenticateRequest>,
+) -> Result<Json<LoginResponse>, ApiError> {
+    // Second-factor ceremonies only accept the user who passed the password step
+    let (bound_user, credential) = check_assertion(&pool, &config, &payload, webauthn_service::CEREMONY_AUTHENTICATION).await?;
+
+    let user = user_service::get_user_by_id(&pool, credential.user_id)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+
+    // The password step's challenge is answered, and its failures forgiven
+    if bound_user.is_some() {
+        mfa_service::spend_challenges(&pool, user.id).await?;
+        login_throttle.record_success(&user.email).await;
+        lockout_service::reset_failures(&pool, user.id).await?;
+    }
+
+    let response = start_session(&pool, &config, &events, &headers, user).await?;
+    Ok(Json(response))
+}
\ No newline at end of file
@@ -0,0 +1,167 @@
+use axum::{Json, http::StatusCode, extract::{Path, Query}, Extension};
+use uuid::Uuid;
+use crate::services::webhook_service;
+use crate::services::database::DbPool;
+use crate::services::encryption_service::EncryptionService;
+use crate::models::pagination::{Page, PageQuery};
+use crate::models::webhook::{event_permission, CreateWebhookRequest, CreatedWebhookSubscription, WebhookDelivery, WebhookSubscription};
+use crate::utils::validation::ValidatedJson;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+use crate::utils::pagination::{page_size, Cursor, Order};
+
+/// Subscribes an endpoint to events of the caller's resources. The signing
+/// secret is only in this response. Subscriptions made with an OAuth token
+/// belong to its client, and are only seen and managed by it.
+#[utoipa::path(
+    post,
+    path = "/api/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookRequest,
+    responses((status = 201, description = "Subscription created", body = CreatedWebhookSubscription)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[axum::debug_handler]
+pub async fn create_webhook(
+    Extension(pool): Extension<DbPool>,
+    Extension(encryption): Extension<EncryptionService>,
+    Extension(claims): Extension<Claims>,
+    ValidatedJson(payload): ValidatedJson<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<CreatedWebhookSubscription>), ApiError> {
+    let user_id = claims.user_id()?;
+
+    // Events carry the resources they are about, so they need the permission to read them
+    let readable = payload
+        .event_types
+        .iter()
+        .all(|event_type| event_permission(event_type).is_some_and(|permission| claims.has_permission(permission)));
+    if !readable {
+        return Err(ApiError::Forbidden);
+    }
+
+    let (subscription, secret) =
+        webhook_service::create_subscription(&pool, &encryption, user_id, claims.client_id.as_deref(), payload).await?;
+    Ok((StatusCode::CREATED, Json(CreatedWebhookSubscription { subscription, secret })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/webhooks",
+    tag = "webhooks",
+    responses((status = 200, description = "Subscriptions of the caller, newest first", body = [WebhookSubscription])),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[axum::debug_handler]
+pub async fn get_webhooks(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<WebhookSubscription>>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    let subscriptions = webhook_service::get_subscriptions(&pool, user_id, claims.client_id.as_deref()).await?;
+    Ok(Json(subscriptions))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = Uuid, Path, description = "Subscription id")),
+    responses((status = 204, description = "Subscription and its deliveries deleted")),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[axum::debug_handler]
+pub async fn delete_webhook(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let user_id = claims.user_id()?;
+
+    match webhook_service::delete_subscription(&pool, id, user_id, claims.client_id.as_deref()).await? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(ApiError::NotFound),
+    }
+}
+
+/// Enables a subscription disabled after repeated failures. Its pending
+/// deliveries are retried right away.
+#[utoipa::path(
+    post,
+    path = "/api/webhooks/{id}/enable",
+    tag = "webhooks",
+    params(("id" = Uuid, Path, description = "Subscription id")),
+    responses((status = 200, description = "Subscription enabled", body = WebhookSubscription)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[axum::debug_handler]
+pub async fn enable_webhook(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookSubscription>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    match webhook_service::enable_subscription(&pool, id, user_id, claims.client_id.as_deref()).await? {
+        Some(subscription) => Ok(Json(subscription)),
+        None => Err(ApiError::NotFound),
+    }
+}
+
+/// The delivery log of a subscription. It is paginated in every version.
+#[utoipa::path(
+    get,
+    path = "/api/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(("id" = Uuid, Path, description = "Subscription id"), PageQuery),
+    responses((status = 200, description = "Deliveries of the subscription, newest first", body = Page<WebhookDelivery>)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[axum::debug_handler]
+pub async fn list_webhook_deliveries(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Page<WebhookDelivery>>, ApiError> {
+    let user_id = claims.user_id()?;
+
+    if webhook_service::get_subscription(&pool, id, user_id, claims.client_id.as_deref()).await?.is_none() {
+        return Err(ApiError::NotFound);
+    }
+
+    let cursor = query.cursor.as_deref().map(|cursor| Cursor::decode(cursor, Order::NEWEST)).transpose()?;
+    let page = webhook_service::list_deliveries(&pool, id, cursor.as_ref(), page_size(query.limit)).await?;
+    Ok(Json(page))
+}
+
+/// Sends the event of a delivery again, as a new delivery with the same
+/// event id.
+#[utoipa::path(
+    post,
+    path = "/api/webhooks/{id}/deliveries/{delivery_id}/redeliver",
+    tag = "webhooks",
+    params(
+        ("id" = Uuid, Path, description = "Subscription id"),
+        ("delivery_id" = Uuid, Path, description = "Delivery to send again"),
+    ),
+    responses((status = 202, description = "Redelivery queued", body = WebhookDelivery)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[axum::debug_handler]
+pub async fn redeliver_webhook(
+    Extension(pool): Extension<DbPool>,
+    Extension(claims): Extension<Claims>,
+    Path((id, delivery_id)): Path<(Uuid, Uuid)>,
+) -> Result<(StatusCode, Json<WebhookDelivery>), ApiError> {
+    let user_id = claims.user_id()?;
+
+    if webhook_service::get_subscription(&pool, id, user_id, claims.client_id.as_deref()).await?.is_none() {
+        return Err(ApiError::NotFound);
+    }
+
+    match webhook_service::redeliver(&pool, id, delivery_id).await? {
+        Some(delivery) => Ok((StatusCode::ACCEPTED, Json(delivery))),
+        None => Err(ApiError::NotFound),
+    }
+}
\ No newline at end of file
@@ -4,14 +4,19 @@ mod services;
 mod middleware;
 mod utils;
 mod config;
+#[cfg(test)]
+mod test_support;
 
 use axum::{
-    routing::{get, post},
+    routing::get,
     Router,
     Extension,
-    middleware::from_fn,
+    middleware::{from_fn, from_fn_with_state},
 };
+use middleware::versioning::ApiVersion;
+use models::role::permissions;
 use std::net::SocketAddr;
+use tower::Layer;
 use tower_http::cors::CorsLayer;
 
 #[tokio::main]
@@ -19,9 +24,29 @@ async fn main() {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
-    // Load configuration
-    let config = config::app_config::AppConfig::from_env()
-        .expect("Failed to load configuration");
+    // Settings come from the environment, over the file given with `--config`
+    let args: Vec<String> = std::env::args().collect();
+    let config_path = config::file::config_path(&args).map(std::path::Path::new);
+
+    // Show what would run, secrets hidden, then report any problem
+    if args.iter().any(|arg| arg == "--print-config") {
+        let (config, problems) = config::app_config::AppConfig::read(config_path);
+        print!("{}", config.to_redacted_toml());
+        if !problems.is_empty() {
+            eprint!("{}", config::app_config::ConfigError { problems });
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Refuse to start on an invalid or insecure configuration
+    let config = match config::app_config::AppConfig::load(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprint!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Initialize database (optional for development)
     let pool = match services::database::create_pool(&config).await {
@@ -39,49 +64,219 @@ async fn main() {
         }
     };
 
-    // Initialize rate limiter (100 requests per minute per IP)
-    let rate_limiter = middleware::rate_limit::RateLimiter::new(100, 60);
+    // Check the audit trail and exit: 0 if intact, 2 at a broken link
+    if args.iter().any(|arg| arg == "--verify-audit-chain") {
+        match services::audit_service::verify_chain(&pool, &config.jwt_keys).await {
+            Ok(verification) => {
+                println!("{}", serde_json::to_string_pretty(&verification).expect("serializable report"));
+                std::process::exit(if verification.valid { 0 } else { 2 });
+            }
+            Err(e) => {
+                eprintln!("audit chain verification failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Chain the audit entries as they are written
+    services::audit_service::spawn_append_worker(pool.clone(), std::time::Duration::from_secs(1));
+
+    // Sign the end of the audit chain periodically
+    services::audit_service::spawn_checkpoint_worker(
+        pool.clone(),
+        config.jwt_keys.clone(),
+        std::time::Duration::from_secs(config.audit_checkpoint_interval_seconds),
+    );
+
+    // Drain the email outbox in the background
+    services::email_service::spawn_outbox_worker(
+        pool.clone(),
+        services::email_service::mail_sender_from_config(&config),
+        config.mail_from.clone(),
+        std::time::Duration::from_secs(5),
+    );
+
+    // Relay the domain events of the outbox to their handlers
+    services::outbox_service::spawn_relay_worker(
+        pool.clone(),
+        vec![std::sync::Arc::new(services::webhook_service::WebhookOutboxHandler)],
+        std::time::Duration::from_secs(1),
+    );
+
+    // Send due webhook deliveries in the background
+    services::webhook_service::spawn_delivery_worker(
+        pool.clone(),
+        services::webhook_sender::webhook_sender_from_config(&config),
+        services::encryption_service::EncryptionService::new(&config.encryption_key).expect("Invalid encryption key"),
+        std::time::Duration::from_secs(5),
+    );
+
+    // Live events of the streams, with the changes notified by the database
+    let events = services::event_bus::EventBus::new();
+    services::event_bus::spawn_notification_listener(pool.clone(), events.clone());
+
+    let addr = SocketAddr::new(config.bind_address, config.port);
+    let app = app(pool, config, events);
+
+    // Run it
+    println!("🚀 Server running on http://{}", addr);
 
-    // Build our application with routes
-    let app = Router::new()
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    // The peer address is the client IP unless it is a trusted proxy
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+}
+
+/// Builds the application with routes and shared state.
+fn app(pool: services::database::DbPool, config: config::app_config::AppConfig, events: services::event_bus::EventBus) -> Router {
+    // Requests allowed per IP and window (100 per minute by default)
+    let rate_limiter = middleware::rate_limit::RateLimiter::new(config.rate_limit_requests, config.rate_limit_window_seconds);
+
+    // Encrypts secrets at rest (TOTP seeds)
+    let encryption_service = services::encryption_service::EncryptionService::new(&config.encryption_key)
+        .expect("Invalid encryption key");
+
+    // Progressive delays for failed logins, per account and per IP
+    let login_throttle = middleware::rate_limit::LoginThrottle::new();
+
+    // Cache session revocation checks for 30 seconds
+    let session_cache = middleware::auth::SessionCache::new(30);
+
+    // Nonces of signed partner requests, kept for the whole timestamp window
+    let nonce_cache = middleware::request_signing::NonceCache::new(
+        2 * middleware::request_signing::MAX_CLOCK_SKEW_SECONDS as u64,
+    );
+
+    // Versions share the routes below until they diverge: a route that
+    // changes in a version is registered in that version's router only, and
+    // handlers can also branch on the `ApiVersion` extension
+    let mut routes = Router::new()
         .route("/", get(root))
         .route("/health", get(health_check))
-        .route("/api/auth/login", axum::routing::post(handlers::auth::login))
-        .route("/api/consumers", axum::routing::post(handlers::users::create_consumer))
-        .route("/api/corporates", axum::routing::post(handlers::users::create_corporate))
-        .route("/api/accounts", axum::routing::post(handlers::accounts::create_account).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/accounts/:id/iban", axum::routing::get(handlers::accounts::get_iban).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/cards", axum::routing::post(handlers::cards::create_card).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/cards/:id", axum::routing::get(handlers::cards::get_card).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/cards/:id/details", axum::routing::get(handlers::cards::get_card_details).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/accounts/:account_id/cards", axum::routing::get(handlers::cards::get_cards_by_account).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/transactions/sends", axum::routing::post(handlers::transactions::send_money).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/transactions/transfers", axum::routing::post(handlers::transactions::transfer_money).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/accounts/:account_id/transactions", axum::routing::get(handlers::transactions::get_transactions).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/transactions/:id", axum::routing::get(handlers::transactions::get_transaction).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/beneficiaries", axum::routing::post(handlers::beneficiaries::create_beneficiary).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/beneficiaries", axum::routing::get(handlers::beneficiaries::get_beneficiaries).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/beneficiaries/:id", axum::routing::get(handlers::beneficiaries::get_beneficiary).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/beneficiaries/:id", axum::routing::delete(handlers::beneficiaries::delete_beneficiary).layer(from_fn(middleware::auth::auth_middleware)))
-        .route("/api/dashboard", axum::routing::get(handlers::dashboard::get_dashboard).layer(from_fn(middleware::auth::auth_middleware)))
+        .route("/.well-known/jwks.json", get(handlers::auth::jwks))
+        // OpenAPI documents of each version, with a Swagger UI
+        .merge(handlers::docs::swagger_ui(&config));
+    for version in ApiVersion::ALL {
+        let api = api_routes(&config).layer(from_fn_with_state(version, middleware::versioning::version_middleware));
+        routes = routes.nest(version.prefix(), api);
+    }
+
+    let routes = routes
+        // Layers wrap the ones added before them, so the audit, rate limiting
+        // and client IP middlewares must be added first to see the extensions
+        // below; the client IP is resolved before the others read it
+        .layer(from_fn(middleware::audit::audit_middleware))
+        .layer(from_fn(middleware::rate_limit::rate_limit_middleware))
+        .layer(from_fn(middleware::client_ip::client_ip_middleware))
         .layer(Extension(pool))
         .layer(Extension(config))
         .layer(Extension(rate_limiter))
-        .layer(from_fn(middleware::rate_limit::rate_limit_middleware))
-        .layer(CorsLayer::permissive());
+        .layer(Extension(login_throttle))
+        .layer(Extension(session_cache))
+        .layer(Extension(encryption_service))
+        .layer(Extension(events))
+        .layer(Extension(nonce_cache));
 
-    // Run it
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    println!("🚀 Server running on http://{}", addr);
+    // Unversioned paths are given a version before routing, so the router is
+    // wrapped rather than layered
+    Router::new()
+        .fallback_service(from_fn(middleware::versioning::negotiate_version).layer(routes))
+        // Outside everything else, so every error gets a correlation id
+        .layer(from_fn(middleware::request_id::request_id_middleware))
+        .layer(CorsLayer::permissive())
+}
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+/// Routes of one version of the API, relative to its `/api/vN` prefix.
+fn api_routes(config: &config::app_config::AppConfig) -> Router {
+    let mut routes = Router::new()
+        .route("/auth/login", axum::routing::post(handlers::auth::login))
+        .route("/auth/logout", axum::routing::post(handlers::auth::logout).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/auth/sessions", axum::routing::get(handlers::auth::list_sessions).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/auth/sessions/:id", axum::routing::delete(handlers::auth::revoke_session).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/auth/mfa/verify", axum::routing::post(handlers::mfa::verify))
+        .route("/auth/mfa/totp", axum::routing::post(handlers::mfa::enroll_totp).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/auth/mfa/totp", axum::routing::delete(handlers::mfa::disable_totp).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/auth/mfa/totp/confirm", axum::routing::post(handlers::mfa::confirm_totp).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/auth/email/verification", axum::routing::post(handlers::verification::request_email_verification).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/auth/email/verify", axum::routing::post(handlers::verification::verify_email))
+        .route("/auth/password/forgot", axum::routing::post(handlers::verification::forgot_password))
+        .route("/auth/password/reset", axum::routing::post(handlers::verification::reset_password))
+        .route("/auth/unlock", axum::routing::post(handlers::verification::unlock_account))
+        .route("/admin/users/:id/roles", axum::routing::get(handlers::admin::get_user_roles).layer(from_fn_with_state(permissions::ROLES_MANAGE, middleware::rbac::require_permission)).layer(from_fn(middleware::auth::auth_middleware)))
+        .route("/admin/users/:id/roles", axum::routing::post(handlers::admin::assign_role).layer(from_fn_with_state(permissions::ROLES_MANAGE, middleware::rbac::require_permission)).layer(from_fn(middleware::auth::auth_middleware)))
+        .route("/admin/users/:id/roles/:role", axum::routing::delete(handlers::admin::revoke_role).layer(from_fn_with_state(permissions::ROLES_MANAGE, middleware::rbac::require_permission)).layer(from_fn(middleware::auth::auth_middleware)))
+        .route("/admin/audit-logs", axum::routing::get(handlers::admin::get_audit_logs).layer(from_fn_with_state(permissions::AUDIT_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::auth::auth_middleware)))
+        .route("/admin/audit-logs/verify", axum::routing::get(handlers::admin::verify_audit_logs).layer(from_fn_with_state(permissions::AUDIT_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::auth::auth_middleware)))
+        .route("/api-keys", axum::routing::post(handlers::api_keys::create_api_key).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/api-keys", axum::routing::get(handlers::api_keys::get_api_keys).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/api-keys/:id", axum::routing::delete(handlers::api_keys::revoke_api_key).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/consumers", axum::routing::post(handlers::users::create_consumer))
+        .route("/corporates", axum::routing::post(handlers::users::create_corporate))
+        .route("/accounts", axum::routing::post(handlers::accounts::create_account).layer(from_fn_with_state(permissions::ACCOUNTS_WRITE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/accounts/:id/iban", axum::routing::get(handlers::accounts::get_iban).layer(from_fn_with_state(permissions::ACCOUNTS_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/cards", axum::routing::post(handlers::cards::create_card).layer(from_fn_with_state(permissions::CARDS_WRITE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/cards/:id", axum::routing::get(handlers::cards::get_card).layer(from_fn_with_state(permissions::CARDS_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/cards/:id/details", axum::routing::get(handlers::cards::get_card_details).layer(from_fn_with_state(permissions::CARDS_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/accounts/:account_id/cards", axum::routing::get(handlers::cards::get_cards_by_account).layer(from_fn_with_state(permissions::CARDS_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/transactions/sends", axum::routing::post(handlers::transactions::send_money).layer(from_fn_with_state(permissions::TRANSACTIONS_WRITE, middleware::rbac::require_permission)).layer(from_fn(middleware::request_signing::signed_request_middleware)))
+        .route("/transactions/transfers", axum::routing::post(handlers::transactions::transfer_money).layer(from_fn_with_state(permissions::TRANSACTIONS_WRITE, middleware::rbac::require_permission)).layer(from_fn(middleware::request_signing::signed_request_middleware)))
+        .route("/transactions", axum::routing::get(handlers::transactions::search_transactions).layer(from_fn_with_state(permissions::TRANSACTIONS_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/accounts/:account_id/transactions", axum::routing::get(handlers::transactions::get_transactions).layer(from_fn_with_state(permissions::TRANSACTIONS_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/transactions/:id", axum::routing::get(handlers::transactions::get_transaction).layer(from_fn_with_state(permissions::TRANSACTIONS_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/beneficiaries", axum::routing::post(handlers::beneficiaries::create_beneficiary).layer(from_fn_with_state(permissions::BENEFICIARIES_WRITE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/beneficiaries", axum::routing::get(handlers::beneficiaries::get_beneficiaries).layer(from_fn_with_state(permissions::BENEFICIARIES_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/beneficiaries/:id", axum::routing::get(handlers::beneficiaries::get_beneficiary).layer(from_fn_with_state(permissions::BENEFICIARIES_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/beneficiaries/:id", axum::routing::delete(handlers::beneficiaries::delete_beneficiary).layer(from_fn_with_state(permissions::BENEFICIARIES_WRITE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/dashboard", axum::routing::get(handlers::dashboard::get_dashboard).layer(from_fn_with_state(permissions::ACCOUNTS_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/events", axum::routing::get(handlers::events::stream_events).layer(from_fn_with_state(permissions::ACCOUNTS_READ, middleware::rbac::require_permission)).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+        .route("/webhooks", axum::routing::post(handlers::webhooks::create_webhook).layer(from_fn_with_state(permissions::WEBHOOKS_MANAGE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/webhooks", axum::routing::get(handlers::webhooks::get_webhooks).layer(from_fn_with_state(permissions::WEBHOOKS_MANAGE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/webhooks/:id", axum::routing::delete(handlers::webhooks::delete_webhook).layer(from_fn_with_state(permissions::WEBHOOKS_MANAGE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/webhooks/:id/enable", axum::routing::post(handlers::webhooks::enable_webhook).layer(from_fn_with_state(permissions::WEBHOOKS_MANAGE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/webhooks/:id/deliveries", axum::routing::get(handlers::webhooks::list_webhook_deliveries).layer(from_fn_with_state(permissions::WEBHOOKS_MANAGE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)))
+        .route("/webhooks/:id/deliveries/:delivery_id/redeliver", axum::routing::post(handlers::webhooks::redeliver_webhook).layer(from_fn_with_state(permissions::WEBHOOKS_MANAGE, middleware::rbac::require_permission)).layer(from_fn(middleware::api_key::api_key_or_jwt_middleware)));
+
+    // Optional features, switched off in the `[features]` configuration
+    if config.webauthn_enabled {
+        routes = routes
+            .route("/auth/webauthn/register/options", axum::routing::post(handlers::webauthn::registration_options).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/auth/webauthn/register", axum::routing::post(handlers::webauthn::register).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/auth/webauthn/credentials", axum::routing::get(handlers::webauthn::list_credentials).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/auth/webauthn/credentials/:id", axum::routing::delete(handlers::webauthn::delete_credential).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/auth/webauthn/step-up/options", axum::routing::post(handlers::webauthn::step_up_options).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/auth/webauthn/login/options", axum::routing::post(handlers::webauthn::authentication_options))
+            .route("/auth/webauthn/login", axum::routing::post(handlers::webauthn::authenticate));
+    }
+    if config.oauth_enabled {
+        routes = routes
+            .route("/oauth/clients", axum::routing::post(handlers::oauth::register_client).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/oauth/clients", axum::routing::get(handlers::oauth::get_clients).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/oauth/clients/:id", axum::routing::delete(handlers::oauth::delete_client).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/oauth/authorize", axum::routing::get(handlers::oauth::authorization_details).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/oauth/authorize", axum::routing::post(handlers::oauth::authorize).layer(from_fn(middleware::auth::first_party_auth_middleware)))
+            .route("/oauth/token", axum::routing::post(handlers::oauth::token))
+            .route("/oauth/introspect", axum::routing::post(handlers::oauth::introspect))
+            .route("/oauth/revoke", axum::routing::post(handlers::oauth::revoke));
+    }
+
+    routes
 }
 
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "meta",
+    responses((status = 200, description = "Name of the API", body = String, content_type = "text/plain"))
+)]
 async fn root() -> &'static str {
     "Vaelix Bank API"
 }
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "meta",
+    responses((status = 200, description = "The API is up", body = String, content_type = "text/plain"))
+)]
 async fn health_check() -> &'static str {
     "OK"
-}
+}
\ No newline at end of file
@@ -0,0 +1,108 @@
+use axum::{
+    http::Request,
+    response::Response,
+    Extension,
+};
+use chrono::Utc;
+use crate::config::app_config::AppConfig;
+use crate::models::api_key::ApiKey;
+use crate::middleware::auth::{auth_middleware, run_as, SessionCache};
+use crate::services::{api_key_service, rbac_service};
+use crate::services::database::DbPool;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+
+/// Authenticates backend services with `Authorization: ApiKey <key>`, and
+/// everyone else with a bearer token through `auth_middleware`.
+///
+/// An API key acts for the user who issued it, limited to the key's scopes
+/// and to the permissions the user still holds. Key requests carry no
+/// session, so routes that manage sessions or credentials stay bearer-only.
+pub async fn api_key_or_jwt_middleware(
+    Extension(config): Extension<AppConfig>,
+    Extension(pool): Extension<DbPool>,
+    Extension(session_cache): Extension<SessionCache>,
+    req: Request<axum::body::Body>,
+    next: axum::middleware::Next,
+) -> Result<Response, ApiError> {
+    let presented = req
+        .headers()
+        .get("authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("ApiKey "))
+        .map(|key| key.trim().to_string());
+
+    let presented = match presented {
+        Some(key) => key,
+        None => return auth_middleware(Extension(config), Extension(pool), Extension(session_cache), req, next).await,
+    };
+
+    let (key, email) = api_key_service::authenticate(&pool, &presented)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+
+    let claims = claims_for_key(&pool, &key, email).await?;
+
+    Ok(run_as(claims, req, next).await)
+}
+
+/// Claims for a request made with `key`: the owner's current permissions
+/// that are also among the key's scopes.
+pub async fn claims_for_key(pool: &DbPool, key: &ApiKey, email: String) -> Result<Claims, ApiError> {
+    let access = rbac_service::get_user_access(pool, key.user_id).await?;
+
+    let now = Utc::now().timestamp() as usize;
+    Ok(Claims {
+        sub: key.user_id.to_string(),
+        email,
+        sid: String::new(),
+        roles: access.roles,
+        permissions: access
+            .permissions
+            .into_iter()
+            .filter(|permission| key.scopes.contains(permission))
+            .collect(),
+        client_id: None,
+        // Claims built per request, not a token: they expire with it
+        exp: now,
+        iat: now,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::api_key::ApiKeyType;
+    use crate::models::role::permissions;
+    use crate::test_support::{create_user, test_pool};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_key_claims_are_scopes_the_owner_still_holds() {
+        let pool = test_pool().await;
+        let owner = create_user(&pool, "Key owner").await;
+        let key = ApiKey {
+            id: Uuid::new_v4(),
+            user_id: owner.id,
+            key_type: ApiKeyType::Server,
+            name: "Reporting".to_string(),
+            prefix: "vk_test".to_string(),
+            // The owner is a customer: audit:read was never theirs
+            scopes: vec![permissions::ACCOUNTS_READ.to_string(), permissions::TRANSACTIONS_READ.to_string(), permissions::AUDIT_READ.to_string()],
+            created_at: Utc::now(),
+            last_used_at: None,
+            expires_at: None,
+            revoked_at: None,
+        };
+
+        let claims = claims_for_key(&pool, &key, owner.email.clone()).await.unwrap();
+        assert_eq!(claims.sub, owner.id.to_string());
+        assert_eq!(claims.permissions, [permissions::ACCOUNTS_READ, permissions::TRANSACTIONS_READ]);
+        assert!(!claims.has_permission(permissions::TRANSACTIONS_WRITE));
+
+        // Permissions the owner loses are lost by the key too
+        rbac_service::revoke_role(&pool, owner.id, crate::models::role::ROLE_CUSTOMER).await.unwrap();
+        assert!(claims_for_key(&pool, &key, owner.email).await.unwrap().permissions.is_empty());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,272 @@
+use axum::{
+    body::Body,
+    extract::MatchedPath,
+    http::{Method, Request},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use crate::middleware::request_id;
+use crate::middleware::versioning::{self, ApiVersion};
+use crate::services::audit_service;
+use crate::services::database::DbPool;
+use crate::utils::client;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+
+/// Bodies up to axum's default extractor limit are buffered for the log.
+const BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Reads worth recording because they expose sensitive data.
+const SENSITIVE_READS: &[&str] = &[
+    "/api/cards/:id/details",
+    "/api/accounts/:id/iban",
+    "/api/admin/audit-logs",
+];
+
+/// Body fields never written to the log.
+const REDACTED_FIELDS: &[&str] = &["code", "code_verifier", "otp", "cvv", "pin", "pan", "card_number", "credential", "assertion"];
+
+fn is_redacted(field: &str) -> bool {
+    let field = field.to_ascii_lowercase();
+    field.contains("password")
+        || field.contains("secret")
+        || field.contains("token")
+        || field.contains("encrypted")
+        || field.ends_with("_hash")
+        || REDACTED_FIELDS.contains(&field.as_str())
+}
+
+/// Copy of a JSON body or stored row with credentials and card data replaced.
+pub fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| {
+                    let value = if is_redacted(key) { json!("[redacted]") } else { redact(value) };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Routes starting a session. Their handlers record the login with the user
+/// and the new session, which the response carries no claims for.
+const LOGIN_ROUTES: &[&str] = &["/api/auth/login", "/api/auth/mfa/verify", "/api/auth/webauthn/login"];
+
+/// The table holding the resource a route changes, by the id in its path.
+fn snapshot_table(route: &str) -> Option<&'static str> {
+    match route {
+        "/api/beneficiaries/:id" => Some("beneficiaries"),
+        "/api/webhooks/:id" | "/api/webhooks/:id/enable" => Some("webhook_subscriptions"),
+        "/api/api-keys/:id" => Some("api_keys"),
+        "/api/oauth/clients/:id" => Some("oauth_clients"),
+        "/api/auth/sessions/:id" => Some("sessions"),
+        "/api/auth/webauthn/credentials/:id" => Some("webauthn_credentials"),
+        _ => None,
+    }
+}
+
+/// The redacted row `id` of `table`. The entry is written without it if it
+/// can't be read.
+async fn snapshot(pool: &DbPool, table: &str, id: Uuid) -> Option<Value> {
+    match audit_service::snapshot(pool, table, id).await {
+        Ok(row) => row.map(|row| redact(&row)),
+        Err(e) => {
+            tracing::error!("failed to read {} {} for the audit log: {}", table, id, e);
+            None
+        }
+    }
+}
+
+/// The kind of resource a route acts on, from its first segment after `/api`.
+fn resource_type(route: &str) -> &'static str {
+    match route.trim_start_matches("/api/").split('/').next().unwrap_or("") {
+        "accounts" | "dashboard" => audit_service::RESOURCE_ACCOUNT,
+        "cards" => audit_service::RESOURCE_CARD,
+        "transactions" => audit_service::RESOURCE_TRANSACTION,
+        "beneficiaries" => audit_service::RESOURCE_BENEFICIARY,
+        "api-keys" => audit_service::RESOURCE_API_KEY,
+        "webhooks" => audit_service::RESOURCE_WEBHOOK,
+        "oauth" => audit_service::RESOURCE_OAUTH_CLIENT,
+        "auth" => audit_service::RESOURCE_SESSION,
+        _ => audit_service::RESOURCE_USER,
+    }
+}
+
+/// Records every state-changing request, and reads of sensitive data, once
+/// answered: who made it (from the claims the auth middlewares leave on the
+/// response), from where, the outcome and the submitted fields. Successful
+/// changes to an existing resource also record its row before and after, so
+/// a deletion keeps what was removed. Failing to write the entry is logged
+/// but doesn't fail the request.
+pub async fn audit_middleware(
+    Extension(pool): Extension<DbPool>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let matched = match req.extensions().get::<MatchedPath>() {
+        Some(route) => route.as_str().to_string(),
+        // Unknown routes change nothing
+        None => return Ok(next.run(req).await),
+    };
+    // Actions are named the same in every version
+    let route = versioning::unversioned(&matched);
+    let api_version = ApiVersion::of_path(&matched).map(ApiVersion::number);
+
+    let writes = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if !writes && !SENSITIVE_READS.contains(&route.as_str()) {
+        return Ok(next.run(req).await);
+    }
+
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let ip_address = client::client_ip(req.headers());
+    let user_agent = client::user_agent(req.headers());
+    let resource_id = path.split('/').find_map(|segment| Uuid::parse_str(segment).ok());
+
+    let (parts, body) = req.into_parts();
+    let body = axum::body::to_bytes(body, BODY_LIMIT)
+        .await
+        .map_err(|_| ApiError::PayloadTooLarge)?;
+    let changes = serde_json::from_slice::<Value>(&body).ok().map(|body| redact(&body));
+
+    let snapshotted = snapshot_table(&route).zip(resource_id).filter(|_| writes);
+    let before = match snapshotted {
+        Some((table, id)) => snapshot(&pool, table, id).await,
+        None => None,
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let succeeded = response.status().is_success();
+
+    if succeeded && LOGIN_ROUTES.contains(&route.as_str()) {
+        return Ok(response);
+    }
+
+    // Rows are only kept for changes that went through, not for attempts on
+    // resources the caller was refused
+    let (before, after) = match snapshotted {
+        Some((table, id)) if succeeded => (before, snapshot(&pool, table, id).await),
+        _ => (None, None),
+    };
+
+    let claims = response.extensions().get::<Claims>();
+    let user_id = claims.and_then(|claims| Uuid::parse_str(&claims.sub).ok());
+    let credential = claims.map(|claims| match (&claims.client_id, claims.sid.is_empty()) {
+        (Some(_), _) => "oauth",
+        (None, true) => "api_key",
+        (None, false) => "session",
+    });
+
+    let details = json!({
+        "status": response.status().as_u16(),
+        "path": path,
+        "changes": changes,
+        "before": before,
+        "after": after,
+        "credential": credential,
+        "client_id": claims.and_then(|claims| claims.client_id.clone()),
+        "api_version": api_version,
+        "correlation_id": request_id::current(),
+    });
+
+    if let Err(e) = audit_service::record(
+        &pool,
+        user_id,
+        &format!("{} {}", method, route),
+        resource_type(&route),
+        resource_id,
+        details,
+        ip_address.as_deref(),
+        user_agent.as_deref(),
+    )
+    .await
+    {
+        tracing::error!("failed to record audit entry for {} {}: {}", method, route, e);
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware::from_fn, routing::delete, Router};
+    use tower::ServiceExt;
+    use crate::models::beneficiary::CreateBeneficiaryRequest;
+    use crate::services::beneficiary_service;
+    use crate::test_support::{create_user, test_pool};
+
+    #[test]
+    fn test_redact_hides_credentials_at_any_depth() {
+        let body = json!({
+            "email": "a@b.c",
+            "password": "hunter22",
+            "new_password": "hunter23",
+            "friendly_name": "Main",
+            "items": [{ "refresh_token": "abc", "amount": 1.0 }],
+        });
+
+        assert_eq!(redact(&body), json!({
+            "email": "a@b.c",
+            "password": "[redacted]",
+            "new_password": "[redacted]",
+            "friendly_name": "Main",
+            "items": [{ "refresh_token": "[redacted]", "amount": 1.0 }],
+        }));
+
+        let row = json!({ "name": "Main", "secret_hash": "ab12", "card_number_encrypted": "x", "cvv_encrypted": "y" });
+        assert_eq!(redact(&row), json!({
+            "name": "Main",
+            "secret_hash": "[redacted]",
+            "card_number_encrypted": "[redacted]",
+            "cvv_encrypted": "[redacted]",
+        }));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_deletions_record_the_removed_resource() {
+        let pool = test_pool().await;
+        let user = create_user(&pool, "Audit").await;
+        let beneficiary = beneficiary_service::create_beneficiary(&pool, user.id, CreateBeneficiaryRequest {
+            name: "Landlord".to_string(),
+            iban: "FR7630006000011234567890189".to_string(),
+            account_number: None,
+            sort_code: None,
+            bank_name: None,
+        })
+        .await
+        .unwrap();
+
+        let handler_pool = pool.clone();
+        let app = Router::new()
+            .route("/api/v1/beneficiaries/:id", delete(move |axum::extract::Path(id): axum::extract::Path<Uuid>| async move {
+                beneficiary_service::delete_beneficiary(&handler_pool, id, user.id).await.unwrap();
+            }))
+            .layer(from_fn(audit_middleware))
+            .layer(Extension(pool.clone()));
+        let request = Request::builder()
+            .method(Method::DELETE)
+            .uri(format!("/api/v1/beneficiaries/{}", beneficiary.id))
+            .body(Body::empty())
+            .unwrap();
+        assert!(app.oneshot(request).await.unwrap().status().is_success());
+
+        let details: Value = sqlx::query_scalar("SELECT details FROM audit_logs WHERE resource_id = $1")
+            .bind(beneficiary.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(details["before"]["name"], "Landlord");
+        assert_eq!(details["before"]["iban"], "FR7630006000011234567890189");
+        assert_eq!(details["after"], Value::Null);
+    }
+}
\ No newline at end of file
@@ -1,45 +1,163 @@
 use axum::{
-    http::{Request, StatusCode},
-    middleware::Next,
-    response::{Response, IntoResponse},
+    http::{HeaderMap, Request},
+    response::Response,
     Extension,
 };
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 use crate::config::app_config::AppConfig;
+use crate::services::database::DbPool;
+use crate::services::session_service;
+use crate::utils::error::ApiError;
 use crate::utils::jwt::{verify_token, Claims};
 
-pub async fn auth_middleware(
-    Extension(config): Extension<AppConfig>,
-    mut req: Request<axum::body::Body>,
-    next: axum::middleware::Next,
-) -> Result<Response, StatusCode> {
+/// Caches whether a session is still active so that `auth_middleware` only
+/// hits the database once per session every `ttl`.
+///
+/// Revocations made through this instance take effect immediately; revocations
+/// made elsewhere (another replica, direct SQL) are picked up once the cached
+/// entry expires.
+#[derive(Clone)]
+pub struct SessionCache {
+    entries: Arc<Mutex<HashMap<Uuid, (bool, Instant)>>>,
+    ttl: Duration,
+}
+
+impl SessionCache {
+    pub fn new(ttl_seconds: u64) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl: Duration::from_secs(ttl_seconds),
+        }
+    }
+
+    pub async fn get(&self, session_id: Uuid) -> Option<bool> {
+        let entries = self.entries.lock().await;
+
+        entries
+            .get(&session_id)
+            .filter(|(_, checked_at)| checked_at.elapsed() < self.ttl)
+            .map(|(active, _)| *active)
+    }
+
+    pub async fn insert(&self, session_id: Uuid, active: bool) {
+        let mut entries = self.entries.lock().await;
+        let now = Instant::now();
+
+        // Drop stale entries so the map doesn't grow with every session ever seen
+        entries.retain(|_, (_, checked_at)| now.duration_since(*checked_at) < self.ttl);
+
+        entries.insert(session_id, (active, now));
+    }
+
+    pub async fn revoke(&self, session_id: Uuid) {
+        self.insert(session_id, false).await;
+    }
+}
+
+/// Checks the bearer token and that its session is still active.
+async fn authenticate(
+    config: &AppConfig,
+    pool: &DbPool,
+    session_cache: &SessionCache,
+    headers: &HeaderMap,
+) -> Result<Claims, ApiError> {
     // Extract token from Authorization header
-    let auth_header = req
-        .headers()
+    let auth_header = headers
         .get("authorization")
         .and_then(|h| h.to_str().ok())
         .and_then(|h| h.strip_prefix("Bearer "));
 
     let token = match auth_header {
         Some(token) => token,
-        None => return Err(StatusCode::UNAUTHORIZED),
+        None => return Err(ApiError::Unauthorized),
     };
 
     // Verify token
-    match verify_token(token, &config.jwt_secret) {
-        Ok(claims) => {
-            // Add user info to request extensions
-            req.extensions_mut().insert(claims);
-            Ok(next.run(req).await)
+    let claims = verify_token(token, &config.jwt_keys)
+        .map_err(|_| ApiError::Unauthorized)?;
+
+    // Reject tokens whose session has been logged out or revoked
+    let session_id = Uuid::parse_str(&claims.sid)
+        .map_err(|_| ApiError::Unauthorized)?;
+
+    if !session_active(pool, session_cache, session_id).await? {
+        return Err(ApiError::Unauthorized);
+    }
+
+    Ok(claims)
+}
+
+/// Whether a session is neither logged out nor revoked, through the cache.
+pub async fn session_active(
+    pool: &DbPool,
+    session_cache: &SessionCache,
+    session_id: Uuid,
+) -> Result<bool, ApiError> {
+    match session_cache.get(session_id).await {
+        Some(active) => Ok(active),
+        None => {
+            let active = session_service::touch_session(pool, session_id).await?;
+            session_cache.insert(session_id, active).await;
+            Ok(active)
         }
-        Err(_) => Err(StatusCode::UNAUTHORIZED),
     }
 }
 
+/// Runs the rest of the request as `claims`. They are also left on the
+/// response for the audit middleware, which runs outside the auth layers.
+pub async fn run_as(
+    claims: Claims,
+    mut req: Request<axum::body::Body>,
+    next: axum::middleware::Next,
+) -> Response {
+    req.extensions_mut().insert(claims.clone());
+    let mut response = next.run(req).await;
+    response.extensions_mut().insert(claims);
+    response
+}
+
+/// Accepts access tokens from logins and from OAuth clients. OAuth tokens
+/// only carry their granted scopes, so they only pass permission checks.
+pub async fn auth_middleware(
+    Extension(config): Extension<AppConfig>,
+    Extension(pool): Extension<DbPool>,
+    Extension(session_cache): Extension<SessionCache>,
+    req: Request<axum::body::Body>,
+    next: axum::middleware::Next,
+) -> Result<Response, ApiError> {
+    let claims = authenticate(&config, &pool, &session_cache, req.headers()).await?;
+
+    Ok(run_as(claims, req, next).await)
+}
+
+/// Like `auth_middleware`, but for routes managing the user's own credentials,
+/// sessions and grants, which third-party (OAuth) tokens must not reach.
+pub async fn first_party_auth_middleware(
+    Extension(config): Extension<AppConfig>,
+    Extension(pool): Extension<DbPool>,
+    Extension(session_cache): Extension<SessionCache>,
+    req: Request<axum::body::Body>,
+    next: axum::middleware::Next,
+) -> Result<Response, ApiError> {
+    let claims = authenticate(&config, &pool, &session_cache, req.headers()).await?;
+
+    if claims.client_id.is_some() {
+        return Err(ApiError::Forbidden);
+    }
+
+    Ok(run_as(claims, req, next).await)
+}
+
+#[allow(dead_code)]
 pub async fn optional_auth_middleware(
     Extension(config): Extension<AppConfig>,
     mut req: Request<axum::body::Body>,
     next: axum::middleware::Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, ApiError> {
     // Extract token from Authorization header (optional)
     let auth_header = req
         .headers()
@@ -49,10 +167,38 @@ pub async fn optional_auth_middleware(
 
     if let Some(token) = auth_header {
         // Verify token if present
-        if let Ok(claims) = verify_token(token, &config.jwt_secret) {
+        if let Ok(claims) = verify_token(token, &config.jwt_keys) {
             req.extensions_mut().insert(claims);
         }
     }
 
     Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_session_cache_revoke() {
+        let cache = SessionCache::new(60);
+        let session_id = Uuid::new_v4();
+
+        assert_eq!(cache.get(session_id).await, None);
+
+        cache.insert(session_id, true).await;
+        assert_eq!(cache.get(session_id).await, Some(true));
+
+        cache.revoke(session_id).await;
+        assert_eq!(cache.get(session_id).await, Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_session_cache_expiry() {
+        let cache = SessionCache::new(0);
+        let session_id = Uuid::new_v4();
+
+        cache.insert(session_id, true).await;
+        assert_eq!(cache.get(session_id).await, None);
+    }
 }
\ No newline at end of file
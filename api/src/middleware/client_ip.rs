@@ -0,0 +1,84 @@
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use std::net::{IpAddr, SocketAddr};
+use crate::config::app_config::AppConfig;
+use crate::utils::client::CLIENT_IP_HEADER;
+
+/// The address a request came from: the peer connected to the server or,
+/// when the peer is a trusted proxy, the hop that proxy appended to
+/// `X-Forwarded-For`. Proxies append the address they were reached from, so
+/// the hops are read from the right, past the trusted proxies; what the
+/// client wrote on the left is never used.
+pub fn resolve(peer: Option<IpAddr>, forwarded_for: &[&str], trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let mut client = peer?;
+
+    if trusted_proxies.contains(&client) {
+        for hop in forwarded_for.iter().rev().flat_map(|header| header.rsplit(',')) {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip;
+            if !trusted_proxies.contains(&ip) {
+                break;
+            }
+        }
+    }
+
+    Some(client)
+}
+
+/// Replaces any `X-Real-IP` sent by the client with the resolved address,
+/// which the rate limits, login throttling and audit entries are keyed on.
+pub async fn client_ip_middleware(
+    Extension(config): Extension<AppConfig>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let peer = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(address)| address.ip());
+    let forwarded_for: Vec<&str> = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    let client = resolve(peer, &forwarded_for, &config.trusted_proxies);
+
+    let headers = req.headers_mut();
+    headers.remove(CLIENT_IP_HEADER);
+    if let Some(ip) = client {
+        let value = HeaderValue::from_str(&ip.to_string()).expect("addresses are valid header values");
+        headers.insert(CLIENT_IP_HEADER, value);
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_hops_only_count_behind_trusted_proxies() {
+        let peer: IpAddr = "10.0.0.2".parse().unwrap();
+        let proxies: Vec<IpAddr> = vec!["10.0.0.2".parse().unwrap(), "10.0.0.3".parse().unwrap()];
+        let ip = |value: &str| Some(value.parse::<IpAddr>().unwrap());
+
+        // Straight from the client, the header is its own word
+        assert_eq!(resolve(ip("203.0.113.7"), &["198.51.100.1"], &proxies), ip("203.0.113.7"));
+        assert_eq!(resolve(Some(peer), &["198.51.100.1"], &[]), Some(peer));
+
+        // Behind the proxies, the hop they appended; the client's own entries are ignored
+        assert_eq!(resolve(Some(peer), &["1.2.3.4, 203.0.113.7"], &proxies), ip("203.0.113.7"));
+        assert_eq!(resolve(Some(peer), &["1.2.3.4, 203.0.113.7, 10.0.0.3"], &proxies), ip("203.0.113.7"));
+        assert_eq!(resolve(Some(peer), &["1.2.3.4", "203.0.113.7"], &proxies), ip("203.0.113.7"));
+        assert_eq!(resolve(Some(peer), &["garbage, 10.0.0.3"], &proxies), ip("10.0.0.3"));
+        assert_eq!(resolve(Some(peer), &[], &proxies), Some(peer));
+        assert_eq!(resolve(None, &["203.0.113.7"], &proxies), None);
+    }
+}
\ No newline at end of file
@@ -1,4 +1,12 @@
 // Middleware modules
 pub mod auth;
 pub mod rate_limit;
-pub mod cors;
\ No newline at end of file
+pub mod cors;
+pub mod rbac;
+pub mod ownership;
+pub mod api_key;
+pub mod request_signing;
+pub mod audit;
+pub mod request_id;
+pub mod versioning;
+pub mod client_ip;
\ No newline at end of file
@@ -0,0 +1,227 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path},
+    http::request::Parts,
+};
+use uuid::Uuid;
+use crate::services::{account_service, card_service, transaction_service};
+use crate::services::database::DbPool;
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+
+// Extractors resolving the `:id` path segment to a resource owned by the
+// caller. Resources belonging to someone else are reported as 404, exactly
+// like missing ones, so ids can't be probed. They must run inside
+// `auth_middleware`, which provides the claims.
+
+/// The authenticated user's id, taken from the token claims.
+pub fn caller_id(parts: &Parts) -> Result<Uuid, ApiError> {
+    parts.extensions.get::<Claims>().ok_or(ApiError::Unauthorized)?.user_id()
+}
+
+fn pool(parts: &Parts) -> Result<DbPool, ApiError> {
+    parts.extensions.get::<DbPool>().cloned().ok_or_else(|| ApiError::internal("database pool extension missing"))
+}
+
+async fn path_id<S: Send + Sync>(parts: &mut Parts, state: &S) -> Result<Uuid, ApiError> {
+    let Path(id) = Path::<Uuid>::from_request_parts(parts, state)
+        .await
+        .map_err(|_| ApiError::BadRequest("the id in the path is not a valid UUID".to_string()))?;
+    Ok(id)
+}
+
+fn owned(result: Result<bool, sqlx::Error>) -> Result<(), ApiError> {
+    match result? {
+        true => Ok(()),
+        false => Err(ApiError::NotFound),
+    }
+}
+
+/// Checks an account id taken from a request body, with the same 404 semantics.
+pub async fn ensure_account_owned(pool: &DbPool, account_id: Uuid, user_id: Uuid) -> Result<(), ApiError> {
+    owned(account_service::user_owns_account(pool, account_id, user_id).await)
+}
+
+pub struct OwnedAccount(pub Uuid);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for OwnedAccount {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user_id = caller_id(parts)?;
+        let account_id = path_id(parts, state).await?;
+
+        ensure_account_owned(&pool(parts)?, account_id, user_id).await?;
+        Ok(OwnedAccount(account_id))
+    }
+}
+
+pub struct OwnedCard(pub Uuid);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for OwnedCard {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user_id = caller_id(parts)?;
+        let card_id = path_id(parts, state).await?;
+
+        owned(card_service::user_owns_card(&pool(parts)?, card_id, user_id).await)?;
+        Ok(OwnedCard(card_id))
+    }
+}
+
+pub struct OwnedTransaction(pub Uuid);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for OwnedTransaction {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user_id = caller_id(parts)?;
+        let transaction_id = path_id(parts, state).await?;
+
+        owned(transaction_service::user_owns_transaction(&pool(parts)?, transaction_id, user_id).await)?;
+        Ok(OwnedTransaction(transaction_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use serde_json::json;
+    use tower::ServiceExt;
+    use crate::config::app_config::AppConfig;
+    use crate::models::card::{CardType, CreateCardRequest};
+    use crate::models::role::UserAccess;
+    use crate::models::transaction::SendMoneyRequest;
+    use crate::services::{auth_service, rbac_service, session_service};
+    use crate::test_support::{create_account, create_user, test_pool};
+
+    struct Tenant {
+        user_id: Uuid,
+        token: String,
+        account_id: Uuid,
+        savings_account_id: Uuid,
+        card_id: Uuid,
+        transaction_id: Uuid,
+    }
+
+    async fn create_tenant(pool: &DbPool, config: &AppConfig) -> Tenant {
+        let user = create_user(pool, "Tenant").await;
+
+        let session_id = session_service::create_session(pool, user.id, None, None).await.unwrap();
+        let access: UserAccess = rbac_service::get_user_access(pool, user.id).await.unwrap();
+        let (token, _) = auth_service::generate_tokens(&user, session_id, &access, &config.jwt_keys).unwrap();
+
+        let account = create_account(pool, user.id, "Main").await;
+        let savings = create_account(pool, user.id, "Savings").await;
+
+        let card = card_service::create_card(pool, user.id, CreateCardRequest {
+            account_id: account.id,
+            card_type: CardType::Virtual,
+            friendly_name: "Card".to_string(),
+        })
+        .await
+        .unwrap();
+
+        let transaction = transaction_service::send_money(pool, user.id, SendMoneyRequest {
+            account_id: account.id,
+            amount: 12.5,
+            currency: "EUR".to_string(),
+            description: None,
+            beneficiary_name: "Someone".to_string(),
+            beneficiary_iban: "GB29NWBK60161331926819".to_string(),
+        })
+        .await
+        .unwrap();
+
+        Tenant {
+            user_id: user.id,
+            token,
+            account_id: account.id,
+            savings_account_id: savings.id,
+            card_id: card.id,
+            transaction_id: transaction.id,
+        }
+    }
+
+    /// Requests as `caller` each route that touches `owner`'s resources.
+    fn requests(caller: &Tenant, owner: &Tenant) -> Vec<(Method, String, Option<serde_json::Value>)> {
+        vec![
+            (Method::GET, format!("/api/accounts/{}/iban", owner.account_id), None),
+            (Method::GET, format!("/api/cards/{}", owner.card_id), None),
+            (Method::GET, format!("/api/cards/{}/details", owner.card_id), None),
+            (Method::GET, format!("/api/accounts/{}/cards", owner.account_id), None),
+            (Method::GET, format!("/api/accounts/{}/transactions", owner.account_id), None),
+            (Method::GET, format!("/api/transactions/{}", owner.transaction_id), None),
+            (Method::POST, "/api/cards".to_string(), Some(json!({
+                "account_id": owner.account_id,
+                "card_type": "Virtual",
+                "friendly_name": "Card",
+            }))),
+            (Method::POST, "/api/transactions/sends".to_string(), Some(json!({
+                "account_id": owner.account_id,
+                "amount": 1.0,
+                "currency": "EUR",
+                "beneficiary_name": "Someone",
+                "beneficiary_iban": "GB29NWBK60161331926819",
+            }))),
+            (Method::POST, "/api/transactions/transfers".to_string(), Some(json!({
+                "from_account_id": owner.account_id,
+                "to_account_id": caller.savings_account_id,
+                "amount": 1.0,
+                "currency": "EUR",
+            }))),
+            // Pulling money into someone else's account is refused as well
+            (Method::POST, "/api/transactions/transfers".to_string(), Some(json!({
+                "from_account_id": caller.account_id,
+                "to_account_id": owner.savings_account_id,
+                "amount": 1.0,
+                "currency": "EUR",
+            }))),
+        ]
+    }
+
+    async fn status(app: &axum::Router, token: &str, method: Method, uri: &str, body: Option<serde_json::Value>) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", format!("Bearer {}", token))
+            .header("content-type", "application/json")
+            .body(body.map(|b| Body::from(b.to_string())).unwrap_or_else(Body::empty))
+            .unwrap();
+
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_cross_tenant_access_is_not_found() {
+        let pool = test_pool().await;
+        let config = AppConfig::load(None).unwrap();
+        let alice = create_tenant(&pool, &config).await;
+        let bob = create_tenant(&pool, &config).await;
+        let app = crate::app(pool.clone(), config, crate::services::event_bus::EventBus::new());
+
+        for (method, uri, body) in requests(&alice, &bob) {
+            let status = status(&app, &alice.token, method.clone(), &uri, body).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{} {} as another tenant", method, uri);
+        }
+
+        // The same routes work on one's own resources
+        for (method, uri, body) in requests(&alice, &alice) {
+            let status = status(&app, &alice.token, method.clone(), &uri, body).await;
+            assert_eq!(status, StatusCode::OK, "{} {} as the owner", method, uri);
+        }
+
+        sqlx::query("DELETE FROM users WHERE id = ANY($1)")
+            .bind(vec![alice.user_id, bob.user_id])
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
\ No newline at end of file
@@ -1,13 +1,15 @@
 use axum::{
-    http::{Request, StatusCode},
+    http::Request,
     middleware::Next,
-    response::{Response, IntoResponse},
+    response::Response,
     Extension,
 };
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use std::time::{Duration, Instant};
+use crate::utils::client;
+use crate::utils::error::ApiError;
 
 #[derive(Clone)]
 pub struct RateLimiter {
@@ -48,18 +50,135 @@ pub async fn rate_limit_middleware(
     Extension(rate_limiter): Extension<RateLimiter>,
     req: Request<axum::body::Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, ApiError> {
     // Use IP address as rate limit key (in production, use user ID)
-    let key = req
-        .headers()
-        .get("x-forwarded-for")
-        .and_then(|h| h.to_str().ok())
-        .or_else(|| req.headers().get("x-real-ip").and_then(|h| h.to_str().ok()))
-        .unwrap_or("unknown");
-
-    if rate_limiter.check_rate_limit(key).await {
+    let key = client::client_ip(req.headers()).unwrap_or_else(|| "unknown".to_string());
+
+    if rate_limiter.check_rate_limit(&key).await {
         Ok(next.run(req).await)
     } else {
-        Err(StatusCode::TOO_MANY_REQUESTS)
+        Err(ApiError::TooManyRequests { retry_after: Some(rate_limiter.window) })
+    }
+}
+
+/// Failed attempts before login delays kick in, and the longest delay imposed.
+const ACCOUNT_FREE_ATTEMPTS: u32 = 3;
+const ACCOUNT_MAX_DELAY_SECONDS: u64 = 30;
+const IP_FREE_ATTEMPTS: u32 = 10;
+const IP_MAX_DELAY_SECONDS: u64 = 300;
+/// Failure streaks are forgotten after this long without a new failure.
+const FAILURE_RESET_SECONDS: u64 = 15 * 60;
+
+/// Delay required after `failures` consecutive failures: none for the first
+/// `free_attempts`, then doubling from one second up to `max_delay`.
+fn backoff(failures: u32, free_attempts: u32, max_delay: Duration) -> Duration {
+    if failures < free_attempts {
+        return Duration::ZERO;
+    }
+
+    let exponent = (failures - free_attempts).min(16);
+    Duration::from_secs(1u64 << exponent).min(max_delay)
+}
+
+/// Progressive delays for failed logins, tracked both per account (by
+/// submitted email, so unknown emails behave the same as real ones) and per
+/// client IP (to slow credential stuffing across many accounts).
+#[derive(Clone, Default)]
+pub struct LoginThrottle {
+    accounts: Arc<Mutex<HashMap<String, (u32, Instant)>>>,
+    ips: Arc<Mutex<HashMap<String, (u32, Instant)>>>,
+}
+
+impl LoginThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    async fn wait_for(
+        entries: &Mutex<HashMap<String, (u32, Instant)>>,
+        key: &str,
+        free_attempts: u32,
+        max_delay: Duration,
+    ) -> Duration {
+        let entries = entries.lock().await;
+
+        match entries.get(key) {
+            Some((failures, last_failure)) => {
+                backoff(*failures, free_attempts, max_delay).saturating_sub(last_failure.elapsed())
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    async fn add_failure(entries: &Mutex<HashMap<String, (u32, Instant)>>, key: &str) {
+        let mut entries = entries.lock().await;
+        let now = Instant::now();
+        let reset_after = Duration::from_secs(FAILURE_RESET_SECONDS);
+
+        entries.retain(|_, (_, last_failure)| now.duration_since(*last_failure) < reset_after);
+
+        let entry = entries.entry(key.to_string()).or_insert((0, now));
+        entry.0 += 1;
+        entry.1 = now;
+    }
+
+    /// How long the client must wait before trying this email again, if at all.
+    pub async fn retry_after(&self, email: &str, ip: Option<&str>) -> Option<Duration> {
+        let mut wait = Self::wait_for(
+            &self.accounts,
+            &email.to_lowercase(),
+            ACCOUNT_FREE_ATTEMPTS,
+            Duration::from_secs(ACCOUNT_MAX_DELAY_SECONDS),
+        )
+        .await;
+
+        if let Some(ip) = ip {
+            wait = wait.max(
+                Self::wait_for(&self.ips, ip, IP_FREE_ATTEMPTS, Duration::from_secs(IP_MAX_DELAY_SECONDS)).await,
+            );
+        }
+
+        (!wait.is_zero()).then_some(wait)
+    }
+
+    pub async fn record_failure(&self, email: &str, ip: Option<&str>) {
+        Self::add_failure(&self.accounts, &email.to_lowercase()).await;
+        if let Some(ip) = ip {
+            Self::add_failure(&self.ips, ip).await;
+        }
+    }
+
+    pub async fn record_success(&self, email: &str) {
+        self.accounts.lock().await.remove(&email.to_lowercase());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_then_caps() {
+        let max = Duration::from_secs(30);
+
+        assert_eq!(backoff(2, 3, max), Duration::ZERO);
+        assert_eq!(backoff(3, 3, max), Duration::from_secs(1));
+        assert_eq!(backoff(5, 3, max), Duration::from_secs(4));
+        assert_eq!(backoff(8, 3, max), max);
+        assert_eq!(backoff(u32::MAX, 3, max), max);
+    }
+
+    #[tokio::test]
+    async fn test_login_throttle_is_case_insensitive_and_resets() {
+        let throttle = LoginThrottle::new();
+
+        for _ in 0..ACCOUNT_FREE_ATTEMPTS {
+            assert!(throttle.retry_after("alice@example.com", None).await.is_none());
+            throttle.record_failure("Alice@Example.com", Some("10.0.0.1")).await;
+        }
+        assert!(throttle.retry_after("alice@example.com", None).await.is_some());
+
+        throttle.record_success("alice@example.com").await;
+        assert!(throttle.retry_after("alice@example.com", Some("10.0.0.1")).await.is_none());
     }
 }
\ No newline at end of file
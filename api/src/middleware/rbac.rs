@@ -0,0 +1,68 @@
+use axum::{
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use crate::utils::error::ApiError;
+use crate::utils::jwt::Claims;
+
+/// Rejects requests whose token lacks `permission`. Must run inside
+/// `auth_middleware`, which provides the claims:
+///
+/// ```ignore
+/// .route("/api/cards", post(create_card)
+///     .layer(from_fn_with_state(permissions::CARDS_WRITE, require_permission))
+///     .layer(from_fn(auth_middleware)))
+/// ```
+pub async fn require_permission(
+    State(permission): State<&'static str>,
+    Extension(claims): Extension<Claims>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if claims.has_permission(permission) {
+        Ok(next.run(req).await)
+    } else {
+        Err(ApiError::Forbidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware::from_fn_with_state, routing::get, Router};
+    use tower::ServiceExt;
+    use crate::models::role::permissions;
+
+    fn claims(permissions: &[&str]) -> Claims {
+        Claims {
+            sub: uuid::Uuid::new_v4().to_string(),
+            email: "rbac@vaelix.test".to_string(),
+            sid: String::new(),
+            roles: Vec::new(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            client_id: None,
+            exp: 0,
+            iat: 0,
+        }
+    }
+
+    async fn status(claims: Claims) -> StatusCode {
+        let app = Router::new()
+            .route("/cards", get(|| async { "cards" }))
+            .layer(from_fn_with_state(permissions::CARDS_READ, require_permission))
+            .layer(Extension(claims));
+        let request = Request::builder().uri("/cards").body(Body::empty()).unwrap();
+
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_the_route_permission() {
+        assert_eq!(status(claims(&[])).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(claims(&[permissions::CARDS_WRITE, permissions::ACCOUNTS_READ])).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(claims(&[permissions::CARDS_READ])).await, StatusCode::OK);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,85 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use uuid::Uuid;
+use crate::utils::error::{ApiError, PROBLEM_CONTENT_TYPE};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest error body kept as the detail of a converted response.
+const DETAIL_LIMIT: usize = 1024;
+
+tokio::task_local! {
+    /// Correlation id of the request being handled on this task.
+    pub static CORRELATION_ID: String;
+}
+
+/// Correlation id of the current request, if called while handling one.
+pub fn current() -> Option<String> {
+    CORRELATION_ID.try_with(Clone::clone).ok()
+}
+
+/// Reuses the caller's `X-Request-Id` if it looks like an id, so logs can be
+/// followed across services.
+fn incoming_id(req: &Request<Body>) -> Option<String> {
+    let id = req.headers().get(&REQUEST_ID_HEADER)?.to_str().ok()?;
+    let valid = !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| id.to_string())
+}
+
+/// Gives every request a correlation id, echoed in `X-Request-Id` and in
+/// error bodies. Errors that weren't produced as an `ApiError` (extractor
+/// rejections, unknown routes, bare status codes) are turned into problem
+/// details here, so every error has the same shape. JSON error bodies, like
+/// the OAuth protocol errors, are left alone.
+pub async fn request_id_middleware(req: Request<Body>, next: Next) -> Response {
+    let id = incoming_id(&req).unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let response = CORRELATION_ID
+        .scope(id.clone(), async move {
+            let response = next.run(req).await;
+            if response.status().is_client_error() || response.status().is_server_error() {
+                to_problem(response).await
+            } else {
+                response
+            }
+        })
+        .await;
+
+    let mut response = response;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+async fn to_problem(response: Response) -> Response {
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if content_type.starts_with(PROBLEM_CONTENT_TYPE) || content_type.starts_with("application/json") {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let detail = axum::body::to_bytes(body, DETAIL_LIMIT)
+        .await
+        .ok()
+        .map(|body| String::from_utf8_lossy(&body).trim().to_string())
+        .filter(|detail| !detail.is_empty());
+
+    let mut problem = ApiError::from_status(parts.status, detail).into_response();
+    // Keep headers like Retry-After or Allow
+    let own: Vec<HeaderName> = problem.headers().keys().cloned().collect();
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_LENGTH && !own.contains(name) {
+            problem.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    problem
+}
\ No newline at end of file
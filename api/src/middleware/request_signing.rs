@@ -0,0 +1,236 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::OriginalUri,
+    http::Request,
+    response::Response,
+    Extension,
+};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use chrono::Utc;
+use crate::config::app_config::AppConfig;
+use crate::middleware::api_key::claims_for_key;
+use crate::middleware::auth::{auth_middleware, run_as, SessionCache};
+use crate::services::api_key_service;
+use crate::services::database::DbPool;
+use crate::services::encryption_service::EncryptionService;
+use crate::utils::error::ApiError;
+
+// Partners sign payment calls with the secret of their API key:
+//
+//   X-Vaelix-Signature: keyId=<key prefix>,ts=<unix seconds>,nonce=<nonce>,sig=<hex>
+//
+// where `sig` is HMAC-SHA256(secret, canonical_request(...)). The key itself
+// is never sent, and a captured request can't be replayed or altered.
+
+pub const SIGNATURE_HEADER: &str = "x-vaelix-signature";
+
+/// How far the request timestamp may be from the server clock, either way.
+pub const MAX_CLOCK_SKEW_SECONDS: i64 = 300;
+
+/// Signed bodies are buffered to be hashed; payment payloads are small.
+const MAX_SIGNED_BODY_BYTES: usize = 64 * 1024;
+
+const NONCE_MIN_LEN: usize = 16;
+const NONCE_MAX_LEN: usize = 64;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, PartialEq)]
+pub struct SignatureHeader {
+    pub key_id: String,
+    pub timestamp: i64,
+    pub nonce: String,
+    pub signature: Vec<u8>,
+}
+
+pub fn parse_signature_header(value: &str) -> Option<SignatureHeader> {
+    let mut key_id = None;
+    let mut timestamp = None;
+    let mut nonce = None;
+    let mut signature = None;
+
+    for part in value.split(',') {
+        let (name, value) = part.trim().split_once('=')?;
+        match name {
+            "keyId" => key_id = Some(value.to_string()),
+            "ts" => timestamp = Some(value.parse().ok()?),
+            "nonce" => nonce = Some(value.to_string()),
+            "sig" => signature = Some(hex::decode(value).ok()?),
+            _ => return None,
+        }
+    }
+
+    let nonce: String = nonce?;
+    let nonce_ok = (NONCE_MIN_LEN..=NONCE_MAX_LEN).contains(&nonce.len())
+        && nonce.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !nonce_ok {
+        return None;
+    }
+
+    Some(SignatureHeader {
+        key_id: key_id?,
+        timestamp: timestamp?,
+        nonce,
+        signature: signature?,
+    })
+}
+
+/// The signed string: method, path with query, timestamp, nonce and the hex
+/// SHA-256 of the body, one per line.
+pub fn canonical_request(method: &str, path_and_query: &str, timestamp: i64, nonce: &str, body: &[u8]) -> String {
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        method.to_ascii_uppercase(),
+        path_and_query,
+        timestamp,
+        nonce,
+        hex::encode(Sha256::digest(body))
+    )
+}
+
+fn request_mac(secret: &str, canonical: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(canonical.as_bytes());
+    mac
+}
+
+pub fn verify_signature(secret: &str, canonical: &str, signature: &[u8]) -> bool {
+    request_mac(secret, canonical).verify_slice(signature).is_ok()
+}
+
+/// Nonces seen within the clock-skew window, per key. Older requests are
+/// refused on their timestamp, so nonces only need to be kept that long.
+///
+/// Like `SessionCache` this is per instance; replicas behind a load balancer
+/// each keep their own.
+#[derive(Clone)]
+pub struct NonceCache {
+    seen: Arc<Mutex<HashMap<String, Instant>>>,
+    ttl: Duration,
+}
+
+impl NonceCache {
+    pub fn new(ttl_seconds: u64) -> Self {
+        Self {
+            seen: Arc::new(Mutex::new(HashMap::new())),
+            ttl: Duration::from_secs(ttl_seconds),
+        }
+    }
+
+    /// Records the nonce, returning `false` if it was already used.
+    pub async fn check_and_insert(&self, key_id: &str, nonce: &str) -> bool {
+        let mut seen = self.seen.lock().await;
+        let now = Instant::now();
+
+        seen.retain(|_, at| now.duration_since(*at) < self.ttl);
+
+        seen.insert(format!("{}:{}", key_id, nonce), now).is_none()
+    }
+}
+
+/// Authenticates payment routes. Partners must sign: a signed request is
+/// checked against its key's secret, while a bare `ApiKey` header is refused.
+/// Bearer tokens from the apps go through `auth_middleware` as usual.
+#[allow(clippy::too_many_arguments)]
+pub async fn signed_request_middleware(
+    Extension(config): Extension<AppConfig>,
+    Extension(pool): Extension<DbPool>,
+    Extension(session_cache): Extension<SessionCache>,
+    Extension(encryption): Extension<EncryptionService>,
+    Extension(nonce_cache): Extension<NonceCache>,
+    req: Request<Body>,
+    next: axum::middleware::Next,
+) -> Result<Response, ApiError> {
+    let header = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .map(|h| h.to_str().ok().and_then(parse_signature_header));
+
+    let header = match header {
+        Some(Some(header)) => header,
+        Some(None) => return Err(ApiError::Unauthorized),
+        None => {
+            let api_key = req
+                .headers()
+                .get("authorization")
+                .and_then(|h| h.to_str().ok())
+                .is_some_and(|h| h.starts_with("ApiKey "));
+            if api_key {
+                return Err(ApiError::Unauthorized);
+            }
+            return auth_middleware(Extension(config), Extension(pool), Extension(session_cache), req, next).await;
+        }
+    };
+
+    if (Utc::now().timestamp() - header.timestamp).abs() > MAX_CLOCK_SKEW_SECONDS {
+        return Err(ApiError::Unauthorized);
+    }
+
+    let (key, email, secret) = api_key_service::signing_key(&pool, &encryption, &header.key_id)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+
+    let (parts, body) = req.into_parts();
+    let body: Bytes = axum::body::to_bytes(body, MAX_SIGNED_BODY_BYTES)
+        .await
+        .map_err(|_| ApiError::PayloadTooLarge)?;
+
+    // The path as the partner sent it, before version negotiation and
+    // nesting rewrote it
+    let uri = parts.extensions.get::<OriginalUri>().map(|original| &original.0).unwrap_or(&parts.uri);
+    let path_and_query = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let canonical = canonical_request(parts.method.as_str(), path_and_query, header.timestamp, &header.nonce, &body);
+    if !verify_signature(&secret, &canonical, &header.signature) {
+        return Err(ApiError::Unauthorized);
+    }
+
+    // Only remember nonces of genuine requests, so forged ones can't burn them
+    if !nonce_cache.check_and_insert(&header.key_id, &header.nonce).await {
+        return Err(ApiError::Unauthorized);
+    }
+
+    api_key_service::touch_last_used(&pool, key.id).await?;
+    let claims = claims_for_key(&pool, &key, email).await?;
+
+    Ok(run_as(claims, Request::from_parts(parts, Body::from(body)), next).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What a partner computes for `sig`.
+    fn sign_request(secret: &str, canonical: &str) -> String {
+        hex::encode(request_mac(secret, canonical).finalize().into_bytes())
+    }
+
+    #[test]
+    fn test_signature_roundtrip() {
+        let canonical = canonical_request("post", "/api/transactions/sends", 1_700_000_000, "0123456789abcdef", b"{\"amount\":1}");
+        let header = format!("keyId=516d0642999a,ts=1700000000,nonce=0123456789abcdef,sig={}", sign_request("secret", &canonical));
+        let header = parse_signature_header(&header).unwrap();
+
+        assert_eq!(header.key_id, "516d0642999a");
+        assert!(verify_signature("secret", &canonical, &header.signature));
+        assert!(!verify_signature("other-secret", &canonical, &header.signature));
+
+        let tampered = canonical_request("POST", "/api/transactions/sends", 1_700_000_000, "0123456789abcdef", b"{\"amount\":1000}");
+        assert!(!verify_signature("secret", &tampered, &header.signature));
+
+        assert_eq!(parse_signature_header("keyId=a,ts=1,nonce=short,sig=00"), None);
+    }
+
+    #[tokio::test]
+    async fn test_nonce_replay_is_refused() {
+        let cache = NonceCache::new(60);
+
+        assert!(cache.check_and_insert("key", "0123456789abcdef").await);
+        assert!(!cache.check_and_insert("key", "0123456789abcdef").await);
+        assert!(cache.check_and_insert("other-key", "0123456789abcdef").await);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,177 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderName, HeaderValue, Request, Uri},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use crate::config::app_config::AppConfig;
+use crate::handlers::docs;
+use crate::utils::error::ApiError;
+
+/// Version asked for by clients calling unversioned `/api/...` paths, and
+/// reported on every versioned response.
+pub const API_VERSION_HEADER: HeaderName = HeaderName::from_static("api-version");
+const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET_HEADER: HeaderName = HeaderName::from_static("sunset");
+
+/// Paths under `/api` that aren't part of a version.
+const UNVERSIONED: &[&str] = &[docs::UI_PATH];
+
+/// Major versions of the API, each served under its own prefix. A version
+/// keeps its contract; breaking changes go to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+    /// Served to unversioned requests without `Api-Version`, so existing
+    /// clients keep the contract they were written against.
+    pub const DEFAULT: ApiVersion = ApiVersion::V1;
+    pub const LATEST: ApiVersion = ApiVersion::V2;
+
+    pub fn number(self) -> u8 {
+        match self {
+            ApiVersion::V1 => 1,
+            ApiVersion::V2 => 2,
+        }
+    }
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/api/v1",
+            ApiVersion::V2 => "/api/v2",
+        }
+    }
+
+    /// Reads `2` or `v2`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let number = value.strip_prefix(['v', 'V']).unwrap_or(value);
+        Self::ALL.into_iter().find(|version| number.parse() == Ok(version.number()))
+    }
+
+    /// The version whose prefix starts `path`.
+    pub fn of_path(path: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|version| {
+            path.strip_prefix(version.prefix()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+
+    /// When the version was deprecated and when it stops being served, if
+    /// it has a successor.
+    pub fn retirement(self, config: &AppConfig) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        match self {
+            ApiVersion::V1 => Some((config.api_v1_deprecation, config.api_v1_sunset)),
+            ApiVersion::V2 => None,
+        }
+    }
+}
+
+/// The route as it's known across versions: `/api/v1/cards/:id` is
+/// `/api/cards/:id`.
+pub fn unversioned(path: &str) -> String {
+    match ApiVersion::of_path(path) {
+        Some(version) => format!("/api{}", &path[version.prefix().len()..]),
+        None => path.to_string(),
+    }
+}
+
+/// Whether the part of a path after `/api/` starts with a version segment,
+/// known or not.
+fn names_version(rest: &str) -> bool {
+    let segment = rest.split('/').next().unwrap_or_default();
+    segment.len() > 1 && segment.starts_with('v') && segment[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Sends unversioned `/api/...` requests to the version named by the
+/// `Api-Version` header, or to `ApiVersion::DEFAULT` without one. A version
+/// in the path wins over the header. Must wrap the router, since it changes
+/// the path that gets routed.
+pub async fn negotiate_version(mut req: Request<Body>, next: Next) -> Result<Response, ApiError> {
+    let path = req.uri().path();
+    let rest = match path.strip_prefix("/api/") {
+        Some(rest) if !names_version(rest) && !UNVERSIONED.iter().any(|p| path == *p || path.starts_with(&format!("{}/", p))) => rest,
+        _ => return Ok(next.run(req).await),
+    };
+
+    let version = match req.headers().get(&API_VERSION_HEADER) {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(ApiVersion::parse)
+            .ok_or_else(|| ApiError::BadRequest("unsupported Api-Version, expected 1 or 2".to_string()))?,
+        None => ApiVersion::DEFAULT,
+    };
+
+    let query = req.uri().query().map(|query| format!("?{}", query)).unwrap_or_default();
+    let mut parts = req.uri().clone().into_parts();
+    parts.path_and_query = Some(
+        format!("{}/{}{}", version.prefix(), rest, query)
+            .parse()
+            .map_err(|_| ApiError::BadRequest("invalid path".to_string()))?,
+    );
+    *req.uri_mut() = Uri::from_parts(parts).map_err(|_| ApiError::BadRequest("invalid path".to_string()))?;
+
+    Ok(next.run(req).await)
+}
+
+/// Marks the requests of a version's router with the version, for handlers
+/// that differ between versions, and its responses with `Api-Version`. A
+/// version with a successor also announces its deprecation (RFC 9745), its
+/// sunset (RFC 8594) and where the same route lives in the latest version.
+pub async fn version_middleware(
+    State(version): State<ApiVersion>,
+    Extension(config): Extension<AppConfig>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    req.extensions_mut().insert(version);
+    // Nesting strips the version prefix, leaving the route's own path
+    let successor = format!("<{}{}>; rel=\"successor-version\"", ApiVersion::LATEST.prefix(), req.uri().path());
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert(API_VERSION_HEADER, HeaderValue::from(u16::from(version.number())));
+
+    if let Some((deprecation, sunset)) = version.retirement(&config) {
+        let values = [
+            (DEPRECATION_HEADER, format!("@{}", deprecation.timestamp())),
+            (SUNSET_HEADER, sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string()),
+            (header::LINK, successor),
+        ];
+        for (name, value) in values {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.append(name, value);
+            }
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_from_paths_and_headers() {
+        assert_eq!(ApiVersion::parse("2"), Some(ApiVersion::V2));
+        assert_eq!(ApiVersion::parse("v1"), Some(ApiVersion::V1));
+        assert_eq!(ApiVersion::parse("3"), None);
+        assert_eq!(ApiVersion::parse("1.0"), None);
+
+        assert_eq!(ApiVersion::of_path("/api/v2/cards"), Some(ApiVersion::V2));
+        assert_eq!(ApiVersion::of_path("/api/v20/cards"), None);
+        assert_eq!(unversioned("/api/v1/cards/:id/details"), "/api/cards/:id/details");
+        assert_eq!(unversioned("/health"), "/health");
+
+        assert!(names_version("v3/cards"));
+        assert!(!names_version("vaults"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditLog {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub action: String,
+    pub resource_type: String,
+    pub resource_id: Option<Uuid>,
+    pub details: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Position in the hash chain and the entry's chained hash; null for a
+    /// moment after it's written, until the entry is chained
+    pub sequence: Option<i64>,
+    pub hash: Option<String>,
+}
+
+/// Filters of `GET /api/admin/audit-logs`; all optional and combined with AND.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditLogQuery {
+    pub user_id: Option<Uuid>,
+    pub action: Option<String>,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<Uuid>,
+    pub ip_address: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditLogPage {
+    pub items: Vec<AuditLog>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BrokenLink {
+    pub sequence: i64,
+    /// The entry found at `sequence`, if any
+    pub id: Option<Uuid>,
+    pub reason: String,
+}
+
+/// Outcome of walking the audit chain from its first entry.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChainVerification {
+    pub valid: bool,
+    pub entries_checked: i64,
+    pub head_sequence: i64,
+    pub checkpoints_checked: i64,
+    /// Checkpoints signed with a key that is no longer published
+    pub checkpoints_unverifiable: i64,
+    pub latest_checkpoint: Option<i64>,
+    pub first_broken_link: Option<BrokenLink>,
+}
\ No newline at end of file
@@ -1,8 +1,12 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use sqlx::FromRow;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use validator::Validate;
+use crate::utils::validation::validate_iban;
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Beneficiary {
     pub id: Uuid,
@@ -16,16 +20,21 @@ pub struct Beneficiary {
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct CreateBeneficiaryRequest {
+    #[validate(length(min = 1, max = 255))]
     pub name: String,
+    #[validate(custom = "validate_iban")]
     pub iban: String,
+    #[validate(length(min = 1, max = 255))]
     pub account_number: Option<String>,
+    #[validate(length(min = 1, max = 10))]
     pub sort_code: Option<String>,
+    #[validate(length(min = 1, max = 255))]
     pub bank_name: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct BeneficiaryResponse {
     pub id: Uuid,
     pub user_id: Uuid,
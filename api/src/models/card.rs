@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use sqlx::FromRow;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use validator::Validate;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Card {
@@ -17,15 +19,15 @@ pub struct Card {
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::Type)]
-#[sqlx(type_name = "card_type", rename_all = "lowercase")]
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
 pub enum CardType {
     Virtual,
     Physical,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::Type)]
-#[sqlx(type_name = "card_status", rename_all = "lowercase")]
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
 pub enum CardStatus {
     Active,
     Blocked,
@@ -33,14 +35,15 @@ pub enum CardStatus {
     Cancelled,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct CreateCardRequest {
     pub account_id: Uuid,
     pub card_type: CardType,
+    #[validate(length(min = 1, max = 255))]
     pub friendly_name: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CardResponse {
     pub id: Uuid,
     pub account_id: Uuid,
@@ -53,7 +56,7 @@ pub struct CardResponse {
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CardDetailsResponse {
     pub id: Uuid,
     pub account_id: Uuid,
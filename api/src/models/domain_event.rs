@@ -0,0 +1,52 @@
+use serde::Serialize;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use crate::models::beneficiary::BeneficiaryResponse;
+use crate::models::card::CardResponse;
+use crate::models::transaction::TransactionResponse;
+
+/// Names of the domain events, also the webhook event types.
+pub mod event_types {
+    pub const TRANSACTION_CREATED: &str = "transaction.created";
+    pub const TRANSACTION_STATUS_CHANGED: &str = "transaction.status_changed";
+    pub const CARD_CREATED: &str = "card.created";
+    pub const CARD_STATUS_CHANGED: &str = "card.status_changed";
+    pub const BENEFICIARY_CREATED: &str = "beneficiary.created";
+    pub const BENEFICIARY_DELETED: &str = "beneficiary.deleted";
+}
+
+/// A change to a customer's resources, recorded in the outbox by the service
+/// making it. Serializes to the event's data. Status changes are recorded by
+/// database triggers, as the processors move statuses too.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum DomainEvent<'a> {
+    TransactionCreated(&'a TransactionResponse),
+    CardCreated(&'a CardResponse),
+    BeneficiaryCreated(&'a BeneficiaryResponse),
+    BeneficiaryDeleted { id: Uuid },
+}
+
+impl DomainEvent<'_> {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DomainEvent::TransactionCreated(_) => event_types::TRANSACTION_CREATED,
+            DomainEvent::CardCreated(_) => event_types::CARD_CREATED,
+            DomainEvent::BeneficiaryCreated(_) => event_types::BENEFICIARY_CREATED,
+            DomainEvent::BeneficiaryDeleted { .. } => event_types::BENEFICIARY_DELETED,
+        }
+    }
+}
+
+/// A domain event as stored in the outbox and handed to its handlers. `id`
+/// is the same on every attempt, for handlers to drop duplicates.
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub event_type: String,
+    pub data: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    /// Failed relays so far
+    pub attempts: i32,
+}
\ No newline at end of file
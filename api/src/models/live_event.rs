@@ -0,0 +1,61 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::models::transaction::TransactionResponse;
+
+/// What a security alert is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityAlertKind {
+    /// A session was opened, on this device or another
+    NewSignIn,
+    /// Too many wrong passwords; an unlock link was emailed
+    AccountLocked,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    ApiKeyCreated,
+}
+
+/// An event of the live stream. It is sent as an SSE event named after
+/// `type`, with `data` as JSON.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// New balance of an account
+    BalanceChanged {
+        account_id: Uuid,
+        balance: f64,
+        currency: String,
+    },
+    /// A transaction was made, from the API or by the bank's processors
+    TransactionCreated(TransactionResponse),
+    /// The status of a transaction changed
+    TransactionUpdated(TransactionResponse),
+    /// A card payment was approved or declined by the card processor
+    CardAuthorization {
+        card_id: Uuid,
+        account_id: Uuid,
+        amount: f64,
+        currency: String,
+        merchant: Option<String>,
+        approved: bool,
+    },
+    SecurityAlert {
+        alert: SecurityAlertKind,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    },
+}
+
+impl LiveEvent {
+    /// The SSE event name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::BalanceChanged { .. } => "balance_changed",
+            LiveEvent::TransactionCreated(_) => "transaction_created",
+            LiveEvent::TransactionUpdated(_) => "transaction_updated",
+            LiveEvent::CardAuthorization { .. } => "card_authorization",
+            LiveEvent::SecurityAlert { .. } => "security_alert",
+        }
+    }
+}
\ No newline at end of file
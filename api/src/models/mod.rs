@@ -4,4 +4,13 @@ pub mod account;
 pub mod card;
 pub mod transaction;
 pub mod beneficiary;
-pub mod api_key;
\ No newline at end of file
+pub mod api_key;
+pub mod session;
+pub mod webauthn;
+pub mod role;
+pub mod oauth;
+pub mod audit;
+pub mod pagination;
+pub mod webhook;
+pub mod live_event;
+pub mod domain_event;
\ No newline at end of file
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use validator::Validate;
+
+pub const GRANT_AUTHORIZATION_CODE: &str = "authorization_code";
+pub const GRANT_CLIENT_CREDENTIALS: &str = "client_credentials";
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OAuthClient {
+    pub id: Uuid,
+    pub client_id: String,
+    pub owner_id: Uuid,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub grant_types: Vec<String>,
+    /// Confidential clients authenticate with a secret, public ones (mobile
+    /// and single-page apps) can't keep one
+    pub confidential: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OAuthClient {
+    pub fn allows_grant(&self, grant_type: &str) -> bool {
+        self.grant_types.iter().any(|g| g == grant_type)
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct RegisterClientRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    /// Permissions the client may request, e.g. `accounts:read`
+    #[validate(length(min = 1))]
+    pub scopes: Vec<String>,
+    #[validate(length(min = 1))]
+    pub grant_types: Vec<String>,
+    #[serde(default = "default_confidential")]
+    pub confidential: bool,
+}
+
+fn default_confidential() -> bool {
+    true
+}
+
+/// Returned once at registration; the secret can't be retrieved afterwards.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RegisteredClientResponse {
+    #[serde(flatten)]
+    pub client: OAuthClient,
+    pub client_secret: Option<String>,
+}
+
+/// Query of `GET /api/oauth/authorize`, also the body of the consent `POST`.
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuthorizeRequest {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ConsentRequest {
+    #[serde(flatten)]
+    pub authorization: AuthorizeRequest,
+    pub approve: bool,
+}
+
+/// What the consent screen shows the user.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuthorizationDetails {
+    pub client_id: String,
+    pub client_name: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConsentResponse {
+    /// Where the frontend sends the browser next, with `code` or `error`
+    pub redirect_to: String,
+}
+
+/// Form body of `POST /api/oauth/token` (RFC 6749).
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    pub scope: String,
+}
+
+/// Form body of the introspection (RFC 7662) and revocation (RFC 7009)
+/// endpoints. Only access tokens exist, so `token_type_hint` is ignored.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TokenActionRequest {
+    pub token: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+}
+
+/// Error body defined by RFC 6749 section 5.2.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthErrorResponse {
+    pub error: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+}
\ No newline at end of file
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Query of the listings paginated by cursor.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// `next_cursor` or `prev_cursor` of a page; the first page without one.
+    /// API v1 lists everything and ignores both parameters, but for the
+    /// webhook delivery log.
+    pub cursor: Option<String>,
+    /// Items per page, 1 to 100 (default 50)
+    pub limit: Option<i64>,
+}
+
+/// A page of a listing, newest first. Items created while paginating never
+/// shift the pages that follow.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor of the older items, if there are any
+    pub next_cursor: Option<String>,
+    /// Cursor of the newer items, if there are any
+    pub prev_cursor: Option<String>,
+}
\ No newline at end of file
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+// Default roles for new sign-ups. All seeded roles (customer, corporate_admin,
+// corporate_member, support, compliance, admin) are defined in the rbac migration.
+pub const ROLE_CUSTOMER: &str = "customer";
+pub const ROLE_CORPORATE_ADMIN: &str = "corporate_admin";
+
+/// Permission names checked by routes. They must exist in the `permissions` table.
+pub mod permissions {
+    pub const ACCOUNTS_READ: &str = "accounts:read";
+    pub const ACCOUNTS_WRITE: &str = "accounts:write";
+    pub const CARDS_READ: &str = "cards:read";
+    pub const CARDS_WRITE: &str = "cards:write";
+    pub const TRANSACTIONS_READ: &str = "transactions:read";
+    pub const TRANSACTIONS_WRITE: &str = "transactions:write";
+    pub const BENEFICIARIES_READ: &str = "beneficiaries:read";
+    pub const BENEFICIARIES_WRITE: &str = "beneficiaries:write";
+    pub const ROLES_MANAGE: &str = "roles:manage";
+    pub const AUDIT_READ: &str = "audit:read";
+    pub const WEBHOOKS_MANAGE: &str = "webhooks:manage";
+}
+
+/// Roles held by a user and the union of their permissions, as embedded in access tokens.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserAccess {
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RoleGrant {
+    pub role: String,
+    pub granted_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AssignRoleRequest {
+    pub role: String,
+}
\ No newline at end of file
@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use sqlx::FromRow;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use validator::{Validate, ValidationError, ValidationErrors};
+use crate::utils::pagination::{time_key, Order, MAX_PAGE_SIZE};
+use crate::utils::validation::{validate_amount, validate_currency, validate_iban};
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Transaction {
     pub id: Uuid,
@@ -18,8 +23,8 @@ pub struct Transaction {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::Type)]
-#[sqlx(type_name = "transaction_type", rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
 pub enum TransactionType {
     Send,
     Transfer,
@@ -27,8 +32,8 @@ pub enum TransactionType {
     WireTransfer,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::Type)]
-#[sqlx(type_name = "transaction_status", rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
 pub enum TransactionStatus {
     Pending,
     Processing,
@@ -37,17 +42,22 @@ pub enum TransactionStatus {
     Cancelled,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct SendMoneyRequest {
     pub account_id: Uuid,
+    #[validate(custom = "validate_amount")]
     pub amount: f64,
+    #[validate(custom = "validate_currency")]
     pub currency: String,
+    #[validate(length(max = 500))]
     pub description: Option<String>,
+    #[validate(length(min = 1, max = 255))]
     pub beneficiary_name: String,
+    #[validate(custom = "validate_iban")]
     pub beneficiary_iban: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct TransferRequest {
     pub from_account_id: Uuid,
     pub to_account_id: Uuid,
@@ -56,7 +66,35 @@ pub struct TransferRequest {
     pub description: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// Written out to report a transfer to the source account on `to_account_id`.
+impl Validate for TransferRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        if self.from_account_id == self.to_account_id {
+            let mut error = ValidationError::new("same_account");
+            error.message = Some("must differ from from_account_id".into());
+            errors.add("to_account_id", error);
+        }
+        if let Err(error) = validate_amount(self.amount) {
+            errors.add("amount", error);
+        }
+        if let Err(error) = validate_currency(&self.currency) {
+            errors.add("currency", error);
+        }
+        if self.description.as_ref().is_some_and(|description| description.chars().count() > 500) {
+            errors.add("description", ValidationError::new("length"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct TransactionResponse {
     pub id: Uuid,
     pub account_id: Uuid,
@@ -68,4 +106,120 @@ pub struct TransactionResponse {
     pub beneficiary_iban: Option<String>,
     pub status: TransactionStatus,
     pub created_at: DateTime<Utc>,
+}
+
+/// Order of a transaction listing; ties are broken by id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionSort {
+    #[default]
+    Newest,
+    Oldest,
+    AmountDesc,
+    AmountAsc,
+}
+
+impl TransactionSort {
+    const AMOUNT_DESC: Order = Order { column: "amount", sql_type: "FLOAT8", descending: true };
+    const AMOUNT_ASC: Order = Order { column: "amount", sql_type: "FLOAT8", descending: false };
+
+    pub fn order(self) -> Order {
+        match self {
+            TransactionSort::Newest => Order::NEWEST,
+            TransactionSort::Oldest => Order::OLDEST,
+            TransactionSort::AmountDesc => Self::AMOUNT_DESC,
+            TransactionSort::AmountAsc => Self::AMOUNT_ASC,
+        }
+    }
+
+    /// The cursor key of `transaction` in this order.
+    pub fn key(self, transaction: &TransactionResponse) -> String {
+        match self {
+            TransactionSort::Newest | TransactionSort::Oldest => time_key(transaction.created_at),
+            TransactionSort::AmountDesc | TransactionSort::AmountAsc => transaction.amount.to_string(),
+        }
+    }
+}
+
+/// Filters and page of the transaction listings; filters are optional and
+/// combined with AND.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransactionQuery {
+    /// Created at or after
+    pub from: Option<DateTime<Utc>>,
+    /// Created before
+    pub to: Option<DateTime<Utc>>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub transaction_type: Option<TransactionType>,
+    pub status: Option<TransactionStatus>,
+    pub currency: Option<String>,
+    pub counterparty_iban: Option<String>,
+    /// Part of the counterparty's name, in any case
+    pub counterparty_name: Option<String>,
+    /// Words of the description, with `"quoted phrases"`, `or` and `-excluded` words
+    pub q: Option<String>,
+    #[param(inline)]
+    pub sort: Option<TransactionSort>,
+    /// `next_cursor` or `prev_cursor` of a page made with the same sort (API v2)
+    pub cursor: Option<String>,
+    /// Items per page, 1 to 100 (default 50)
+    pub limit: Option<i64>,
+    /// Items to skip (API v1)
+    pub offset: Option<i64>,
+}
+
+impl Validate for TransactionQuery {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from >= to {
+                let mut error = ValidationError::new("range");
+                error.message = Some("must be after from".into());
+                errors.add("to", error);
+            }
+        }
+        for (field, amount) in [("min_amount", self.min_amount), ("max_amount", self.max_amount)] {
+            if amount.is_some_and(|amount| !amount.is_finite() || amount < 0.0) {
+                errors.add(field, ValidationError::new("range"));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_amount, self.max_amount) {
+            if min > max {
+                let mut error = ValidationError::new("range");
+                error.message = Some("must not be below min_amount".into());
+                errors.add("max_amount", error);
+            }
+        }
+        if let Some(Err(error)) = self.currency.as_deref().map(validate_currency) {
+            errors.add("currency", error);
+        }
+        if let Some(Err(error)) = self.counterparty_iban.as_deref().map(validate_iban) {
+            errors.add("counterparty_iban", error);
+        }
+        if self.counterparty_name.as_ref().is_some_and(|name| name.is_empty() || name.chars().count() > 255) {
+            errors.add("counterparty_name", ValidationError::new("length"));
+        }
+        if self.q.as_ref().is_some_and(|q| q.trim().is_empty() || q.chars().count() > 200) {
+            errors.add("q", ValidationError::new("length"));
+        }
+        if self.limit.is_some_and(|limit| !(1..=MAX_PAGE_SIZE).contains(&limit)) {
+            let mut error = ValidationError::new("range");
+            error.message = Some(format!("must be between 1 and {}", MAX_PAGE_SIZE).into());
+            errors.add("limit", error);
+        }
+        if self.offset.is_some_and(|offset| offset < 0) {
+            let mut error = ValidationError::new("range");
+            error.message = Some("must not be negative".into());
+            errors.add("offset", error);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 }
\ No newline at end of file
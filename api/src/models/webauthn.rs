@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct WebauthnCredential {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub credential_id: String, // base64url
+    pub public_key: Vec<u8>,   // COSE_Key
+    pub sign_count: i64,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebauthnCredentialResponse {
+    pub id: Uuid,
+    pub credential_id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+// Ceremony options, shaped like the PublicKeyCredential*Options dictionaries
+// so clients can pass them to navigator.credentials after base64url decoding.
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialParameter {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub user_verification: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    pub challenge: String,
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameter>,
+    pub timeout: u64,
+    pub attestation: String,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout: u64,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub user_verification: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RegistrationOptionsResponse {
+    pub ceremony_id: Uuid,
+    pub public_key: CreationOptions,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuthenticationOptionsResponse {
+    pub ceremony_id: Uuid,
+    pub public_key: RequestOptions,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AttestationResponse {
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RegisterCredentialRequest {
+    pub ceremony_id: Uuid,
+    pub name: Option<String>,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AuthenticationOptionsRequest {
+    /// Present when the passkey is used as a second factor after a password login
+    pub mfa_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AssertionResponse {
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AuthenticateRequest {
+    pub ceremony_id: Uuid,
+    pub credential_id: String,
+    pub response: AssertionResponse,
+}
+
+/// Proof of a second factor, asked before a passkey is removed: a TOTP or
+/// recovery code, or an assertion answering a step-up ceremony.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeleteCredentialRequest {
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+    pub assertion: Option<AuthenticateRequest>,
+}
\ No newline at end of file
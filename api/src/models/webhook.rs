@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use validator::{Validate, ValidationError};
+use crate::models::domain_event::event_types;
+use crate::models::role::permissions;
+
+/// Every event type subscribers can ask for, with the permission needed to
+/// receive it, the one that reads the same resource through the API.
+pub const EVENT_TYPES: &[(&str, &str)] = &[
+    (event_types::TRANSACTION_CREATED, permissions::TRANSACTIONS_READ),
+    (event_types::TRANSACTION_STATUS_CHANGED, permissions::TRANSACTIONS_READ),
+    (event_types::CARD_CREATED, permissions::CARDS_READ),
+    (event_types::CARD_STATUS_CHANGED, permissions::CARDS_READ),
+    (event_types::BENEFICIARY_CREATED, permissions::BENEFICIARIES_READ),
+    (event_types::BENEFICIARY_DELETED, permissions::BENEFICIARIES_READ),
+];
+
+pub fn event_permission(event_type: &str) -> Option<&'static str> {
+    EVENT_TYPES.iter().find(|(name, _)| *name == event_type).map(|(_, permission)| *permission)
+}
+
+fn validate_event_types(event_types: &[String]) -> Result<(), ValidationError> {
+    if event_types.iter().all(|event_type| event_permission(event_type).is_some()) {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("event_type");
+        error.message = Some(format!(
+            "must be among {}",
+            EVENT_TYPES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+        ).into());
+        Err(error)
+    }
+}
+
+fn validate_webhook_url(url: &str) -> Result<(), ValidationError> {
+    match url::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.host().is_some() && parsed.fragment().is_none() => Ok(()),
+        _ => {
+            let mut error = ValidationError::new("url");
+            error.message = Some("must be an absolute http(s) URL without a fragment".into());
+            Err(error)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct CreateWebhookRequest {
+    /// Receives the events as `POST` requests
+    #[validate(length(max = 2048), custom = "validate_webhook_url")]
+    pub url: String,
+    #[validate(length(min = 1), custom = "validate_event_types")]
+    pub event_types: Vec<String>,
+    #[validate(length(max = 255))]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub url: String,
+    pub description: Option<String>,
+    pub event_types: Vec<String>,
+    /// OAuth client that subscribed, if any
+    pub client_id: Option<String>,
+    /// Failed attempts since the last successful one
+    pub consecutive_failures: i32,
+    /// Set when deliveries kept failing; nothing is sent until re-enabled
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub disabled_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Returned once at creation; the secret can't be retrieved afterwards.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedWebhookSubscription {
+    #[serde(flatten)]
+    pub subscription: WebhookSubscription,
+    /// Key of the HMAC-SHA256 in `Vaelix-Signature`
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Out of attempts
+    Failed,
+}
+
+/// An event sent, or being sent, to a subscription.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    /// HTTP status of the last attempt, if the endpoint answered
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// When the next attempt is due, while pending
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    /// Delivery this one resends
+    pub redelivery_of: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Body of every webhook request. `id` stays the same across retries and
+/// redeliveries, for receivers to drop duplicates.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookEvent {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub created_at: DateTime<Utc>,
+    /// The resource as the API returns it; status changes carry `id`,
+    /// `account_id`, `status` and `previous_status`
+    pub data: serde_json::Value,
+}
\ No newline at end of file
@@ -1,16 +1,19 @@
-use crate::models::account::Account;
 use crate::services::database::DbPool;
 use sqlx::Row;
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct CreateAccountRequest {
+    #[validate(length(min = 1, max = 255))]
     pub profile_id: String,
+    #[validate(length(min = 1, max = 255))]
     pub friendly_name: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AccountResponse {
     pub id: Uuid,
     pub profile_id: String,
@@ -57,8 +60,22 @@ pub async fn get_account_iban(
     Ok(row.and_then(|r| r.try_get("iban").ok()))
 }
 
+pub async fn user_owns_account(
+    pool: &DbPool,
+    account_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT EXISTS(SELECT 1 FROM accounts WHERE id = $1 AND user_id = $2) AS owned")
+        .bind(account_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    row.try_get("owned")
+}
+
 fn generate_mock_iban() -> String {
     // Generate a mock IBAN for testing
     // In production, this would come from Weavr API
-    format!("GB29 NWBK 6016 1331 9268 19")
+    "GB29 NWBK 6016 1331 9268 19".to_string()
 }
\ No newline at end of file
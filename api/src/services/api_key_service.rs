@@ -0,0 +1,241 @@
+use crate::models::api_key::{ApiKey, CreateApiKeyRequest};
+use crate::services::database::DbPool;
+use crate::services::encryption_service::EncryptionService;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::Row;
+use uuid::Uuid;
+
+/// Keys look like `vbk_<prefix>_<secret>`. The prefix is stored in clear to
+/// find the key and the secret is checked against its hash. The secret is
+/// also kept encrypted, as the HMAC key for signed requests.
+pub const KEY_PREFIX: &str = "vbk";
+
+const PREFIX_BYTES: usize = 6;
+const SECRET_BYTES: usize = 32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiKeyError {
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error("secret encryption failed: {0}")]
+    Encryption(String),
+}
+
+/// Skip rewriting `last_used_at` more often than this.
+const LAST_USED_RESOLUTION_SECONDS: i32 = 60;
+
+pub fn generate_key() -> (String, String) {
+    let mut prefix = [0u8; PREFIX_BYTES];
+    let mut secret = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut prefix);
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    (hex::encode(prefix), URL_SAFE_NO_PAD.encode(secret))
+}
+
+pub fn format_key(prefix: &str, secret: &str) -> String {
+    format!("{}_{}_{}", KEY_PREFIX, prefix, secret)
+}
+
+/// Splits a presented key into its lookup prefix and secret.
+pub fn parse_key(key: &str) -> Option<(&str, &str)> {
+    let rest = key.strip_prefix(KEY_PREFIX)?.strip_prefix('_')?;
+    // The prefix is hex, so the first underscore ends it even if the secret contains more
+    let (prefix, secret) = rest.split_once('_')?;
+
+    if prefix.len() != PREFIX_BYTES * 2 || secret.is_empty() {
+        return None;
+    }
+
+    Some((prefix, secret))
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Stores a new key and returns it with the full key string, which is not kept.
+pub async fn create_api_key(
+    pool: &DbPool,
+    encryption: &EncryptionService,
+    user_id: Uuid,
+    request: CreateApiKeyRequest,
+) -> Result<(ApiKey, String), ApiKeyError> {
+    let id = Uuid::new_v4();
+    let (prefix, secret) = generate_key();
+    let expires_at = request.expires_in_days.map(|days| Utc::now() + Duration::days(days));
+    let encrypted_secret = encryption
+        .encrypt(&secret)
+        .map_err(|e| ApiKeyError::Encryption(e.to_string()))?;
+
+    sqlx::query(
+        "INSERT INTO api_keys (id, user_id, key_type, name, prefix, secret_hash, encrypted_secret, scopes, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(&request.key_type)
+    .bind(&request.name)
+    .bind(&prefix)
+    .bind(hash_secret(&secret))
+    .bind(&encrypted_secret)
+    .bind(&request.scopes)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    let key = ApiKey {
+        id,
+        user_id,
+        key_type: request.key_type,
+        name: request.name,
+        prefix: prefix.clone(),
+        scopes: request.scopes,
+        created_at: Utc::now(),
+        last_used_at: None,
+        expires_at,
+        revoked_at: None,
+    };
+
+    Ok((key, format_key(&prefix, &secret)))
+}
+
+pub async fn get_api_keys_by_user(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<Vec<ApiKey>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, user_id, key_type, name, prefix, scopes, created_at, last_used_at, expires_at, revoked_at FROM api_keys WHERE user_id = $1 AND revoked_at IS NULL ORDER BY created_at DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut keys = Vec::new();
+    for row in rows {
+        keys.push(api_key_from_row(&row)?);
+    }
+
+    Ok(keys)
+}
+
+pub async fn revoke_api_key(
+    pool: &DbPool,
+    id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE api_keys SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Resolves a presented key to an active (unrevoked, unexpired) key and the
+/// email of its owner, recording the use.
+pub async fn authenticate(
+    pool: &DbPool,
+    presented: &str,
+) -> Result<Option<(ApiKey, String)>, sqlx::Error> {
+    let (prefix, secret) = match parse_key(presented) {
+        Some(parts) => parts,
+        None => return Ok(None),
+    };
+
+    let row = sqlx::query(
+        "SELECT k.id, k.user_id, k.key_type, k.name, k.prefix, k.scopes, k.created_at, k.last_used_at, k.expires_at, k.revoked_at, u.email FROM api_keys k JOIN users u ON u.id = k.user_id WHERE k.prefix = $1 AND k.secret_hash = $2 AND k.revoked_at IS NULL AND (k.expires_at IS NULL OR k.expires_at > NOW())"
+    )
+    .bind(prefix)
+    .bind(hash_secret(secret))
+    .fetch_optional(pool)
+    .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let key = api_key_from_row(&row)?;
+
+    touch_last_used(pool, key.id).await?;
+
+    Ok(Some((key, row.try_get("email")?)))
+}
+
+/// Resolves the public prefix of an active key to the key, the email of its
+/// owner and its secret, to check a request signature. Keys without a stored
+/// secret can't sign. The use is recorded by `touch_last_used` once the
+/// signature has been checked.
+pub async fn signing_key(
+    pool: &DbPool,
+    encryption: &EncryptionService,
+    prefix: &str,
+) -> Result<Option<(ApiKey, String, String)>, ApiKeyError> {
+    let row = sqlx::query(
+        "SELECT k.id, k.user_id, k.key_type, k.name, k.prefix, k.scopes, k.created_at, k.last_used_at, k.expires_at, k.revoked_at, k.encrypted_secret, u.email FROM api_keys k JOIN users u ON u.id = k.user_id WHERE k.prefix = $1 AND k.encrypted_secret IS NOT NULL AND k.revoked_at IS NULL AND (k.expires_at IS NULL OR k.expires_at > NOW())"
+    )
+    .bind(prefix)
+    .fetch_optional(pool)
+    .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let encrypted_secret: String = row.try_get("encrypted_secret")?;
+    let secret = encryption
+        .decrypt(&encrypted_secret)
+        .map_err(|e| ApiKeyError::Encryption(e.to_string()))?;
+
+    Ok(Some((api_key_from_row(&row)?, row.try_get("email")?, secret)))
+}
+
+pub async fn touch_last_used(
+    pool: &DbPool,
+    id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE api_keys SET last_used_at = NOW() WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - make_interval(secs => $2))"
+    )
+    .bind(id)
+    .bind(LAST_USED_RESOLUTION_SECONDS)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+fn api_key_from_row(row: &sqlx::postgres::PgRow) -> Result<ApiKey, sqlx::Error> {
+    Ok(ApiKey {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        key_type: row.try_get("key_type")?,
+        name: row.try_get("name")?,
+        prefix: row.try_get("prefix")?,
+        scopes: row.try_get("scopes")?,
+        created_at: row.try_get("created_at")?,
+        last_used_at: row.try_get("last_used_at")?,
+        expires_at: row.try_get("expires_at")?,
+        revoked_at: row.try_get("revoked_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_roundtrip() {
+        let (prefix, secret) = generate_key();
+        let key = format_key(&prefix, &secret);
+
+        assert_eq!(parse_key(&key), Some((prefix.as_str(), secret.as_str())));
+        assert_eq!(parse_key("vbk_short_secret"), None);
+        assert_eq!(parse_key(&key.replacen(KEY_PREFIX, "xyz", 1)), None);
+        assert_eq!(parse_key(&format!("{}_{}_", KEY_PREFIX, prefix)), None);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,482 @@
+use crate::models::audit::{AuditLog, AuditLogPage, AuditLogQuery, BrokenLink, ChainVerification};
+use crate::services::database::DbPool;
+use crate::utils::jwt::{self, JwtKeys};
+use sha2::{Digest, Sha256};
+use sqlx::{Postgres, QueryBuilder, Row};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const RESOURCE_USER: &str = "user";
+pub const RESOURCE_API_KEY: &str = "api_key";
+pub const RESOURCE_OAUTH_CLIENT: &str = "oauth_client";
+pub const RESOURCE_SESSION: &str = "session";
+pub const RESOURCE_ACCOUNT: &str = "account";
+pub const RESOURCE_CARD: &str = "card";
+pub const RESOURCE_TRANSACTION: &str = "transaction";
+pub const RESOURCE_BENEFICIARY: &str = "beneficiary";
+pub const RESOURCE_WEBHOOK: &str = "webhook";
+
+/// `prev_hash` of the first entry of the chain.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Entries read per query while verifying the chain.
+const VERIFY_BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error("checkpoint signing failed: {0}")]
+    Signing(#[from] jsonwebtoken::errors::Error),
+}
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// Writes an entry to `audit_logs`, to be chained by `chain_pending`. Takes
+/// any executor so the entry can be written in the same transaction as the
+/// change it records; it shares no lock with other entries, so audited writes
+/// don't wait on each other.
+#[allow(clippy::too_many_arguments)]
+pub async fn record<'e, E>(
+    executor: E,
+    user_id: Option<Uuid>,
+    action: &str,
+    resource_type: &str,
+    resource_id: Option<Uuid>,
+    details: serde_json::Value,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    // The column is INET; forwarded headers can carry anything, so drop what doesn't parse
+    let ip_address = ip_address
+        .and_then(|ip| ip.parse::<IpAddr>().ok())
+        .map(|ip| ip.to_string());
+
+    sqlx::query(
+        "INSERT INTO audit_logs (id, user_id, action, resource_type, resource_id, details, ip_address, user_agent, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7::inet, $8, NOW())"
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(action)
+    .bind(resource_type)
+    .bind(resource_id)
+    .bind(details)
+    .bind(ip_address)
+    .bind(user_agent)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Links up to `batch_size` committed entries that aren't chained yet to the
+/// end of the chain, oldest first, and returns how many it chained.
+pub async fn chain_pending(pool: &DbPool, batch_size: i64) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    // The head's lock keeps the appenders of other instances off these entries
+    sqlx::query("SELECT sequence FROM audit_chain_head WHERE id FOR UPDATE")
+        .execute(&mut *tx)
+        .await?;
+
+    let ids: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM audit_logs WHERE sequence IS NULL ORDER BY created_at, id LIMIT $1"
+    )
+    .bind(batch_size)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // The head's old values give the link
+    for id in &ids {
+        sqlx::query(
+            r#"
+            WITH head AS (
+                UPDATE audit_chain_head h SET
+                    sequence = h.sequence + 1,
+                    prev_hash = h.hash,
+                    hash = audit_entry_hash(h.hash, h.sequence + 1, e.id, e.user_id, e.action, e.resource_type,
+                        e.resource_id, e.details, e.ip_address, e.user_agent, e.created_at)
+                FROM audit_logs e
+                WHERE h.id AND e.id = $1
+                RETURNING h.sequence, h.prev_hash, h.hash
+            )
+            UPDATE audit_logs SET sequence = head.sequence, prev_hash = head.prev_hash, hash = head.hash
+            FROM head WHERE audit_logs.id = $1
+            "#
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(ids.len())
+}
+
+/// Background loop chaining the entries written since its last pass every
+/// `interval`.
+pub fn spawn_append_worker(pool: DbPool, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = chain_pending(&pool, 1000).await {
+                tracing::warn!("audit chain append failed: {}", e);
+            }
+        }
+    });
+}
+
+/// The row `id` of `table` as JSON, for the before and after state of an
+/// audited change; None once it's gone. `table` is interpolated, so only
+/// pass names from the code.
+pub async fn snapshot(pool: &DbPool, table: &str, id: Uuid) -> Result<Option<serde_json::Value>, sqlx::Error> {
+    sqlx::query_scalar(&format!("SELECT to_jsonb(t) FROM {} t WHERE id = $1", table))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Appends the filters of `query` as a WHERE clause.
+fn push_filters<'a>(builder: &mut QueryBuilder<'a, Postgres>, query: &'a AuditLogQuery) {
+    builder.push(" WHERE TRUE");
+
+    if let Some(user_id) = query.user_id {
+        builder.push(" AND user_id = ").push_bind(user_id);
+    }
+    if let Some(action) = &query.action {
+        builder.push(" AND action = ").push_bind(action);
+    }
+    if let Some(resource_type) = &query.resource_type {
+        builder.push(" AND resource_type = ").push_bind(resource_type);
+    }
+    if let Some(resource_id) = query.resource_id {
+        builder.push(" AND resource_id = ").push_bind(resource_id);
+    }
+    if let Some(ip_address) = &query.ip_address {
+        builder.push(" AND ip_address = ").push_bind(ip_address).push("::inet");
+    }
+    if let Some(from) = query.from {
+        builder.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        builder.push(" AND created_at < ").push_bind(to);
+    }
+}
+
+/// Entries matching `query`, newest first, with the total count of matches.
+pub async fn search(
+    pool: &DbPool,
+    query: &AuditLogQuery,
+) -> Result<AuditLogPage, sqlx::Error> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM audit_logs");
+    push_filters(&mut count, query);
+    let total: i64 = count.build_query_scalar().fetch_one(pool).await?;
+
+    let mut select = QueryBuilder::new(
+        "SELECT id, user_id, action, resource_type, resource_id, details, host(ip_address) AS ip_address, user_agent, created_at, sequence, hash FROM audit_logs"
+    );
+    push_filters(&mut select, query);
+    select
+        .push(" ORDER BY sequence DESC NULLS FIRST, created_at DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    let rows = select.build().fetch_all(pool).await?;
+
+    let mut items = Vec::new();
+    for row in rows {
+        items.push(AuditLog {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            action: row.try_get("action")?,
+            resource_type: row.try_get("resource_type")?,
+            resource_id: row.try_get("resource_id")?,
+            details: row.try_get("details")?,
+            ip_address: row.try_get("ip_address")?,
+            user_agent: row.try_get("user_agent")?,
+            created_at: row.try_get("created_at")?,
+            sequence: row.try_get("sequence")?,
+            hash: row.try_get("hash")?,
+        });
+    }
+
+    Ok(AuditLogPage { items, total, limit, offset })
+}
+
+/// The chained hash of an entry from its canonical form, computed here rather
+/// than trusting the database function that wrote it.
+pub fn entry_hash(prev_hash: &str, canonical: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash.as_bytes());
+    hasher.update(canonical.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+fn earliest(found: Option<BrokenLink>, candidate: BrokenLink) -> Option<BrokenLink> {
+    match found {
+        Some(found) if found.sequence <= candidate.sequence => Some(found),
+        _ => Some(candidate),
+    }
+}
+
+fn missing(from: i64, to: i64) -> String {
+    if from == to {
+        format!("entry {} is missing", from)
+    } else {
+        format!("entries {} to {} are missing", from, to)
+    }
+}
+
+/// Walks the chain from its first entry and reports the first broken link:
+/// an entry whose contents or link don't match its hash, a gap in the
+/// sequence, a checkpoint that isn't validly signed or doesn't match the
+/// entry it covers, or entries missing at the end. Entries the appender
+/// hasn't chained yet aren't covered.
+pub async fn verify_chain(
+    pool: &DbPool,
+    keys: &JwtKeys,
+) -> Result<ChainVerification, sqlx::Error> {
+    // One snapshot for the whole walk, so entries appended meanwhile don't
+    // look like a mismatch with the head
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+
+    let mut broken = None;
+
+    let rows = sqlx::query("SELECT sequence, hash, signature FROM audit_checkpoints ORDER BY sequence")
+        .fetch_all(&mut *tx)
+        .await?;
+
+    // Only checkpoints with a valid signature are trusted below
+    let mut checkpoints = BTreeMap::new();
+    let mut checkpoints_unverifiable = 0;
+    for row in rows {
+        let sequence: i64 = row.try_get("sequence")?;
+        let hash: String = row.try_get("hash")?;
+        let signature: String = row.try_get("signature")?;
+
+        match jwt::verify_audit_checkpoint(&signature, keys) {
+            Ok(Some(claims)) if claims.seq == sequence && claims.hash == hash => {
+                checkpoints.insert(sequence, hash);
+            }
+            // Signed with a retired key whose public part was removed
+            Ok(None) => checkpoints_unverifiable += 1,
+            _ => {
+                broken = earliest(broken, BrokenLink {
+                    sequence,
+                    id: None,
+                    reason: "checkpoint signature is invalid".to_string(),
+                });
+            }
+        }
+    }
+    let checkpoints_checked = checkpoints.len() as i64;
+    let latest_checkpoint = checkpoints.keys().next_back().copied();
+
+    let head = sqlx::query("SELECT sequence, hash FROM audit_chain_head")
+        .fetch_one(&mut *tx)
+        .await?;
+    let head_sequence: i64 = head.try_get("sequence")?;
+    let head_hash: String = head.try_get("hash")?;
+
+    let mut entries_checked = 0;
+    let mut last_sequence = 0;
+    let mut last_hash = GENESIS_HASH.to_string();
+
+    'walk: loop {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, sequence, prev_hash, hash,
+                audit_entry_canonical(sequence, id, user_id, action, resource_type, resource_id, details, ip_address, user_agent, created_at) AS canonical
+            FROM audit_logs WHERE sequence > $1 ORDER BY sequence LIMIT $2
+            "#
+        )
+        .bind(last_sequence)
+        .bind(VERIFY_BATCH_SIZE)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        for row in rows {
+            let id: Uuid = row.try_get("id")?;
+            let sequence: i64 = row.try_get("sequence")?;
+            let prev_hash: String = row.try_get("prev_hash")?;
+            let hash: String = row.try_get("hash")?;
+            let canonical: String = row.try_get("canonical")?;
+
+            let reason = if sequence != last_sequence + 1 {
+                Some(missing(last_sequence + 1, sequence - 1))
+            } else if prev_hash != last_hash {
+                Some("previous hash doesn't match the previous entry".to_string())
+            } else if entry_hash(&prev_hash, &canonical) != hash {
+                Some("contents don't match the entry's hash".to_string())
+            } else if checkpoints.get(&sequence).is_some_and(|checkpoint| *checkpoint != hash) {
+                Some("hash doesn't match the signed checkpoint".to_string())
+            } else {
+                None
+            };
+
+            if let Some(reason) = reason {
+                let sequence = sequence.min(last_sequence + 1);
+                broken = earliest(broken, BrokenLink { sequence, id: Some(id), reason });
+                break 'walk;
+            }
+
+            entries_checked += 1;
+            last_sequence = sequence;
+            last_hash = hash;
+        }
+    }
+
+    tx.commit().await?;
+
+    // Entries removed from the end leave no gap, but the head or a later
+    // checkpoint still remembers them
+    if broken.is_none() {
+        let expected = head_sequence.max(latest_checkpoint.unwrap_or(0));
+        if last_sequence < expected {
+            broken = Some(BrokenLink {
+                sequence: last_sequence + 1,
+                id: None,
+                reason: missing(last_sequence + 1, expected),
+            });
+        } else if last_sequence != head_sequence || last_hash != head_hash {
+            broken = Some(BrokenLink {
+                sequence: last_sequence,
+                id: None,
+                reason: "chain head doesn't match the last entry".to_string(),
+            });
+        }
+    }
+
+    Ok(ChainVerification {
+        valid: broken.is_none(),
+        entries_checked,
+        head_sequence,
+        checkpoints_checked,
+        checkpoints_unverifiable,
+        latest_checkpoint,
+        first_broken_link: broken,
+    })
+}
+
+/// Signs the current end of the chain, unless nothing was appended since the
+/// last checkpoint. Returns the sequence checkpointed.
+pub async fn create_checkpoint(
+    pool: &DbPool,
+    keys: &JwtKeys,
+) -> Result<Option<i64>, AuditError> {
+    let row = sqlx::query(
+        "SELECT sequence, hash FROM audit_chain_head WHERE sequence > COALESCE((SELECT MAX(sequence) FROM audit_checkpoints), 0)"
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let sequence: i64 = row.try_get("sequence")?;
+    let hash: String = row.try_get("hash")?;
+
+    let signature = jwt::sign_audit_checkpoint(sequence, &hash, keys)?;
+
+    sqlx::query("INSERT INTO audit_checkpoints (sequence, hash, signature) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING")
+        .bind(sequence)
+        .bind(&hash)
+        .bind(&signature)
+        .execute(pool)
+        .await?;
+
+    Ok(Some(sequence))
+}
+
+/// Background loop checkpointing the chain every `interval`.
+pub fn spawn_checkpoint_worker(pool: DbPool, keys: JwtKeys, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = create_checkpoint(&pool, &keys).await {
+                tracing::warn!("audit checkpoint failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_pool;
+    use serde_json::json;
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_recorded_entries_extend_a_valid_chain() {
+        let pool = test_pool().await;
+        let keys = JwtKeys::ephemeral("vaelix-bank", "vaelix-api");
+
+        let resource_id = Uuid::new_v4();
+        record(&pool, None, "chain_test", RESOURCE_USER, Some(resource_id), json!({ "amount": 12.5 }), Some("10.0.0.1"), None)
+            .await
+            .unwrap();
+        while chain_pending(&pool, 1000).await.unwrap() > 0 {}
+
+        // The hash written by the database matches the one computed here
+        let row = sqlx::query(
+            "SELECT prev_hash, hash, audit_entry_canonical(sequence, id, user_id, action, resource_type, resource_id, details, ip_address, user_agent, created_at) AS canonical FROM audit_logs WHERE resource_id = $1"
+        )
+        .bind(resource_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let prev_hash: String = row.get("prev_hash");
+        let canonical: String = row.get("canonical");
+        assert_eq!(entry_hash(&prev_hash, &canonical), row.get::<String, _>("hash"));
+
+        let verification = verify_chain(&pool, &keys).await.unwrap();
+        assert!(verification.valid, "{:?}", verification.first_broken_link);
+        assert!(verification.entries_checked >= 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_open_transactions_dont_hold_back_other_entries() {
+        let pool = test_pool().await;
+
+        let mut tx = pool.begin().await.unwrap();
+        record(&mut *tx, None, "chain_test", RESOURCE_USER, None, json!({}), None, None)
+            .await
+            .unwrap();
+
+        let other = record(&pool, None, "chain_test", RESOURCE_USER, None, json!({}), None, None);
+        tokio::time::timeout(Duration::from_secs(5), other)
+            .await
+            .expect("the entry waited for the open transaction")
+            .unwrap();
+
+        // The appender doesn't wait for it either, and skips it until committed
+        tokio::time::timeout(Duration::from_secs(5), chain_pending(&pool, 1000))
+            .await
+            .expect("the appender waited for the open transaction")
+            .unwrap();
+        tx.rollback().await.unwrap();
+    }
+}
\ No newline at end of file
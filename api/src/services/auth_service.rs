@@ -1,52 +1,222 @@
-use bcrypt::{hash, verify, DEFAULT_COST};
-use crate::utils::jwt;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use argon2::password_hash::SaltString;
+use crate::utils::jwt::{self, JwtKeys};
+use crate::models::role::UserAccess;
 use crate::models::user::User;
 use crate::services::database::DbPool;
 use sqlx::Row;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use tokio::sync::OnceCell;
 
-pub async fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
-    hash(password, DEFAULT_COST)
+pub enum AuthOutcome {
+    Authenticated(User),
+    /// Wrong email or password; `user_id` is set when the email exists
+    InvalidCredentials { user_id: Option<Uuid> },
+    /// Right password, but the account is locked
+    Locked { until: DateTime<Utc> },
 }
 
-pub async fn verify_password(password: &str, hash: &str) -> Result<bool, bcrypt::BcryptError> {
-    verify(password, hash)
+#[derive(Debug, thiserror::Error)]
+pub enum PasswordError {
+    #[error("password hashing failed: {0}")]
+    Hashing(String),
+    #[error(transparent)]
+    Task(#[from] tokio::task::JoinError),
+}
+
+fn argon2(params: &Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+}
+
+/// Hashes with Argon2id into a PHC string (`$argon2id$v=19$m=...`), which
+/// records the parameters next to the salt. Runs on the blocking pool: a hash
+/// takes tens of milliseconds of CPU by design.
+pub async fn hash_password(password: &str, params: &Params) -> Result<String, PasswordError> {
+    let password = password.to_owned();
+    let params = params.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        argon2(&params)
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| PasswordError::Hashing(e.to_string()))
+    })
+    .await?
+}
+
+/// Checks a password against an Argon2 PHC string or a legacy bcrypt hash.
+pub async fn verify_password(password: &str, hash: &str) -> Result<bool, PasswordError> {
+    let password = password.to_owned();
+    let hash = hash.to_owned();
+
+    tokio::task::spawn_blocking(move || {
+        if hash.starts_with("$argon2") {
+            let parsed = PasswordHash::new(&hash).map_err(|e| PasswordError::Hashing(e.to_string()))?;
+            // The parameters come from the hash itself, not the configuration
+            Ok(Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+        } else {
+            bcrypt::verify(&password, &hash).map_err(|e| PasswordError::Hashing(e.to_string()))
+        }
+    })
+    .await?
+}
+
+/// Whether a stored hash should be replaced: bcrypt hashes, and Argon2 hashes
+/// made with other parameters than the configured ones.
+pub fn needs_rehash(hash: &str, params: &Params) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => {
+            parsed.algorithm != Algorithm::Argon2id.ident()
+                || Params::try_from(&parsed)
+                    .map(|p| (p.m_cost(), p.t_cost(), p.p_cost()) != (params.m_cost(), params.t_cost(), params.p_cost()))
+                    .unwrap_or(true)
+        }
+        Err(_) => true,
+    }
+}
+
+/// Hash checked against when the email is unknown, so those attempts cost
+/// the same work as real ones and can't be told apart by timing.
+async fn dummy_hash(params: &Params) -> Result<&'static str, PasswordError> {
+    static DUMMY_HASH: OnceCell<String> = OnceCell::const_new();
+    DUMMY_HASH
+        .get_or_try_init(|| async move { hash_password(&Uuid::new_v4().to_string(), params).await })
+        .await
+        .map(String::as_str)
 }
 
 pub async fn authenticate_user(
     pool: &DbPool,
     email: &str,
     password: &str,
-) -> Result<Option<User>, sqlx::Error> {
+    params: &Params,
+) -> Result<AuthOutcome, sqlx::Error> {
     let row = sqlx::query(
-        "SELECT id, email, name, user_type, hashed_password, created_at, updated_at FROM users WHERE email = $1"
+        "SELECT id, email, name, user_type, hashed_password, locked_until, created_at, updated_at FROM users WHERE email = $1"
     )
     .bind(email)
     .fetch_optional(pool)
     .await?;
 
-    if let Some(row) = row {
-        let hashed_password: String = row.try_get("hashed_password")?;
-        if verify_password(password, &hashed_password).await.unwrap_or(false) {
-            let user = User {
-                id: row.try_get("id")?,
-                email: row.try_get("email")?,
-                name: row.try_get("name")?,
-                user_type: row.try_get("user_type")?,
-                created_at: row.try_get("created_at")?,
-                updated_at: row.try_get("updated_at")?,
-            };
-            Ok(Some(user))
-        } else {
-            Ok(None)
+    let row = match row {
+        Some(row) => row,
+        None => {
+            if let Ok(dummy) = dummy_hash(params).await {
+                let _ = verify_password(password, dummy).await;
+            }
+            return Ok(AuthOutcome::InvalidCredentials { user_id: None });
         }
-    } else {
-        Ok(None)
+    };
+
+    // The password is checked first, so a locked account takes as long as
+    // any other and is only reported to whoever knows the password
+    let hashed_password: String = row.try_get("hashed_password")?;
+    if !verify_password(password, &hashed_password).await.unwrap_or(false) {
+        return Ok(AuthOutcome::InvalidCredentials { user_id: Some(row.try_get("id")?) });
     }
+
+    let locked_until: Option<DateTime<Utc>> = row.try_get("locked_until")?;
+    if let Some(until) = locked_until.filter(|until| *until > Utc::now()) {
+        return Ok(AuthOutcome::Locked { until });
+    }
+
+    let user = User {
+        id: row.try_get("id")?,
+        email: row.try_get("email")?,
+        name: row.try_get("name")?,
+        user_type: row.try_get("user_type")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    };
+
+    // Upgrade legacy hashes now that we have the plain password. A failure
+    // here must not fail the login; the next one retries.
+    if needs_rehash(&hashed_password, params) {
+        if let Err(e) = rehash_password(pool, user.id, password, &hashed_password, params).await {
+            tracing::warn!("failed to rehash password for user {}: {}", user.id, e);
+        }
+    }
+
+    Ok(AuthOutcome::Authenticated(user))
+}
+
+async fn rehash_password(
+    pool: &DbPool,
+    user_id: Uuid,
+    password: &str,
+    old_hash: &str,
+    params: &Params,
+) -> Result<(), anyhow::Error> {
+    let new_hash = hash_password(password, params).await?;
+
+    // Skip if the password changed meanwhile, e.g. a concurrent reset
+    sqlx::query("UPDATE users SET hashed_password = $3 WHERE id = $1 AND hashed_password = $2")
+        .bind(user_id)
+        .bind(old_hash)
+        .bind(&new_hash)
+        .execute(pool)
+        .await?;
+
+    Ok(())
 }
 
-pub fn generate_tokens(user: &User, jwt_secret: &str, refresh_secret: &str) -> Result<(String, String), jsonwebtoken::errors::Error> {
-    let access_token = jwt::create_access_token(&user.id.to_string(), &user.email, jwt_secret)?;
-    let refresh_token = jwt::create_refresh_token(&user.id.to_string(), &user.email, refresh_secret)?;
+pub fn generate_tokens(user: &User, session_id: Uuid, access: &UserAccess, keys: &JwtKeys) -> Result<(String, String), jsonwebtoken::errors::Error> {
+    let session_id = session_id.to_string();
+    let access_token = jwt::create_access_token(&user.id.to_string(), &user.email, &session_id, access, keys)?;
+    let refresh_token = jwt::create_refresh_token(&user.id.to_string(), &user.email, &session_id, keys)?;
 
     Ok((access_token, refresh_token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::lockout_service;
+    use crate::test_support::{create_user, test_pool};
+
+    #[tokio::test]
+    async fn test_argon2_and_legacy_bcrypt() {
+        let params = Params::new(8 * 1024, 1, 1, None).unwrap();
+
+        let hash = hash_password("correct horse", &params).await.unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=8192,t=1,p=1$"));
+        assert!(verify_password("correct horse", &hash).await.unwrap());
+        assert!(!verify_password("wrong horse", &hash).await.unwrap());
+        assert!(!needs_rehash(&hash, &params));
+        assert!(needs_rehash(&hash, &Params::new(16 * 1024, 1, 1, None).unwrap()));
+
+        let legacy = bcrypt::hash("correct horse", 4).unwrap();
+        assert!(verify_password("correct horse", &legacy).await.unwrap());
+        assert!(needs_rehash(&legacy, &params));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_locked_accounts_only_answer_the_right_password() {
+        let pool = test_pool().await;
+        let params = Params::default();
+        let user = create_user(&pool, "Locked").await;
+        sqlx::query("UPDATE users SET locked_until = NOW() + INTERVAL '10 minutes', failed_login_attempts = 0 WHERE id = $1")
+            .bind(user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let wrong = authenticate_user(&pool, &user.email, "wrong", &params).await.unwrap();
+        assert!(matches!(wrong, AuthOutcome::InvalidCredentials { user_id: Some(id) } if id == user.id));
+        let right = authenticate_user(&pool, &user.email, "defaultpassword123", &params).await.unwrap();
+        assert!(matches!(right, AuthOutcome::Locked { .. }));
+
+        // Failures while locked neither count nor lock again
+        let locked = lockout_service::record_failure(&pool, user.id, None, None, "secret", "http://localhost").await.unwrap();
+        assert!(locked.is_none());
+        let attempts: i32 = sqlx::query_scalar("SELECT failed_login_attempts FROM users WHERE id = $1")
+            .bind(user.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(attempts, 0);
+    }
 }
\ No newline at end of file
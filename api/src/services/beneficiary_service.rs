@@ -1,6 +1,10 @@
-use crate::models::beneficiary::{Beneficiary, CreateBeneficiaryRequest, BeneficiaryResponse};
+use crate::models::beneficiary::{CreateBeneficiaryRequest, BeneficiaryResponse};
+use crate::models::domain_event::DomainEvent;
+use crate::models::pagination::Page;
 use crate::services::database::DbPool;
-use sqlx::Row;
+use crate::services::outbox_service;
+use crate::utils::pagination::{into_page, push_keyset, time_key, Cursor, Order};
+use sqlx::{QueryBuilder, Row};
 use uuid::Uuid;
 
 pub async fn create_beneficiary(
@@ -10,6 +14,7 @@ pub async fn create_beneficiary(
 ) -> Result<BeneficiaryResponse, sqlx::Error> {
     let beneficiary_id = Uuid::new_v4();
 
+    let mut tx = pool.begin().await?;
     sqlx::query(
         "INSERT INTO beneficiaries (id, user_id, name, iban, account_number, sort_code, bank_name, verified) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
     )
@@ -21,10 +26,10 @@ pub async fn create_beneficiary(
     .bind(&request.sort_code)
     .bind(&request.bank_name)
     .bind(false) // Not verified by default
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
 
-    Ok(BeneficiaryResponse {
+    let beneficiary = BeneficiaryResponse {
         id: beneficiary_id,
         user_id,
         name: request.name,
@@ -34,9 +39,14 @@ pub async fn create_beneficiary(
         bank_name: request.bank_name,
         verified: false,
         created_at: chrono::Utc::now(),
-    })
+    };
+    outbox_service::record(&mut *tx, user_id, &DomainEvent::BeneficiaryCreated(&beneficiary)).await?;
+    tx.commit().await?;
+
+    Ok(beneficiary)
 }
 
+/// All the user's beneficiaries, the listing of API v1.
 pub async fn get_beneficiaries_by_user(
     pool: &DbPool,
     user_id: Uuid,
@@ -48,22 +58,25 @@ pub async fn get_beneficiaries_by_user(
     .fetch_all(pool)
     .await?;
 
-    let mut beneficiaries = Vec::new();
-    for row in rows {
-        beneficiaries.push(BeneficiaryResponse {
-            id: row.try_get("id")?,
-            user_id: row.try_get("user_id")?,
-            name: row.try_get("name")?,
-            iban: row.try_get("iban")?,
-            account_number: row.try_get("account_number")?,
-            sort_code: row.try_get("sort_code")?,
-            bank_name: row.try_get("bank_name")?,
-            verified: row.try_get("verified")?,
-            created_at: row.try_get("created_at")?,
-        });
-    }
+    rows.iter().map(beneficiary_from_row).collect()
+}
+
+/// The page of the user's beneficiaries at `cursor`, newest first.
+pub async fn list_beneficiaries(
+    pool: &DbPool,
+    user_id: Uuid,
+    cursor: Option<&Cursor>,
+    limit: i64,
+) -> Result<Page<BeneficiaryResponse>, sqlx::Error> {
+    let mut select = QueryBuilder::new(
+        "SELECT id, user_id, name, iban, account_number, sort_code, bank_name, verified, created_at FROM beneficiaries WHERE user_id = "
+    );
+    select.push_bind(user_id);
+    push_keyset(&mut select, Order::NEWEST, cursor, limit);
 
-    Ok(beneficiaries)
+    let rows = select.build().fetch_all(pool).await?;
+    let beneficiaries = rows.iter().map(beneficiary_from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok(into_page(beneficiaries, Order::NEWEST, cursor, limit, |beneficiary| (time_key(beneficiary.created_at), beneficiary.id)))
 }
 
 pub async fn get_beneficiary(
@@ -79,21 +92,7 @@ pub async fn get_beneficiary(
     .fetch_optional(pool)
     .await?;
 
-    if let Some(row) = row {
-        Ok(Some(BeneficiaryResponse {
-            id: row.try_get("id")?,
-            user_id: row.try_get("user_id")?,
-            name: row.try_get("name")?,
-            iban: row.try_get("iban")?,
-            account_number: row.try_get("account_number")?,
-            sort_code: row.try_get("sort_code")?,
-            bank_name: row.try_get("bank_name")?,
-            verified: row.try_get("verified")?,
-            created_at: row.try_get("created_at")?,
-        }))
-    } else {
-        Ok(None)
-    }
+    row.as_ref().map(beneficiary_from_row).transpose()
 }
 
 pub async fn delete_beneficiary(
@@ -101,13 +100,35 @@ pub async fn delete_beneficiary(
     beneficiary_id: Uuid,
     user_id: Uuid,
 ) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
     let result = sqlx::query(
         "DELETE FROM beneficiaries WHERE id = $1 AND user_id = $2"
     )
     .bind(beneficiary_id)
     .bind(user_id)
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
 
-    Ok(result.rows_affected() > 0)
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    outbox_service::record(&mut *tx, user_id, &DomainEvent::BeneficiaryDeleted { id: beneficiary_id }).await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+fn beneficiary_from_row(row: &sqlx::postgres::PgRow) -> Result<BeneficiaryResponse, sqlx::Error> {
+    Ok(BeneficiaryResponse {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        name: row.try_get("name")?,
+        iban: row.try_get("iban")?,
+        account_number: row.try_get("account_number")?,
+        sort_code: row.try_get("sort_code")?,
+        bank_name: row.try_get("bank_name")?,
+        verified: row.try_get("verified")?,
+        created_at: row.try_get("created_at")?,
+    })
 }
\ No newline at end of file
@@ -1,12 +1,17 @@
-use crate::models::card::{Card, CreateCardRequest, CardResponse, CardDetailsResponse, CardType, CardStatus};
+use crate::models::card::{Card, CreateCardRequest, CardResponse, CardDetailsResponse, CardStatus};
+use crate::models::domain_event::DomainEvent;
+use crate::models::pagination::Page;
 use crate::services::database::DbPool;
-use crate::services::encryption_service::EncryptionService;
-use sqlx::Row;
+use crate::services::outbox_service;
+use crate::utils::pagination::{into_page, push_keyset, time_key, Cursor, Order};
+use sqlx::{QueryBuilder, Row};
 use uuid::Uuid;
 use chrono::{Utc, Datelike};
 
+/// Issues a card on an account of `user_id`, with its domain event.
 pub async fn create_card(
     pool: &DbPool,
+    user_id: Uuid,
     request: CreateCardRequest,
 ) -> Result<CardResponse, sqlx::Error> {
     let card_id = Uuid::new_v4();
@@ -18,6 +23,7 @@ pub async fn create_card(
     let card_number_encrypted = Some(card_number.clone());
     let cvv_encrypted = Some(cvv.clone());
 
+    let mut tx = pool.begin().await?;
     sqlx::query(
         "INSERT INTO cards (id, account_id, card_type, friendly_name, card_number_encrypted, expiry_month, expiry_year, cvv_encrypted, status) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
     )
@@ -30,10 +36,10 @@ pub async fn create_card(
     .bind(expiry_year)
     .bind(&cvv_encrypted)
     .bind("active")
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
 
-    Ok(CardResponse {
+    let card = CardResponse {
         id: card_id,
         account_id: request.account_id,
         card_type: request.card_type,
@@ -43,7 +49,11 @@ pub async fn create_card(
         expiry_year,
         status: CardStatus::Active,
         created_at: Utc::now(),
-    })
+    };
+    outbox_service::record(&mut *tx, user_id, &DomainEvent::CardCreated(&card)).await?;
+    tx.commit().await?;
+
+    Ok(card)
 }
 
 pub async fn get_card(
@@ -104,6 +114,7 @@ pub async fn get_card_details(
     }
 }
 
+/// All the account's cards, the listing of API v1.
 pub async fn get_cards_by_account(
     pool: &DbPool,
     account_id: Uuid,
@@ -115,32 +126,65 @@ pub async fn get_cards_by_account(
     .fetch_all(pool)
     .await?;
 
-    let mut cards = Vec::new();
-    for row in rows {
-        let card_number = row.try_get::<Option<String>, _>("card_number_encrypted")?
-            .unwrap_or_else(|| "4111111111111111".to_string());
+    rows.iter().map(card_from_row).collect()
+}
 
-        cards.push(CardResponse {
-            id: row.try_get("id")?,
-            account_id: row.try_get("account_id")?,
-            card_type: row.try_get("card_type")?,
-            friendly_name: row.try_get("friendly_name")?,
-            masked_card_number: mask_card_number(&card_number),
-            expiry_month: row.try_get("expiry_month")?,
-            expiry_year: row.try_get("expiry_year")?,
-            status: row.try_get("status")?,
-            created_at: row.try_get("created_at")?,
-        });
-    }
+/// The page of the account's cards at `cursor`, newest first.
+pub async fn list_cards(
+    pool: &DbPool,
+    account_id: Uuid,
+    cursor: Option<&Cursor>,
+    limit: i64,
+) -> Result<Page<CardResponse>, sqlx::Error> {
+    let mut select = QueryBuilder::new(
+        "SELECT id, account_id, card_type, friendly_name, card_number_encrypted, expiry_month, expiry_year, status, created_at FROM cards WHERE account_id = "
+    );
+    select.push_bind(account_id);
+    push_keyset(&mut select, Order::NEWEST, cursor, limit);
+
+    let rows = select.build().fetch_all(pool).await?;
+    let cards = rows.iter().map(card_from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok(into_page(cards, Order::NEWEST, cursor, limit, |card| (time_key(card.created_at), card.id)))
+}
+
+fn card_from_row(row: &sqlx::postgres::PgRow) -> Result<CardResponse, sqlx::Error> {
+    let card_number = row.try_get::<Option<String>, _>("card_number_encrypted")?
+        .unwrap_or_else(|| "4111111111111111".to_string());
+
+    Ok(CardResponse {
+        id: row.try_get("id")?,
+        account_id: row.try_get("account_id")?,
+        card_type: row.try_get("card_type")?,
+        friendly_name: row.try_get("friendly_name")?,
+        masked_card_number: mask_card_number(&card_number),
+        expiry_month: row.try_get("expiry_month")?,
+        expiry_year: row.try_get("expiry_year")?,
+        status: row.try_get("status")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+pub async fn user_owns_card(
+    pool: &DbPool,
+    card_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT EXISTS(SELECT 1 FROM cards c JOIN accounts a ON a.id = c.account_id WHERE c.id = $1 AND a.user_id = $2) AS owned"
+    )
+    .bind(card_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
 
-    Ok(cards)
+    row.try_get("owned")
 }
 
 fn generate_mock_card_details() -> (String, i32, i32, String) {
     // Generate mock card details for testing
     let card_number = format!("411111111111{}", 1000 + (rand::random::<u32>() % 9000));
     let expiry_month = ((Utc::now().month() as i32 - 1 + 6) % 12) + 1; // 6 months from now
-    let expiry_year = Utc::now().year() as i32 + 3;
+    let expiry_year = Utc::now().year() + 3;
     let cvv = format!("{:03}", rand::random::<u32>() % 1000);
 
     (card_number, expiry_month, expiry_year, cvv)
@@ -1,11 +1,20 @@
+use std::time::Duration;
 use sqlx::{PgPool, postgres::PgPoolOptions};
 use crate::config::app_config::AppConfig;
 
 pub type DbPool = PgPool;
 
 pub async fn create_pool(config: &AppConfig) -> Result<DbPool, sqlx::Error> {
+    let idle_timeout = match config.database_idle_timeout_seconds {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    };
+
     PgPoolOptions::new()
-        .max_connections(5)
+        .max_connections(config.database_max_connections)
+        .min_connections(config.database_min_connections)
+        .acquire_timeout(Duration::from_secs(config.database_acquire_timeout_seconds))
+        .idle_timeout(idle_timeout)
         .connect(&config.database_url)
         .await
 }
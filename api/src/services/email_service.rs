@@ -0,0 +1,184 @@
+use crate::config::app_config::AppConfig;
+use crate::services::database::DbPool;
+use async_trait::async_trait;
+use sqlx::{Postgres, Row};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Rows that keep failing are parked as `failed` after this many attempts.
+pub const MAX_ATTEMPTS: i32 = 5;
+
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub id: Uuid,
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers outbox messages. Production deployments plug in an SMTP or
+/// provider-API implementation; development uses the log or file senders.
+#[async_trait]
+pub trait MailSender: Send + Sync {
+    async fn send(&self, message: &EmailMessage) -> Result<(), anyhow::Error>;
+}
+
+/// Writes every message to the application log.
+pub struct LogMailSender;
+
+#[async_trait]
+impl MailSender for LogMailSender {
+    async fn send(&self, message: &EmailMessage) -> Result<(), anyhow::Error> {
+        tracing::info!(to = %message.to, subject = %message.subject, "email:\n{}", message.body);
+        Ok(())
+    }
+}
+
+/// Drops each message as an `.eml` file in a directory, handy for clicking
+/// through verification and reset links locally.
+pub struct FileMailSender {
+    pub dir: PathBuf,
+}
+
+#[async_trait]
+impl MailSender for FileMailSender {
+    async fn send(&self, message: &EmailMessage) -> Result<(), anyhow::Error> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let contents = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nMessage-ID: <{}@vaelixbank>\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+            message.from, message.to, message.subject, message.id, message.body
+        );
+        tokio::fs::write(self.dir.join(format!("{}.eml", message.id)), contents).await?;
+
+        Ok(())
+    }
+}
+
+pub fn mail_sender_from_config(config: &AppConfig) -> Arc<dyn MailSender> {
+    match config.mail_sender.as_str() {
+        "file" => Arc::new(FileMailSender { dir: PathBuf::from(&config.mail_outbox_dir) }),
+        _ => Arc::new(LogMailSender),
+    }
+}
+
+/// Queues an email. Takes any executor so callers can enqueue inside the
+/// transaction that produced the email and never send for rolled-back work.
+pub async fn enqueue_email<'e, E>(
+    executor: E,
+    recipient: &str,
+    subject: &str,
+    body: &str,
+) -> Result<Uuid, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let id = Uuid::new_v4();
+
+    sqlx::query("INSERT INTO email_outbox (id, recipient, subject, body) VALUES ($1, $2, $3, $4)")
+        .bind(id)
+        .bind(recipient)
+        .bind(subject)
+        .bind(body)
+        .execute(executor)
+        .await?;
+
+    Ok(id)
+}
+
+/// Sends up to `batch_size` pending messages and returns how many were sent.
+/// Rows are locked with SKIP LOCKED so several workers can share the outbox.
+pub async fn dispatch_pending(
+    pool: &DbPool,
+    sender: &dyn MailSender,
+    from: &str,
+    batch_size: i64,
+) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let rows = sqlx::query(
+        "SELECT id, recipient, subject, body, attempts FROM email_outbox WHERE status = 'pending' ORDER BY created_at LIMIT $1 FOR UPDATE SKIP LOCKED"
+    )
+    .bind(batch_size)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut sent = 0;
+    for row in rows {
+        let message = EmailMessage {
+            id: row.try_get("id")?,
+            from: from.to_string(),
+            to: row.try_get("recipient")?,
+            subject: row.try_get("subject")?,
+            body: row.try_get("body")?,
+        };
+        let attempts: i32 = row.try_get::<i32, _>("attempts")? + 1;
+
+        match sender.send(&message).await {
+            Ok(()) => {
+                sqlx::query("UPDATE email_outbox SET status = 'sent', attempts = $2, sent_at = NOW(), last_error = NULL WHERE id = $1")
+                    .bind(message.id)
+                    .bind(attempts)
+                    .execute(&mut *tx)
+                    .await?;
+                sent += 1;
+            }
+            Err(e) => {
+                let status = if attempts >= MAX_ATTEMPTS { "failed" } else { "pending" };
+                sqlx::query("UPDATE email_outbox SET status = $2, attempts = $3, last_error = $4 WHERE id = $1")
+                    .bind(message.id)
+                    .bind(status)
+                    .bind(attempts)
+                    .bind(e.to_string())
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(sent)
+}
+
+/// Background loop draining the outbox every `interval`.
+pub fn spawn_outbox_worker(pool: DbPool, sender: Arc<dyn MailSender>, from: String, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = dispatch_pending(&pool, sender.as_ref(), &from, 50).await {
+                tracing::warn!("email outbox dispatch failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_mail_sender_writes_eml() {
+        let dir = std::env::temp_dir().join(format!("vaelix-mail-{}", Uuid::new_v4()));
+        let sender = FileMailSender { dir: dir.clone() };
+        let message = EmailMessage {
+            id: Uuid::new_v4(),
+            from: "no-reply@vaelixbank.com".to_string(),
+            to: "alice@example.com".to_string(),
+            subject: "Hello".to_string(),
+            body: "Click the link".to_string(),
+        };
+
+        sender.send(&message).await.unwrap();
+
+        let written = std::fs::read_to_string(dir.join(format!("{}.eml", message.id))).unwrap();
+        assert!(written.contains("To: alice@example.com"));
+        assert!(written.ends_with("Click the link"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
\ No newline at end of file
@@ -1,38 +1,57 @@
-use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aes_gcm::{Aes256Gcm, Nonce};
 use aes_gcm::aead::{Aead, KeyInit};
-use rand::Rng;
+use rand::RngCore;
 use base64::{Engine as _, engine::general_purpose};
 
+const NONCE_LEN: usize = 12;
+
+#[derive(Clone)]
 pub struct EncryptionService {
     cipher: Aes256Gcm,
 }
 
 impl EncryptionService {
     pub fn new(key: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        // Ensure key is 32 bytes (256 bits)
-        let key_bytes = if key.len() == 64 {
-            hex::decode(key)?
-        } else {
-            // If not hex, treat as base64 or pad/truncate
-            let mut key_vec = key.as_bytes().to_vec();
-            key_vec.resize(32, 0);
-            key_vec
-        };
-
-        let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
-        let cipher = Aes256Gcm::new(key);
+        // 32 bytes (256 bits) as hex; anything else is refused rather than padded
+        if key.len() != 64 {
+            return Err("encryption key must be 64 hex characters".into());
+        }
+        let key_bytes = hex::decode(key)?;
+
+        let cipher = Aes256Gcm::new_from_slice(&key_bytes)
+            .map_err(|_| "encryption key must be 32 bytes")?;
 
         Ok(Self { cipher })
     }
 
+    /// Encrypts with a fresh random nonce and returns base64(nonce || ciphertext).
     pub fn encrypt(&self, plaintext: &str) -> Result<String, Box<dyn std::error::Error>> {
-        // For now, return plaintext (TODO: implement proper encryption)
-        Ok(plaintext.to_string())
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+
+        let ciphertext = self.cipher
+            .encrypt(&Nonce::from(nonce_bytes), plaintext.as_bytes())
+            .map_err(|_| "encryption failed")?;
+
+        let mut payload = nonce_bytes.to_vec();
+        payload.extend_from_slice(&ciphertext);
+
+        Ok(general_purpose::STANDARD.encode(payload))
     }
 
     pub fn decrypt(&self, encrypted_data: &str) -> Result<String, Box<dyn std::error::Error>> {
-        // For now, return as-is (TODO: implement proper decryption)
-        Ok(encrypted_data.to_string())
+        let payload = general_purpose::STANDARD.decode(encrypted_data)?;
+        if payload.len() < NONCE_LEN {
+            return Err("ciphertext too short".into());
+        }
+
+        let (nonce_bytes, ciphertext) = payload.split_at(NONCE_LEN);
+        let nonce_bytes: [u8; NONCE_LEN] = nonce_bytes.try_into()?;
+        let plaintext = self.cipher
+            .decrypt(&Nonce::from(nonce_bytes), ciphertext)
+            .map_err(|_| "decryption failed")?;
+
+        Ok(String::from_utf8(plaintext)?)
     }
 }
 
@@ -50,5 +69,6 @@ mod tests {
         let decrypted = service.decrypt(&encrypted).unwrap();
 
         assert_eq!(original, decrypted);
+        assert_ne!(original, encrypted);
     }
 }
\ No newline at end of file
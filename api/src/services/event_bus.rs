@@ -0,0 +1,232 @@
+use crate::models::live_event::{LiveEvent, SecurityAlertKind};
+use crate::services::database::DbPool;
+use crate::services::transaction_service;
+use serde::Deserialize;
+use sqlx::postgres::PgListener;
+use sqlx::Row;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex};
+use uuid::Uuid;
+
+/// Events buffered per user for slow streams; a stream further behind is
+/// told to resync.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Live streams open at once per user.
+pub const MAX_STREAMS_PER_USER: usize = 5;
+
+/// Postgres channel the database triggers and the bank's processors notify.
+pub const NOTIFICATION_CHANNEL: &str = "live_events";
+
+/// In-process pub/sub of live events, one channel per connected user.
+/// Events published while nobody of a user is connected are dropped.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    channels: Arc<Mutex<HashMap<Uuid, broadcast::Sender<LiveEvent>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends `event` to the open streams of `user_id` and returns how many
+    /// got it.
+    pub async fn publish(&self, user_id: Uuid, event: LiveEvent) -> usize {
+        let mut channels = self.channels.lock().await;
+
+        let Some(sender) = channels.get(&user_id) else {
+            return 0;
+        };
+        match sender.send(event) {
+            Ok(receivers) => receivers,
+            Err(_) => {
+                // Every stream of the user is gone
+                channels.remove(&user_id);
+                0
+            }
+        }
+    }
+
+    /// Tells the user's devices about a security event, made from
+    /// `ip_address` with `user_agent`.
+    pub async fn alert(&self, user_id: Uuid, alert: SecurityAlertKind, ip_address: Option<String>, user_agent: Option<String>) {
+        self.publish(user_id, LiveEvent::SecurityAlert { alert, ip_address, user_agent }).await;
+    }
+
+    /// Opens a stream of the events of `user_id`, unless the user already
+    /// has `MAX_STREAMS_PER_USER` open.
+    pub async fn subscribe(&self, user_id: Uuid) -> Option<broadcast::Receiver<LiveEvent>> {
+        let mut channels = self.channels.lock().await;
+
+        // Drop the channels of users who left, so the map doesn't grow with every user ever connected
+        channels.retain(|_, sender| sender.receiver_count() > 0);
+
+        let sender = channels.entry(user_id).or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0);
+        if sender.receiver_count() >= MAX_STREAMS_PER_USER {
+            return None;
+        }
+        Some(sender.subscribe())
+    }
+}
+
+/// Payloads of `NOTIFICATION_CHANNEL`. Balances and transactions are
+/// notified by triggers, whoever changed them; card authorizations by the
+/// card processor, with `pg_notify('live_events', '{"type": "card_authorization", ...}')`.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Notification {
+    Balance {
+        user_id: Uuid,
+        account_id: Uuid,
+        balance: f64,
+        currency: String,
+    },
+    Transaction {
+        user_id: Uuid,
+        transaction_id: Uuid,
+        created: bool,
+    },
+    CardAuthorization {
+        card_id: Uuid,
+        amount: f64,
+        currency: String,
+        merchant: Option<String>,
+        approved: bool,
+    },
+}
+
+/// The event of a notification and whom it is for, or `None` when what it
+/// is about is gone.
+async fn resolve(pool: &DbPool, notification: Notification) -> Result<Option<(Uuid, LiveEvent)>, sqlx::Error> {
+    match notification {
+        Notification::Balance { user_id, account_id, balance, currency } => {
+            Ok(Some((user_id, LiveEvent::BalanceChanged { account_id, balance, currency })))
+        }
+        Notification::Transaction { user_id, transaction_id, created } => {
+            // Loaded rather than carried, as notifications are limited to 8000 bytes
+            let transaction = transaction_service::get_transaction(pool, transaction_id).await?;
+            Ok(transaction.map(|transaction| {
+                let event = match created {
+                    true => LiveEvent::TransactionCreated(transaction),
+                    false => LiveEvent::TransactionUpdated(transaction),
+                };
+                (user_id, event)
+            }))
+        }
+        Notification::CardAuthorization { card_id, amount, currency, merchant, approved } => {
+            let owner = sqlx::query("SELECT a.user_id, c.account_id FROM cards c JOIN accounts a ON a.id = c.account_id WHERE c.id = $1")
+                .bind(card_id)
+                .fetch_optional(pool)
+                .await?;
+            let Some(owner) = owner else {
+                return Ok(None);
+            };
+            let event = LiveEvent::CardAuthorization {
+                card_id,
+                account_id: owner.try_get("account_id")?,
+                amount,
+                currency,
+                merchant,
+                approved,
+            };
+            Ok(Some((owner.try_get("user_id")?, event)))
+        }
+    }
+}
+
+/// Background loop relaying the notifications of `NOTIFICATION_CHANNEL` to
+/// `bus`, so changes made outside the API reach the streams too. The
+/// listener reconnects by itself; what is notified in between is lost.
+pub fn spawn_notification_listener(pool: DbPool, bus: EventBus) {
+    tokio::spawn(async move {
+        loop {
+            let mut listener = match PgListener::connect_with(&pool).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    tracing::warn!("live event listener failed to connect: {}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+            if let Err(e) = listener.listen(NOTIFICATION_CHANNEL).await {
+                tracing::warn!("live event listener failed to listen: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+
+            loop {
+                let notification = match listener.recv().await {
+                    Ok(notification) => notification,
+                    Err(e) => {
+                        tracing::warn!("live event listener failed: {}", e);
+                        break;
+                    }
+                };
+                let parsed = match serde_json::from_str::<Notification>(notification.payload()) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        tracing::warn!("ignoring live event notification {:?}: {}", notification.payload(), e);
+                        continue;
+                    }
+                };
+                match resolve(&pool, parsed).await {
+                    Ok(Some((user_id, event))) => {
+                        bus.publish(user_id, event).await;
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("live event notification failed: {}", e),
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert() -> LiveEvent {
+        LiveEvent::SecurityAlert { alert: SecurityAlertKind::NewSignIn, ip_address: None, user_agent: None }
+    }
+
+    #[tokio::test]
+    async fn test_events_reach_only_their_user() {
+        let bus = EventBus::new();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let mut phone = bus.subscribe(alice).await.unwrap();
+        let mut laptop = bus.subscribe(alice).await.unwrap();
+        assert_eq!(bus.publish(alice, alert()).await, 2);
+        assert_eq!(bus.publish(bob, alert()).await, 0);
+        assert_eq!(phone.recv().await.unwrap().name(), "security_alert");
+        assert!(laptop.recv().await.is_ok());
+        assert!(phone.try_recv().is_err());
+
+        // Capped per user, and freed once streams close
+        let mut more = Vec::new();
+        while let Some(receiver) = bus.subscribe(alice).await {
+            more.push(receiver);
+        }
+        assert_eq!(more.len(), MAX_STREAMS_PER_USER - 2);
+        drop((phone, laptop, more));
+        assert_eq!(bus.publish(alice, alert()).await, 0);
+        assert!(bus.channels.lock().await.is_empty());
+    }
+
+    #[test]
+    fn test_notification_payloads() {
+        let card_id = Uuid::new_v4();
+        let payload = format!(
+            r#"{{"type": "card_authorization", "card_id": "{}", "amount": 12.5, "currency": "EUR", "merchant": "Café", "approved": false}}"#,
+            card_id
+        );
+        assert_eq!(
+            serde_json::from_str::<Notification>(&payload).unwrap(),
+            Notification::CardAuthorization { card_id, amount: 12.5, currency: "EUR".to_string(), merchant: Some("Café".to_string()), approved: false }
+        );
+        assert!(serde_json::from_str::<Notification>(r#"{"type": "card_authorization"}"#).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,164 @@
+use crate::services::database::DbPool;
+use crate::services::{audit_service, email_service, verification_service};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+/// Consecutive failed logins before the account is locked.
+pub const LOCKOUT_THRESHOLD: i32 = 10;
+pub const LOCKOUT_MINUTES: i64 = 30;
+/// Failures older than this no longer count towards the threshold.
+pub const FAILURE_WINDOW_MINUTES: i64 = 60;
+
+pub const UNLOCK_TOKEN_LIFETIME_HOURS: i64 = 24;
+
+/// Counts a failed password for the user. When the threshold is reached the
+/// account is locked, the lockout is audited and an unlock email is queued,
+/// all in one transaction. Returns the lock expiry if this failure locked it.
+/// Failures while the account is locked aren't counted, so they can't extend
+/// the lock or queue another email.
+pub async fn record_failure(
+    pool: &DbPool,
+    user_id: Uuid,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+    secret: &str,
+    app_base_url: &str,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let locked_until = Utc::now() + Duration::minutes(LOCKOUT_MINUTES);
+
+    let row = sqlx::query(
+        r#"
+        WITH attempt AS (
+            SELECT id,
+                CASE
+                    WHEN last_failed_login_at IS NULL OR last_failed_login_at < NOW() - make_interval(mins => $3) THEN 1
+                    ELSE failed_login_attempts + 1
+                END AS attempts
+            FROM users WHERE id = $1 AND (locked_until IS NULL OR locked_until <= NOW()) FOR UPDATE
+        )
+        UPDATE users SET
+            failed_login_attempts = CASE WHEN attempt.attempts >= $2 THEN 0 ELSE attempt.attempts END,
+            locked_until = CASE WHEN attempt.attempts >= $2 THEN $4 ELSE users.locked_until END,
+            last_failed_login_at = NOW()
+        FROM attempt
+        WHERE users.id = attempt.id
+        RETURNING attempt.attempts, users.email, users.name
+        "#
+    )
+    .bind(user_id)
+    .bind(LOCKOUT_THRESHOLD)
+    .bind(FAILURE_WINDOW_MINUTES as i32)
+    .bind(locked_until)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let attempts: i32 = row.try_get("attempts")?;
+    if attempts < LOCKOUT_THRESHOLD {
+        tx.commit().await?;
+        return Ok(None);
+    }
+
+    let email: String = row.try_get("email")?;
+    let name: String = row.try_get("name")?;
+
+    audit_service::record(
+        &mut *tx,
+        Some(user_id),
+        "account_locked",
+        audit_service::RESOURCE_USER,
+        Some(user_id),
+        json!({ "failed_attempts": LOCKOUT_THRESHOLD, "locked_until": locked_until }),
+        ip_address,
+        user_agent,
+    )
+    .await?;
+
+    let token = verification_service::issue_token(
+        &mut tx,
+        user_id,
+        verification_service::PURPOSE_ACCOUNT_UNLOCK,
+        Duration::hours(UNLOCK_TOKEN_LIFETIME_HOURS),
+        secret,
+    )
+    .await?;
+
+    let body = format!(
+        "Hello {},\n\nYour Vaelix Bank account was locked for {} minutes after {} failed sign-in attempts.\n\nIf this was you, unlock it now:\n\n{}/unlock-account?token={}\n\nIf it wasn't, we recommend resetting your password.\n",
+        name, LOCKOUT_MINUTES, LOCKOUT_THRESHOLD, app_base_url, token
+    );
+    email_service::enqueue_email(&mut *tx, &email, "Your account has been locked", &body).await?;
+
+    tx.commit().await?;
+
+    Ok(Some(locked_until))
+}
+
+/// When the lockout of `user_id` ends, if the account is locked.
+pub async fn locked_until(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let row = sqlx::query("SELECT locked_until FROM users WHERE id = $1 AND locked_until > NOW()")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    row.map(|row| row.try_get("locked_until")).transpose()
+}
+
+pub async fn reset_failures(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET failed_login_attempts = 0, last_failed_login_at = NULL WHERE id = $1 AND failed_login_attempts > 0")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Lifts a lockout using the token from the unlock email.
+pub async fn unlock_account(
+    pool: &DbPool,
+    token: &str,
+    secret: &str,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let user_id = match verification_service::consume_token(&mut tx, token, verification_service::PURPOSE_ACCOUNT_UNLOCK, secret).await? {
+        Some(user_id) => user_id,
+        None => return Ok(false),
+    };
+
+    sqlx::query("UPDATE users SET failed_login_attempts = 0, last_failed_login_at = NULL, locked_until = NULL WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit_service::record(
+        &mut *tx,
+        Some(user_id),
+        "account_unlocked",
+        audit_service::RESOURCE_USER,
+        Some(user_id),
+        json!({ "method": "email" }),
+        ip_address,
+        user_agent,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
\ No newline at end of file
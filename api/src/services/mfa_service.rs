@@ -0,0 +1,355 @@
+use crate::services::database::DbPool;
+use crate::services::encryption_service::EncryptionService;
+use crate::services::webauthn_service;
+use crate::utils::{jwt, totp};
+use sqlx::Row;
+use uuid::Uuid;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use chrono::{Duration, Utc};
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Wrong codes a login challenge takes before it is spent and the password
+/// has to be entered again.
+pub const MAX_CHALLENGE_FAILURES: i32 = 5;
+
+// Crockford-style alphabet without easily confused characters
+const RECOVERY_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTVWXYZ23456789";
+
+#[derive(Debug, thiserror::Error)]
+pub enum MfaError {
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error("secret encryption failed: {0}")]
+    Encryption(String),
+}
+
+pub async fn is_totp_enabled(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT enabled FROM user_totp WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.and_then(|r| r.try_get::<Option<bool>, _>("enabled").ok().flatten()).unwrap_or(false))
+}
+
+/// Second factors the user has set up, as advertised in the login challenge.
+/// Empty means password login alone is enough.
+pub async fn second_factor_methods(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut methods = Vec::new();
+
+    if is_totp_enabled(pool, user_id).await? {
+        methods.push("totp".to_string());
+        methods.push("recovery_code".to_string());
+    }
+
+    if webauthn_service::has_credentials(pool, user_id).await? {
+        methods.push("webauthn".to_string());
+    }
+
+    Ok(methods)
+}
+
+/// Stores a fresh, not yet enabled secret for the user. Returns `None` when
+/// TOTP is already enabled; it has to be disabled before re-enrolling.
+pub async fn begin_totp_enrollment(
+    pool: &DbPool,
+    encryption: &EncryptionService,
+    user_id: Uuid,
+) -> Result<Option<Vec<u8>>, MfaError> {
+    let secret = totp::generate_secret();
+    let encrypted_secret = encryption
+        .encrypt(&totp::encode_secret(&secret))
+        .map_err(|e| MfaError::Encryption(e.to_string()))?;
+
+    let result = sqlx::query(
+        "INSERT INTO user_totp (user_id, encrypted_secret) VALUES ($1, $2) ON CONFLICT (user_id) DO UPDATE SET encrypted_secret = EXCLUDED.encrypted_secret, last_used_step = NULL, created_at = NOW() WHERE user_totp.enabled = FALSE"
+    )
+    .bind(user_id)
+    .bind(&encrypted_secret)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(secret))
+}
+
+/// Enables TOTP once the user proves their authenticator produces valid codes,
+/// and returns a new set of recovery codes to show once.
+pub async fn confirm_totp_enrollment(
+    pool: &DbPool,
+    encryption: &EncryptionService,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Option<Vec<String>>, MfaError> {
+    if !verify_totp(pool, encryption, user_id, code, false).await? {
+        return Ok(None);
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query("UPDATE user_totp SET enabled = TRUE, enabled_at = NOW() WHERE user_id = $1 AND enabled = FALSE")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // Already enabled; the transaction is rolled back on drop
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+    for code in &codes {
+        sqlx::query("INSERT INTO mfa_recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(hash_recovery_code(code))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Some(codes))
+}
+
+/// Checks a TOTP code. A time step that has already been accepted once is
+/// refused, so an observed code cannot be replayed within its window.
+pub async fn verify_totp(
+    pool: &DbPool,
+    encryption: &EncryptionService,
+    user_id: Uuid,
+    code: &str,
+    require_enabled: bool,
+) -> Result<bool, MfaError> {
+    let row = sqlx::query("SELECT encrypted_secret, enabled FROM user_totp WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(false),
+    };
+
+    let enabled: Option<bool> = row.try_get("enabled")?;
+    if require_enabled && !enabled.unwrap_or(false) {
+        return Ok(false);
+    }
+
+    let encrypted_secret: String = row.try_get("encrypted_secret")?;
+    let secret = encryption
+        .decrypt(&encrypted_secret)
+        .ok()
+        .and_then(|encoded| totp::decode_secret(&encoded))
+        .ok_or_else(|| MfaError::Encryption("stored TOTP secret is unreadable".to_string()))?;
+
+    let step = match totp::verify_code(&secret, code, Utc::now().timestamp() as u64) {
+        Some(step) => step,
+        None => return Ok(false),
+    };
+
+    let result = sqlx::query(
+        "UPDATE user_totp SET last_used_step = $2 WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)"
+    )
+    .bind(user_id)
+    .bind(step as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn disable_totp(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM user_totp WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+/// Consumes a recovery code. Each code works exactly once.
+pub async fn use_recovery_code(
+    pool: &DbPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE mfa_recovery_codes SET used_at = NOW() WHERE id = (SELECT id FROM mfa_recovery_codes WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL LIMIT 1)"
+    )
+    .bind(user_id)
+    .bind(hash_recovery_code(code))
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Opens a login challenge for `user_id`, to be answered with a second
+/// factor; its id goes in the challenge token.
+pub async fn create_challenge(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    // Opportunistically clear expired challenges
+    sqlx::query("DELETE FROM mfa_challenges WHERE expires_at < NOW()")
+        .execute(pool)
+        .await?;
+
+    let row = sqlx::query("INSERT INTO mfa_challenges (user_id, expires_at) VALUES ($1, $2) RETURNING id")
+        .bind(user_id)
+        .bind(Utc::now() + Duration::minutes(jwt::MFA_CHALLENGE_MINUTES))
+        .fetch_one(pool)
+        .await?;
+
+    row.try_get("id")
+}
+
+/// Whether the challenge of `user_id` can still be answered.
+pub async fn challenge_open(
+    pool: &DbPool,
+    challenge_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT 1 FROM mfa_challenges WHERE id = $1 AND user_id = $2 AND spent_at IS NULL AND expires_at > NOW()")
+        .bind(challenge_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.is_some())
+}
+
+/// Counts a wrong code on the challenge, spending it after
+/// `MAX_CHALLENGE_FAILURES`.
+pub async fn fail_challenge(
+    pool: &DbPool,
+    challenge_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE mfa_challenges SET failed_attempts = failed_attempts + 1, spent_at = CASE WHEN failed_attempts + 1 >= $2 THEN NOW() END WHERE id = $1 AND spent_at IS NULL"
+    )
+    .bind(challenge_id)
+    .bind(MAX_CHALLENGE_FAILURES)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Spends the challenge once its second factor was checked. Only one
+/// caller gets `true`, so a challenge opens one session at most.
+pub async fn spend_challenge(
+    pool: &DbPool,
+    challenge_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE mfa_challenges SET spent_at = NOW() WHERE id = $1 AND spent_at IS NULL AND expires_at > NOW()")
+        .bind(challenge_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Spends every open challenge of `user_id`, once a session was opened
+/// another way.
+pub async fn spend_challenges(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE mfa_challenges SET spent_at = NOW() WHERE user_id = $1 AND spent_at IS NULL")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+fn generate_recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: String = (0..10)
+        .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+        .collect();
+
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    // Codes are random and high entropy, so a plain digest is enough here
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{create_user, test_pool};
+
+    #[test]
+    fn test_recovery_code_hash_ignores_formatting() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(hash_recovery_code(&code), hash_recovery_code(&code.replace('-', "").to_lowercase()));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_challenges_open_one_session_and_take_bounded_failures() {
+        let pool = test_pool().await;
+        let user = create_user(&pool, "Challenged").await;
+        let other = create_user(&pool, "Other").await;
+
+        // Answered once, then spent
+        let answered = create_challenge(&pool, user.id).await.unwrap();
+        assert!(challenge_open(&pool, answered, user.id).await.unwrap());
+        assert!(!challenge_open(&pool, answered, other.id).await.unwrap());
+        assert!(spend_challenge(&pool, answered).await.unwrap());
+        assert!(!spend_challenge(&pool, answered).await.unwrap());
+        assert!(!challenge_open(&pool, answered, user.id).await.unwrap());
+
+        // Spent by the last wrong code it takes
+        let guessed = create_challenge(&pool, user.id).await.unwrap();
+        for _ in 1..MAX_CHALLENGE_FAILURES {
+            fail_challenge(&pool, guessed).await.unwrap();
+        }
+        assert!(challenge_open(&pool, guessed, user.id).await.unwrap());
+        fail_challenge(&pool, guessed).await.unwrap();
+        assert!(!challenge_open(&pool, guessed, user.id).await.unwrap());
+        assert!(!spend_challenge(&pool, guessed).await.unwrap());
+
+        // Another sign-in spends the open ones
+        let pending = create_challenge(&pool, user.id).await.unwrap();
+        spend_challenges(&pool, user.id).await.unwrap();
+        assert!(!challenge_open(&pool, pending, user.id).await.unwrap());
+    }
+}
\ No newline at end of file
@@ -6,4 +6,18 @@ pub mod card_service;
 pub mod transaction_service;
 pub mod beneficiary_service;
 pub mod encryption_service;
-pub mod database;
\ No newline at end of file
+pub mod database;
+pub mod session_service;
+pub mod mfa_service;
+pub mod webauthn_service;
+pub mod email_service;
+pub mod verification_service;
+pub mod audit_service;
+pub mod lockout_service;
+pub mod rbac_service;
+pub mod api_key_service;
+pub mod oauth_service;
+pub mod webhook_sender;
+pub mod webhook_service;
+pub mod event_bus;
+pub mod outbox_service;
\ No newline at end of file
@@ -0,0 +1,437 @@
+use crate::models::oauth::{OAuthClient, RegisterClientRequest, TokenResponse};
+use crate::services::database::DbPool;
+use crate::services::rbac_service;
+use crate::utils::jwt::{self, JwtKeys};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, Row};
+use uuid::Uuid;
+
+pub const CLIENT_ID_PREFIX: &str = "vbc";
+pub const AUTHORIZATION_CODE_MINUTES: i64 = 10;
+pub const ACCESS_TOKEN_MINUTES: i64 = 60;
+
+/// RFC 7636: verifiers are 43 to 128 unreserved characters.
+const PKCE_VERIFIER_MIN_LEN: usize = 43;
+const PKCE_VERIFIER_MAX_LEN: usize = 128;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OAuthError {
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Token(#[from] jsonwebtoken::errors::Error),
+}
+
+fn random_token(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut buf);
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// The S256 code challenge for a PKCE verifier.
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+pub fn verify_pkce(verifier: &str, challenge: &str) -> bool {
+    let well_formed = (PKCE_VERIFIER_MIN_LEN..=PKCE_VERIFIER_MAX_LEN).contains(&verifier.len())
+        && verifier.bytes().all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b));
+
+    well_formed && pkce_challenge(verifier) == challenge
+}
+
+/// Splits a space-delimited `scope` parameter.
+pub fn parse_scope(scope: &str) -> Vec<String> {
+    scope.split_whitespace().map(str::to_string).collect()
+}
+
+/// Whether every scope names a permission.
+pub async fn scopes_exist(
+    pool: &DbPool,
+    scopes: &[String],
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT COUNT(*) AS known FROM permissions WHERE name = ANY($1)")
+        .bind(scopes)
+        .fetch_one(pool)
+        .await?;
+
+    let known: i64 = row.try_get("known")?;
+    Ok(known as usize == scopes.len())
+}
+
+/// Stores a new client and returns it with its secret, which is not kept.
+/// Public clients get no secret.
+pub async fn register_client(
+    pool: &DbPool,
+    owner_id: Uuid,
+    request: RegisterClientRequest,
+) -> Result<(OAuthClient, Option<String>), sqlx::Error> {
+    let id = Uuid::new_v4();
+    let client_id = format!("{}_{}", CLIENT_ID_PREFIX, hex::encode(Uuid::new_v4().as_bytes()));
+    let secret = request.confidential.then(|| random_token(32));
+
+    sqlx::query(
+        "INSERT INTO oauth_clients (id, client_id, secret_hash, owner_id, name, redirect_uris, scopes, grant_types) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+    )
+    .bind(id)
+    .bind(&client_id)
+    .bind(secret.as_deref().map(hash_secret))
+    .bind(owner_id)
+    .bind(&request.name)
+    .bind(&request.redirect_uris)
+    .bind(&request.scopes)
+    .bind(&request.grant_types)
+    .execute(pool)
+    .await?;
+
+    let client = OAuthClient {
+        id,
+        client_id,
+        owner_id,
+        name: request.name,
+        redirect_uris: request.redirect_uris,
+        scopes: request.scopes,
+        grant_types: request.grant_types,
+        confidential: request.confidential,
+        created_at: Utc::now(),
+    };
+
+    Ok((client, secret))
+}
+
+pub async fn get_clients_by_owner(
+    pool: &DbPool,
+    owner_id: Uuid,
+) -> Result<Vec<OAuthClient>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, client_id, secret_hash, owner_id, name, redirect_uris, scopes, grant_types, created_at FROM oauth_clients WHERE owner_id = $1 AND revoked_at IS NULL ORDER BY created_at DESC"
+    )
+    .bind(owner_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut clients = Vec::new();
+    for row in rows {
+        clients.push(client_from_row(&row)?);
+    }
+
+    Ok(clients)
+}
+
+/// Deletes a client and revokes every token issued to it. Returns the revoked
+/// session ids for the session cache, or `None` if the client wasn't found.
+pub async fn delete_client(
+    pool: &DbPool,
+    id: Uuid,
+    owner_id: Uuid,
+) -> Result<Option<Vec<Uuid>>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query("UPDATE oauth_clients SET revoked_at = NOW() WHERE id = $1 AND owner_id = $2 AND revoked_at IS NULL")
+        .bind(id)
+        .bind(owner_id)
+        .execute(&mut *tx)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let rows = sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE oauth_client_id = $1 AND revoked_at IS NULL RETURNING id")
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    let mut revoked = Vec::new();
+    for row in rows {
+        revoked.push(row.try_get("id")?);
+    }
+
+    tx.commit().await?;
+
+    Ok(Some(revoked))
+}
+
+pub async fn find_client(
+    pool: &DbPool,
+    client_id: &str,
+) -> Result<Option<OAuthClient>, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT id, client_id, secret_hash, owner_id, name, redirect_uris, scopes, grant_types, created_at FROM oauth_clients WHERE client_id = $1 AND revoked_at IS NULL"
+    )
+    .bind(client_id)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(row) => Ok(Some(client_from_row(&row)?)),
+        None => Ok(None),
+    }
+}
+
+/// Authenticates a client at the token endpoints. Confidential clients must
+/// present their secret; public clients have none to present.
+pub async fn authenticate_client(
+    pool: &DbPool,
+    client_id: &str,
+    client_secret: Option<&str>,
+) -> Result<Option<OAuthClient>, sqlx::Error> {
+    let row = sqlx::query("SELECT secret_hash FROM oauth_clients WHERE client_id = $1 AND revoked_at IS NULL")
+        .bind(client_id)
+        .fetch_optional(pool)
+        .await?;
+
+    let secret_hash: Option<String> = match row {
+        Some(row) => row.try_get("secret_hash")?,
+        None => return Ok(None),
+    };
+
+    let authenticated = match (secret_hash, client_secret) {
+        (Some(expected), Some(presented)) => hash_secret(presented) == expected,
+        (None, None) => true,
+        _ => false,
+    };
+
+    if !authenticated {
+        return Ok(None);
+    }
+
+    find_client(pool, client_id).await
+}
+
+/// Records the user's consent as a single-use code bound to the client,
+/// redirect URI, scopes and PKCE challenge.
+pub async fn create_authorization_code(
+    pool: &DbPool,
+    client: &OAuthClient,
+    user_id: Uuid,
+    redirect_uri: &str,
+    scopes: &[String],
+    code_challenge: &str,
+) -> Result<String, sqlx::Error> {
+    let code = random_token(32);
+
+    sqlx::query(
+        "INSERT INTO oauth_authorization_codes (code_hash, client_id, user_id, redirect_uri, scopes, code_challenge, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7)"
+    )
+    .bind(hash_secret(&code))
+    .bind(client.id)
+    .bind(user_id)
+    .bind(redirect_uri)
+    .bind(scopes)
+    .bind(code_challenge)
+    .bind(Utc::now() + Duration::minutes(AUTHORIZATION_CODE_MINUTES))
+    .execute(pool)
+    .await?;
+
+    Ok(code)
+}
+
+/// Redeems an authorization code. Codes that are unknown, expired, issued to
+/// another client or redirect URI, or whose PKCE verifier doesn't match are
+/// refused with `None`. A code presented twice was intercepted: the token
+/// issued for it is revoked and its session id returned for eviction.
+pub async fn exchange_code(
+    pool: &DbPool,
+    client: &OAuthClient,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: &str,
+    jwt_keys: &JwtKeys,
+) -> Result<Result<TokenResponse, Option<Uuid>>, OAuthError> {
+    let mut tx = pool.begin().await?;
+
+    let row = sqlx::query(
+        "SELECT client_id, user_id, redirect_uri, scopes, code_challenge, expires_at > NOW() AS live, used_at IS NOT NULL AS used, session_id FROM oauth_authorization_codes WHERE code_hash = $1 FOR UPDATE"
+    )
+    .bind(hash_secret(code))
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(Err(None)),
+    };
+
+    let issued_to: Uuid = row.try_get("client_id")?;
+    if issued_to != client.id {
+        return Ok(Err(None));
+    }
+
+    if row.try_get::<bool, _>("used")? {
+        let session_id: Option<Uuid> = row.try_get("session_id")?;
+        if let Some(session_id) = session_id {
+            sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL")
+                .bind(session_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        return Ok(Err(session_id));
+    }
+
+    let expected_uri: String = row.try_get("redirect_uri")?;
+    let challenge: String = row.try_get("code_challenge")?;
+    if !row.try_get::<bool, _>("live")? || expected_uri != redirect_uri || !verify_pkce(code_verifier, &challenge) {
+        return Ok(Err(None));
+    }
+
+    let user_id: Uuid = row.try_get("user_id")?;
+    let scopes: Vec<String> = row.try_get("scopes")?;
+    let session_id = create_grant_session(&mut tx, client, user_id).await?;
+
+    sqlx::query("UPDATE oauth_authorization_codes SET used_at = NOW(), session_id = $2 WHERE code_hash = $1")
+        .bind(hash_secret(code))
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Ok(issue_access_token(pool, client, user_id, session_id, &scopes, jwt_keys).await?))
+}
+
+/// Client credentials grant: the client acts for its owner, limited to the
+/// requested scopes among those it was registered with.
+pub async fn client_credentials(
+    pool: &DbPool,
+    client: &OAuthClient,
+    scopes: &[String],
+    jwt_keys: &JwtKeys,
+) -> Result<TokenResponse, OAuthError> {
+    let mut conn = pool.acquire().await?;
+    let session_id = create_grant_session(&mut conn, client, client.owner_id).await?;
+
+    issue_access_token(pool, client, client.owner_id, session_id, scopes, jwt_keys).await
+}
+
+/// Each access token gets its own session, so revoking one token leaves the
+/// others alone. It lives exactly as long as the token.
+async fn create_grant_session(
+    conn: &mut PgConnection,
+    client: &OAuthClient,
+    user_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    let session_id = Uuid::new_v4();
+
+    sqlx::query("INSERT INTO sessions (id, user_id, user_agent, oauth_client_id, expires_at) VALUES ($1, $2, $3, $4, $5)")
+        .bind(session_id)
+        .bind(user_id)
+        .bind(&client.name)
+        .bind(client.id)
+        .bind(Utc::now() + Duration::minutes(ACCESS_TOKEN_MINUTES))
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(session_id)
+}
+
+/// Signs the token with the granted scopes the user still holds, so a
+/// client never gets more than the user could do.
+async fn issue_access_token(
+    pool: &DbPool,
+    client: &OAuthClient,
+    user_id: Uuid,
+    session_id: Uuid,
+    scopes: &[String],
+    jwt_keys: &JwtKeys,
+) -> Result<TokenResponse, OAuthError> {
+    let row = sqlx::query("SELECT email FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    let email: String = row.try_get("email")?;
+
+    let access = rbac_service::get_user_access(pool, user_id).await?;
+    let granted: Vec<String> = scopes
+        .iter()
+        .filter(|scope| access.permissions.contains(scope))
+        .cloned()
+        .collect();
+
+    let access_token = jwt::create_oauth_access_token(
+        &user_id.to_string(),
+        &email,
+        &session_id.to_string(),
+        &granted,
+        &client.client_id,
+        jwt_keys,
+        ACCESS_TOKEN_MINUTES,
+    )?;
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: ACCESS_TOKEN_MINUTES * 60,
+        scope: granted.join(" "),
+    })
+}
+
+/// Revokes the session behind a token issued to `client`. Returns whether
+/// anything was revoked.
+pub async fn revoke_grant(
+    pool: &DbPool,
+    client: &OAuthClient,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND oauth_client_id = $2 AND revoked_at IS NULL")
+        .bind(session_id)
+        .bind(client.id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn grant_active(
+    pool: &DbPool,
+    client: &OAuthClient,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT 1 FROM sessions WHERE id = $1 AND oauth_client_id = $2 AND revoked_at IS NULL AND expires_at > NOW()")
+        .bind(session_id)
+        .bind(client.id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.is_some())
+}
+
+fn client_from_row(row: &sqlx::postgres::PgRow) -> Result<OAuthClient, sqlx::Error> {
+    let secret_hash: Option<String> = row.try_get("secret_hash")?;
+
+    Ok(OAuthClient {
+        id: row.try_get("id")?,
+        client_id: row.try_get("client_id")?,
+        owner_id: row.try_get("owner_id")?,
+        name: row.try_get("name")?,
+        redirect_uris: row.try_get("redirect_uris")?,
+        scopes: row.try_get("scopes")?,
+        grant_types: row.try_get("grant_types")?,
+        confidential: secret_hash.is_some(),
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pkce_s256() {
+        let verifier = "dBjftJeZ4CVP-mB92K9uhvRlxJbaE7vWg0Nc3KCcOvM";
+        let challenge = "i8WtlYdgy24AMuMc0C2jKV4XR3IwMIWmVdyzr9iG6E8";
+
+        assert_eq!(pkce_challenge(verifier), challenge);
+        assert!(verify_pkce(verifier, challenge));
+        assert!(!verify_pkce("dBjftJeZ4CVP-mB92K9uhvRlxJbaE7vWg0Nc3KCcOvN", challenge));
+        assert!(!verify_pkce("short", &pkce_challenge("short")));
+    }
+}
\ No newline at end of file
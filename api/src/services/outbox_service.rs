@@ -0,0 +1,270 @@
+use crate::models::domain_event::{DomainEvent, OutboxEvent};
+use crate::services::database::DbPool;
+use async_trait::async_trait;
+use sqlx::{Postgres, Row};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Wait before relaying an event again after a handler failed, doubled
+/// after each failed relay up to `MAX_RETRY_DELAY_SECONDS`. Events are
+/// retried until every handler took them.
+const FIRST_RETRY_DELAY_SECONDS: i64 = 10;
+const MAX_RETRY_DELAY_SECONDS: i64 = 3600;
+
+/// Claimed events are not picked again by other workers for this long.
+const LEASE_SECONDS: i32 = 60;
+
+/// Dispatched events are kept this long, then purged.
+pub const RETENTION_DAYS: i32 = 7;
+
+/// Longest error kept on an event.
+const MAX_ERROR_LENGTH: usize = 500;
+
+/// Takes the events of the outbox somewhere. Delivery is at least once: an
+/// event is handed again until the handler succeeds, and a handler that
+/// crashed after acting but before its success was recorded sees it again,
+/// so handlers drop events whose `id` they already handled.
+#[async_trait]
+pub trait OutboxHandler: Send + Sync {
+    /// Records which events the handler took; must not change once deployed.
+    fn name(&self) -> &'static str;
+
+    async fn handle(&self, pool: &DbPool, event: &OutboxEvent) -> Result<(), anyhow::Error>;
+}
+
+/// Records `event` of `user_id`'s resources. Takes any executor so services
+/// record the event in the transaction of the change: it is relayed if and
+/// only if the change is committed.
+pub async fn record<'e, E>(executor: E, user_id: Uuid, event: &DomainEvent<'_>) -> Result<Uuid, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let id = Uuid::new_v4();
+
+    sqlx::query("INSERT INTO outbox_events (id, user_id, event_type, data) VALUES ($1, $2, $3, $4)")
+        .bind(id)
+        .bind(user_id)
+        .bind(event.event_type())
+        .bind(sqlx::types::Json(event))
+        .execute(executor)
+        .await?;
+
+    Ok(id)
+}
+
+/// Wait before the relay following the `attempts`-th failed one.
+fn retry_delay(attempts: i32) -> chrono::Duration {
+    let doublings = (attempts - 1).clamp(0, 20) as u32;
+    chrono::Duration::seconds((FIRST_RETRY_DELAY_SECONDS << doublings).min(MAX_RETRY_DELAY_SECONDS))
+}
+
+/// Hands up to `batch_size` due events, oldest first, to the handlers that
+/// haven't taken them yet, and returns how many events every handler has
+/// now taken. Events are leased with SKIP LOCKED, so several workers can
+/// share the outbox; an event failing doesn't hold back the next ones.
+pub async fn relay_due(pool: &DbPool, handlers: &[Arc<dyn OutboxHandler>], batch_size: i64) -> Result<usize, sqlx::Error> {
+    let rows = sqlx::query(
+        "UPDATE outbox_events SET next_attempt_at = NOW() + make_interval(secs => $2) WHERE id IN (SELECT id FROM outbox_events WHERE dispatched_at IS NULL AND next_attempt_at <= NOW() ORDER BY created_at LIMIT $1 FOR UPDATE SKIP LOCKED) RETURNING id, user_id, event_type, data, created_at, attempts, ARRAY(SELECT handler FROM outbox_dispatches d WHERE d.event_id = outbox_events.id) AS handled_by"
+    )
+    .bind(batch_size)
+    .bind(LEASE_SECONDS as f64)
+    .fetch_all(pool)
+    .await?;
+
+    let mut events = rows.iter().map(|row| Ok((event_from_row(row)?, row.try_get::<Vec<String>, _>("handled_by")?))).collect::<Result<Vec<_>, sqlx::Error>>()?;
+    events.sort_by_key(|(event, _)| event.created_at);
+
+    let mut dispatched = 0;
+    for (event, handled_by) in events {
+        let mut errors = Vec::new();
+        for handler in handlers.iter().filter(|handler| !handled_by.iter().any(|name| name == handler.name())) {
+            match handler.handle(pool, &event).await {
+                Ok(()) => {
+                    sqlx::query("INSERT INTO outbox_dispatches (event_id, handler) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+                        .bind(event.id)
+                        .bind(handler.name())
+                        .execute(pool)
+                        .await?;
+                }
+                Err(e) => errors.push(format!("{}: {}", handler.name(), e)),
+            }
+        }
+
+        if errors.is_empty() {
+            sqlx::query("UPDATE outbox_events SET dispatched_at = NOW(), last_error = NULL WHERE id = $1")
+                .bind(event.id)
+                .execute(pool)
+                .await?;
+            dispatched += 1;
+        } else {
+            let attempts = event.attempts + 1;
+            let error: String = errors.join("; ").chars().take(MAX_ERROR_LENGTH).collect();
+            tracing::warn!(event_id = %event.id, attempts, "outbox event {} not relayed: {}", event.event_type, error);
+            sqlx::query("UPDATE outbox_events SET attempts = $2, last_error = $3, next_attempt_at = NOW() + make_interval(secs => $4) WHERE id = $1")
+                .bind(event.id)
+                .bind(attempts)
+                .bind(&error)
+                .bind(retry_delay(attempts).num_seconds() as f64)
+                .execute(pool)
+                .await?;
+        }
+    }
+
+    Ok(dispatched)
+}
+
+/// Deletes the events dispatched more than `RETENTION_DAYS` ago.
+pub async fn purge_dispatched(pool: &DbPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM outbox_events WHERE dispatched_at < NOW() - make_interval(days => $1)")
+        .bind(RETENTION_DAYS)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Background loop relaying due events every `interval`, and purging the
+/// old ones hourly.
+pub fn spawn_relay_worker(pool: DbPool, handlers: Vec<Arc<dyn OutboxHandler>>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut purge = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if let Err(e) = relay_due(&pool, &handlers, 100).await {
+                        tracing::warn!("outbox relay failed: {}", e);
+                    }
+                }
+                _ = purge.tick() => {
+                    if let Err(e) = purge_dispatched(&pool).await {
+                        tracing::warn!("outbox purge failed: {}", e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn event_from_row(row: &sqlx::postgres::PgRow) -> Result<OutboxEvent, sqlx::Error> {
+    Ok(OutboxEvent {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        event_type: row.try_get("event_type")?,
+        data: row.try_get("data")?,
+        created_at: row.try_get("created_at")?,
+        attempts: row.try_get("attempts")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::beneficiary::CreateBeneficiaryRequest;
+    use crate::models::domain_event::event_types;
+    use crate::services::beneficiary_service;
+    use crate::test_support::{create_account, create_user, test_pool};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    /// Remembers the events of one user it was handed, failing while `down`.
+    struct Recorder {
+        name: &'static str,
+        user_id: Uuid,
+        down: AtomicBool,
+        handled: Mutex<Vec<(Uuid, String)>>,
+    }
+
+    impl Recorder {
+        fn new(name: &'static str, user_id: Uuid) -> Arc<Self> {
+            Arc::new(Self { name, user_id, down: AtomicBool::new(false), handled: Mutex::new(Vec::new()) })
+        }
+
+        fn handled(&self) -> Vec<(Uuid, String)> {
+            self.handled.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl OutboxHandler for Recorder {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn handle(&self, _pool: &DbPool, event: &OutboxEvent) -> Result<(), anyhow::Error> {
+            if event.user_id != self.user_id {
+                return Ok(());
+            }
+            if self.down.load(Ordering::SeqCst) {
+                anyhow::bail!("down");
+            }
+            self.handled.lock().unwrap().push((event.id, event.event_type.clone()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_events_are_relayed_with_their_change() {
+        let pool = test_pool().await;
+
+        let user = create_user(&pool, "Outbox").await;
+        let analytics = Recorder::new("analytics", user.id);
+        let notifications = Recorder::new("notifications", user.id);
+        let handlers: Vec<Arc<dyn OutboxHandler>> = vec![analytics.clone(), notifications.clone()];
+
+        // Written with the change, or not at all
+        let beneficiary = beneficiary_service::create_beneficiary(&pool, user.id, CreateBeneficiaryRequest {
+            name: "Alice".to_string(),
+            iban: "FR7630006000011234567890189".to_string(),
+            account_number: None,
+            sort_code: None,
+            bank_name: None,
+        })
+        .await
+        .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        record(&mut *tx, user.id, &DomainEvent::BeneficiaryDeleted { id: beneficiary.id }).await.unwrap();
+        tx.rollback().await.unwrap();
+
+        // Status changes made outside the services are recorded by the database
+        let account = create_account(&pool, user.id, "Main").await;
+        let card_id: Uuid = sqlx::query_scalar("INSERT INTO cards (account_id, card_type, friendly_name, status) VALUES ($1, 'virtual', 'Card', 'active') RETURNING id")
+            .bind(account.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE cards SET status = 'frozen' WHERE id = $1").bind(card_id).execute(&pool).await.unwrap();
+
+        // One handler failing: the other takes the events once, the failing one on a later relay
+        notifications.down.store(true, Ordering::SeqCst);
+        while relay_due(&pool, &handlers, 100).await.unwrap() > 0 {}
+        let types: Vec<String> = analytics.handled().into_iter().map(|(_, event_type)| event_type).collect();
+        assert_eq!(types, [event_types::BENEFICIARY_CREATED, event_types::CARD_STATUS_CHANGED]);
+        assert!(notifications.handled().is_empty());
+
+        let (attempts, last_error): (i32, Option<String>) =
+            sqlx::query_as("SELECT attempts, last_error FROM outbox_events WHERE user_id = $1 AND event_type = $2")
+                .bind(user.id)
+                .bind(event_types::BENEFICIARY_CREATED)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(attempts, 1);
+        assert!(last_error.unwrap().ends_with(": down"));
+
+        notifications.down.store(false, Ordering::SeqCst);
+        sqlx::query("UPDATE outbox_events SET next_attempt_at = NOW() WHERE user_id = $1").bind(user.id).execute(&pool).await.unwrap();
+        while relay_due(&pool, &handlers, 100).await.unwrap() > 0 {}
+        assert_eq!(notifications.handled(), analytics.handled());
+        assert_eq!(analytics.handled().len(), 2);
+
+        let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM outbox_events WHERE user_id = $1 AND dispatched_at IS NULL")
+            .bind(user.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(pending, 0);
+    }
+}
\ No newline at end of file
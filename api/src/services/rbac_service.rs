@@ -0,0 +1,139 @@
+use crate::models::role::{RoleGrant, UserAccess, ROLE_CORPORATE_ADMIN, ROLE_CUSTOMER};
+use crate::models::user::UserType;
+use crate::services::database::DbPool;
+use sqlx::{Postgres, Row};
+use uuid::Uuid;
+
+/// Role given to new sign-ups. A corporate sign-up administers its own company.
+pub fn default_role(user_type: &UserType) -> &'static str {
+    match user_type {
+        UserType::Consumer => ROLE_CUSTOMER,
+        UserType::Corporate => ROLE_CORPORATE_ADMIN,
+    }
+}
+
+pub async fn get_user_access(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<UserAccess, sqlx::Error> {
+    let rows = sqlx::query("SELECT role FROM user_roles WHERE user_id = $1 ORDER BY role")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+    let mut roles = Vec::new();
+    for row in rows {
+        roles.push(row.try_get("role")?);
+    }
+
+    let rows = sqlx::query(
+        "SELECT DISTINCT rp.permission FROM role_permissions rp JOIN user_roles ur ON ur.role = rp.role WHERE ur.user_id = $1 ORDER BY rp.permission"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut permissions = Vec::new();
+    for row in rows {
+        permissions.push(row.try_get("permission")?);
+    }
+
+    Ok(UserAccess { roles, permissions })
+}
+
+pub async fn get_role_grants(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<Vec<RoleGrant>, sqlx::Error> {
+    let rows = sqlx::query("SELECT role, granted_by, created_at FROM user_roles WHERE user_id = $1 ORDER BY created_at")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+    let mut grants = Vec::new();
+    for row in rows {
+        grants.push(RoleGrant {
+            role: row.try_get("role")?,
+            granted_by: row.try_get("granted_by")?,
+            created_at: row.try_get("created_at")?,
+        });
+    }
+
+    Ok(grants)
+}
+
+/// Grants a role. Granting a role the user already holds is a no-op; an
+/// unknown role or user surfaces as a foreign key violation.
+pub async fn assign_role<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    role: &str,
+    granted_by: Option<Uuid>,
+) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    sqlx::query("INSERT INTO user_roles (user_id, role, granted_by) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING")
+        .bind(user_id)
+        .bind(role)
+        .bind(granted_by)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn revoke_role(
+    pool: &DbPool,
+    user_id: Uuid,
+    role: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM user_roles WHERE user_id = $1 AND role = $2")
+        .bind(user_id)
+        .bind(role)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{create_user, test_pool};
+
+    const BANKING: &[&str] = &[
+        "accounts:read", "accounts:write", "beneficiaries:read", "beneficiaries:write",
+        "cards:read", "cards:write", "transactions:read", "transactions:write",
+    ];
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_seeded_roles_resolve_to_their_permissions() {
+        let pool = test_pool().await;
+        let every: Vec<String> = sqlx::query_scalar("SELECT name FROM permissions ORDER BY name")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+
+        let roles: [(&str, Vec<&str>); 6] = [
+            ("customer", [BANKING, &["webhooks:manage"]].concat()),
+            ("corporate_admin", [BANKING, &["corporate:members:manage", "webhooks:manage"]].concat()),
+            ("corporate_member", vec!["accounts:read", "beneficiaries:read", "cards:read", "transactions:read", "transactions:write"]),
+            ("support", vec!["users:read"]),
+            ("compliance", vec!["audit:read", "users:read"]),
+            ("admin", every.iter().map(String::as_str).collect()),
+        ];
+
+        for (role, mut expected) in roles {
+            expected.sort();
+            let user = create_user(&pool, role).await;
+            revoke_role(&pool, user.id, ROLE_CUSTOMER).await.unwrap();
+            assign_role(&pool, user.id, role, None).await.unwrap();
+
+            let access = get_user_access(&pool, user.id).await.unwrap();
+            assert_eq!(access.roles, [role]);
+            assert_eq!(access.permissions, expected, "permissions of {}", role);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,91 @@
+use crate::models::session::Session;
+use crate::services::database::DbPool;
+use sqlx::Row;
+use uuid::Uuid;
+use chrono::{Duration, Utc};
+
+/// Sessions live as long as the refresh token issued with them.
+pub const SESSION_LIFETIME_HOURS: i64 = 24 * 7;
+
+pub async fn create_session(
+    pool: &DbPool,
+    user_id: Uuid,
+    user_agent: Option<&str>,
+    ip_address: Option<&str>,
+) -> Result<Uuid, sqlx::Error> {
+    let session_id = Uuid::new_v4();
+    let expires_at = Utc::now() + Duration::hours(SESSION_LIFETIME_HOURS);
+
+    sqlx::query(
+        "INSERT INTO sessions (id, user_id, user_agent, ip_address, expires_at) VALUES ($1, $2, $3, $4, $5)"
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .bind(user_agent)
+    .bind(ip_address)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(session_id)
+}
+
+/// Records activity on a session and reports whether it is still usable.
+/// Revoked, expired and unknown sessions all report `false`.
+pub async fn touch_session(
+    pool: &DbPool,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query(
+        "UPDATE sessions SET last_seen_at = NOW() WHERE id = $1 AND revoked_at IS NULL AND expires_at > NOW() RETURNING id"
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.is_some())
+}
+
+pub async fn get_sessions_by_user(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<Vec<Session>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, user_id, user_agent, ip_address, created_at, last_seen_at, expires_at, revoked_at FROM sessions WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW() ORDER BY last_seen_at DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut sessions = Vec::new();
+    for row in rows {
+        sessions.push(Session {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            user_agent: row.try_get("user_agent")?,
+            ip_address: row.try_get("ip_address")?,
+            created_at: row.try_get("created_at")?,
+            last_seen_at: row.try_get("last_seen_at")?,
+            expires_at: row.try_get("expires_at")?,
+            revoked_at: row.try_get("revoked_at")?,
+        });
+    }
+
+    Ok(sessions)
+}
+
+pub async fn revoke_session(
+    pool: &DbPool,
+    session_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL"
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
\ No newline at end of file
@@ -1,15 +1,22 @@
-use crate::models::transaction::{Transaction, SendMoneyRequest, TransferRequest, TransactionResponse, TransactionType, TransactionStatus};
+use crate::models::transaction::{SendMoneyRequest, TransferRequest, TransactionQuery, TransactionResponse, TransactionType, TransactionStatus};
+use crate::models::domain_event::DomainEvent;
+use crate::models::pagination::Page;
 use crate::services::database::DbPool;
-use sqlx::Row;
+use crate::services::outbox_service;
+use crate::utils::pagination::{into_page, push_keyset, Cursor, DEFAULT_PAGE_SIZE};
+use sqlx::{Postgres, QueryBuilder, Row};
 use uuid::Uuid;
 use chrono::Utc;
 
+/// Records a payment from an account of `user_id`, with its domain event.
 pub async fn send_money(
     pool: &DbPool,
+    user_id: Uuid,
     request: SendMoneyRequest,
 ) -> Result<TransactionResponse, sqlx::Error> {
     let transaction_id = Uuid::new_v4();
 
+    let mut tx = pool.begin().await?;
     sqlx::query(
         "INSERT INTO transactions (id, account_id, transaction_type, amount, currency, description, beneficiary_name, beneficiary_iban, status) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
     )
@@ -22,10 +29,10 @@ pub async fn send_money(
     .bind(&request.beneficiary_name)
     .bind(&request.beneficiary_iban)
     .bind("pending")
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
 
-    Ok(TransactionResponse {
+    let transaction = TransactionResponse {
         id: transaction_id,
         account_id: request.account_id,
         transaction_type: TransactionType::Send,
@@ -36,11 +43,17 @@ pub async fn send_money(
         beneficiary_iban: Some(request.beneficiary_iban),
         status: TransactionStatus::Pending,
         created_at: Utc::now(),
-    })
+    };
+    outbox_service::record(&mut *tx, user_id, &DomainEvent::TransactionCreated(&transaction)).await?;
+    tx.commit().await?;
+
+    Ok(transaction)
 }
 
+/// Records a transfer between accounts of `user_id`, with its domain event.
 pub async fn transfer_money(
     pool: &DbPool,
+    user_id: Uuid,
     request: TransferRequest,
 ) -> Result<TransactionResponse, sqlx::Error> {
     let transaction_id = Uuid::new_v4();
@@ -53,6 +66,7 @@ pub async fn transfer_money(
         .and_then(|row| row.try_get("iban").ok())
         .flatten();
 
+    let mut tx = pool.begin().await?;
     sqlx::query(
         "INSERT INTO transactions (id, account_id, transaction_type, amount, currency, description, beneficiary_iban, status) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
     )
@@ -64,10 +78,10 @@ pub async fn transfer_money(
     .bind(&request.description)
     .bind(&beneficiary_iban)
     .bind("pending")
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
 
-    Ok(TransactionResponse {
+    let transaction = TransactionResponse {
         id: transaction_id,
         account_id: request.from_account_id,
         transaction_type: TransactionType::Transfer,
@@ -75,47 +89,144 @@ pub async fn transfer_money(
         currency: request.currency,
         description: request.description,
         beneficiary_name: None,
-        beneficiary_iban: beneficiary_iban,
+        beneficiary_iban,
         status: TransactionStatus::Pending,
         created_at: Utc::now(),
-    })
+    };
+    outbox_service::record(&mut *tx, user_id, &DomainEvent::TransactionCreated(&transaction)).await?;
+    tx.commit().await?;
+
+    Ok(transaction)
+}
+
+/// Whose transactions a listing covers.
+#[derive(Debug, Clone, Copy)]
+pub enum TransactionScope {
+    Account(Uuid),
+    /// Every account of the user
+    User(Uuid),
+}
+
+const SELECT_TRANSACTIONS: &str =
+    "SELECT id, account_id, transaction_type, amount::FLOAT8 AS amount, currency, description, beneficiary_name, beneficiary_iban, status, created_at FROM transactions";
+
+fn push_filters(builder: &mut QueryBuilder<'_, Postgres>, scope: TransactionScope, query: &TransactionQuery) {
+    match scope {
+        TransactionScope::Account(account_id) => {
+            builder.push(" WHERE account_id = ").push_bind(account_id);
+        }
+        TransactionScope::User(user_id) => {
+            builder.push(" WHERE account_id IN (SELECT id FROM accounts WHERE user_id = ").push_bind(user_id).push(")");
+        }
+    }
+
+    if let Some(from) = query.from {
+        builder.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        builder.push(" AND created_at < ").push_bind(to);
+    }
+    if let Some(min_amount) = query.min_amount {
+        builder.push(" AND amount >= ").push_bind(min_amount);
+    }
+    if let Some(max_amount) = query.max_amount {
+        builder.push(" AND amount <= ").push_bind(max_amount);
+    }
+    if let Some(transaction_type) = query.transaction_type {
+        builder.push(" AND transaction_type = ").push_bind(transaction_type);
+    }
+    if let Some(status) = query.status {
+        builder.push(" AND status = ").push_bind(status);
+    }
+    if let Some(currency) = &query.currency {
+        builder.push(" AND currency = ").push_bind(currency.clone());
+    }
+    if let Some(iban) = &query.counterparty_iban {
+        builder.push(" AND beneficiary_iban = ").push_bind(iban.clone());
+    }
+    if let Some(name) = &query.counterparty_name {
+        let pattern = format!("%{}%", name.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        builder.push(" AND beneficiary_name ILIKE ").push_bind(pattern);
+    }
+    if let Some(q) = &query.q {
+        // Same expression as idx_transactions_description_search
+        builder
+            .push(" AND to_tsvector('simple', COALESCE(description, '')) @@ websearch_to_tsquery('simple', ")
+            .push_bind(q.clone())
+            .push(")");
+    }
 }
 
-pub async fn get_transactions_by_account(
+/// Transactions matching `query` by offset, the listing of API v1.
+pub async fn list_transactions_by_offset(
     pool: &DbPool,
-    account_id: Uuid,
-    limit: Option<i64>,
-    offset: Option<i64>,
+    scope: TransactionScope,
+    query: &TransactionQuery,
 ) -> Result<Vec<TransactionResponse>, sqlx::Error> {
-    let limit = limit.unwrap_or(50);
-    let offset = offset.unwrap_or(0);
+    let order = query.sort.unwrap_or_default().order();
+    let direction = if order.descending { "DESC" } else { "ASC" };
+
+    let mut select = QueryBuilder::new(SELECT_TRANSACTIONS);
+    push_filters(&mut select, scope, query);
+    select
+        .push(format!(" ORDER BY {} {}, id {}", order.column, direction, direction))
+        .push(" LIMIT ")
+        .push_bind(query.limit.unwrap_or(DEFAULT_PAGE_SIZE))
+        .push(" OFFSET ")
+        .push_bind(query.offset.unwrap_or(0));
 
-    let rows = sqlx::query(
-        "SELECT id, account_id, transaction_type, amount, currency, description, beneficiary_name, beneficiary_iban, status, created_at FROM transactions WHERE account_id = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3"
+    let rows = select.build().fetch_all(pool).await?;
+    rows.iter().map(transaction_from_row).collect()
+}
+
+/// The page of transactions matching `query` at `cursor`, in the query's sort.
+pub async fn list_transactions(
+    pool: &DbPool,
+    scope: TransactionScope,
+    query: &TransactionQuery,
+    cursor: Option<&Cursor>,
+    limit: i64,
+) -> Result<Page<TransactionResponse>, sqlx::Error> {
+    let sort = query.sort.unwrap_or_default();
+
+    let mut select = QueryBuilder::new(SELECT_TRANSACTIONS);
+    push_filters(&mut select, scope, query);
+    push_keyset(&mut select, sort.order(), cursor, limit);
+
+    let rows = select.build().fetch_all(pool).await?;
+    let transactions = rows.iter().map(transaction_from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok(into_page(transactions, sort.order(), cursor, limit, |transaction| (sort.key(transaction), transaction.id)))
+}
+
+fn transaction_from_row(row: &sqlx::postgres::PgRow) -> Result<TransactionResponse, sqlx::Error> {
+    Ok(TransactionResponse {
+        id: row.try_get("id")?,
+        account_id: row.try_get("account_id")?,
+        transaction_type: row.try_get("transaction_type")?,
+        amount: row.try_get("amount")?,
+        currency: row.try_get("currency")?,
+        description: row.try_get("description")?,
+        beneficiary_name: row.try_get("beneficiary_name")?,
+        beneficiary_iban: row.try_get("beneficiary_iban")?,
+        status: row.try_get("status")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+pub async fn user_owns_transaction(
+    pool: &DbPool,
+    transaction_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT EXISTS(SELECT 1 FROM transactions t JOIN accounts a ON a.id = t.account_id WHERE t.id = $1 AND a.user_id = $2) AS owned"
     )
-    .bind(account_id)
-    .bind(limit)
-    .bind(offset)
-    .fetch_all(pool)
+    .bind(transaction_id)
+    .bind(user_id)
+    .fetch_one(pool)
     .await?;
 
-    let mut transactions = Vec::new();
-    for row in rows {
-        transactions.push(TransactionResponse {
-            id: row.try_get("id")?,
-            account_id: row.try_get("account_id")?,
-            transaction_type: row.try_get("transaction_type")?,
-            amount: row.try_get("amount")?,
-            currency: row.try_get("currency")?,
-            description: row.try_get("description")?,
-            beneficiary_name: row.try_get("beneficiary_name")?,
-            beneficiary_iban: row.try_get("beneficiary_iban")?,
-            status: row.try_get("status")?,
-            created_at: row.try_get("created_at")?,
-        });
-    }
-
-    Ok(transactions)
+    row.try_get("owned")
 }
 
 pub async fn get_transaction(
@@ -123,7 +234,7 @@ pub async fn get_transaction(
     transaction_id: Uuid,
 ) -> Result<Option<TransactionResponse>, sqlx::Error> {
     let row = sqlx::query(
-        "SELECT id, account_id, transaction_type, amount, currency, description, beneficiary_name, beneficiary_iban, status, created_at FROM transactions WHERE id = $1"
+        "SELECT id, account_id, transaction_type, amount::FLOAT8 AS amount, currency, description, beneficiary_name, beneficiary_iban, status, created_at FROM transactions WHERE id = $1"
     )
     .bind(transaction_id)
     .fetch_optional(pool)
@@ -145,4 +256,83 @@ pub async fn get_transaction(
     } else {
         Ok(None)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::transaction::TransactionSort;
+    use validator::Validate;
+    use crate::test_support::{create_account, create_user, test_pool};
+
+    #[test]
+    fn test_page_bounds_are_validated() {
+        for (limit, offset) in [(Some(1), Some(0)), (Some(100), Some(250)), (None, None)] {
+            assert!(TransactionQuery { limit, offset, ..Default::default() }.validate().is_ok());
+        }
+
+        for limit in [-1, 0, 101] {
+            let errors = TransactionQuery { limit: Some(limit), ..Default::default() }.validate().unwrap_err();
+            assert!(errors.field_errors().contains_key("limit"), "limit {}", limit);
+        }
+        let errors = TransactionQuery { offset: Some(-1), ..Default::default() }.validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("offset"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_search_across_accounts_by_page() {
+        let pool = test_pool().await;
+
+        let user = create_user(&pool, "Searcher").await;
+        let mut accounts = Vec::new();
+        for name in ["Main", "Savings"] {
+            accounts.push(create_account(&pool, user.id, name).await.id);
+        }
+
+        for (account_id, amount, description) in [
+            (accounts[0], 12.5, "Loyer de mars"),
+            (accounts[1], 40.0, "Courses"),
+            (accounts[1], 7.25, "Loyer du garage"),
+            (accounts[0], 99.99, "Cadeau"),
+        ] {
+            send_money(&pool, user.id, SendMoneyRequest {
+                account_id,
+                amount,
+                currency: "EUR".to_string(),
+                description: Some(description.to_string()),
+                beneficiary_name: "Jo 100% Bio".to_string(),
+                beneficiary_iban: "DE89370400440532013000".to_string(),
+            })
+            .await
+            .unwrap();
+        }
+
+        let scope = TransactionScope::User(user.id);
+        let rent = TransactionQuery { q: Some("loyer".to_string()), ..Default::default() };
+        let page = list_transactions(&pool, scope, &rent, None, 10).await.unwrap();
+        assert_eq!(page.items.len(), 2);
+        let in_main = list_transactions(&pool, TransactionScope::Account(accounts[0]), &rent, None, 10).await.unwrap();
+        assert_eq!(in_main.items.len(), 1);
+
+        // `%` in a name is matched as itself
+        let name = TransactionQuery { counterparty_name: Some("100%".to_string()), ..Default::default() };
+        assert_eq!(list_transactions(&pool, scope, &name, None, 10).await.unwrap().items.len(), 4);
+        let name = TransactionQuery { counterparty_name: Some("1_0".to_string()), ..Default::default() };
+        assert!(list_transactions(&pool, scope, &name, None, 10).await.unwrap().items.is_empty());
+
+        // Walking the largest amounts two by two, then back
+        let largest = TransactionQuery { sort: Some(TransactionSort::AmountDesc), min_amount: Some(10.0), ..Default::default() };
+        let order = TransactionSort::AmountDesc.order();
+        let first = list_transactions(&pool, scope, &largest, None, 2).await.unwrap();
+        assert_eq!(first.items.iter().map(|t| t.amount).collect::<Vec<_>>(), [99.99, 40.0]);
+        let next = Cursor::decode(first.next_cursor.as_deref().unwrap(), order).unwrap();
+        let second = list_transactions(&pool, scope, &largest, Some(&next), 2).await.unwrap();
+        assert_eq!(second.items.iter().map(|t| t.amount).collect::<Vec<_>>(), [12.5]);
+        assert!(second.next_cursor.is_none());
+        let prev = Cursor::decode(second.prev_cursor.as_deref().unwrap(), order).unwrap();
+        let back = list_transactions(&pool, scope, &largest, Some(&prev), 2).await.unwrap();
+        assert_eq!(back.items.iter().map(|t| t.id).collect::<Vec<_>>(), first.items.iter().map(|t| t.id).collect::<Vec<_>>());
+        assert!(back.prev_cursor.is_none());
+    }
 }
\ No newline at end of file
@@ -1,19 +1,21 @@
-use crate::models::user::{User, CreateUserRequest, UserResponse, UserType};
+use crate::models::user::{User, CreateUserRequest, UserResponse};
 use crate::services::database::DbPool;
-use crate::services::auth_service;
+use crate::services::{auth_service, rbac_service};
+use crate::utils::error::ApiError;
+use argon2::Params;
 use sqlx::Row;
 use uuid::Uuid;
 
 pub async fn create_user(
     pool: &DbPool,
     request: CreateUserRequest,
-) -> Result<UserResponse, sqlx::Error> {
+    password_params: &Params,
+) -> Result<UserResponse, ApiError> {
     // Hash password (for now, use a default password since it's not in the request)
-    let hashed_password = auth_service::hash_password("defaultpassword123")
-        .await
-        .map_err(|_| sqlx::Error::RowNotFound)?; // TODO: Handle this better
+    let hashed_password = auth_service::hash_password("defaultpassword123", password_params).await?;
 
     let user_id = Uuid::new_v4();
+    let mut tx = pool.begin().await?;
 
     sqlx::query(
         "INSERT INTO users (id, email, name, user_type, hashed_password) VALUES ($1, $2, $3, $4, $5)"
@@ -23,9 +25,13 @@ pub async fn create_user(
     .bind(&request.name)
     .bind(format!("{:?}", request.user_type).to_lowercase())
     .bind(&hashed_password)
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
 
+    rbac_service::assign_role(&mut *tx, user_id, rbac_service::default_role(&request.user_type), None).await?;
+
+    tx.commit().await?;
+
     Ok(UserResponse {
         id: user_id,
         email: request.email,
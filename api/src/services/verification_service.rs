@@ -0,0 +1,262 @@
+use crate::services::database::DbPool;
+use crate::services::email_service;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::{PgConnection, Row};
+use uuid::Uuid;
+
+pub const PURPOSE_EMAIL_VERIFICATION: &str = "email_verification";
+pub const PURPOSE_PASSWORD_RESET: &str = "password_reset";
+pub const PURPOSE_ACCOUNT_UNLOCK: &str = "account_unlock";
+
+pub const EMAIL_VERIFICATION_LIFETIME_HOURS: i64 = 24;
+pub const PASSWORD_RESET_LIFETIME_MINUTES: i64 = 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn token_mac(id: Uuid, purpose: &str, secret: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(purpose.as_bytes());
+    mac.update(b":");
+    mac.update(id.as_bytes());
+    mac
+}
+
+/// Formats a token as `<id>.<signature>`. The signature binds the id to its
+/// purpose, so a reset token can't be replayed as a verification token and
+/// guessed ids are rejected before touching the database.
+pub fn sign_token(id: Uuid, purpose: &str, secret: &str) -> String {
+    format!("{}.{}", id.simple(), URL_SAFE_NO_PAD.encode(token_mac(id, purpose, secret).finalize().into_bytes()))
+}
+
+/// Returns the token id if the signature is valid for `purpose`.
+pub fn parse_token(token: &str, purpose: &str, secret: &str) -> Option<Uuid> {
+    let (id, sig) = token.split_once('.')?;
+    let id = Uuid::parse_str(id).ok()?;
+    let sig = URL_SAFE_NO_PAD.decode(sig).ok()?;
+
+    token_mac(id, purpose, secret).verify_slice(&sig).ok()?;
+
+    Some(id)
+}
+
+/// Creates a single-use token, invalidating any earlier unused token for the
+/// same user and purpose so only the latest email works.
+pub async fn issue_token(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    purpose: &str,
+    lifetime: Duration,
+    secret: &str,
+) -> Result<String, sqlx::Error> {
+    sqlx::query("UPDATE action_tokens SET used_at = NOW() WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL")
+        .bind(user_id)
+        .bind(purpose)
+        .execute(&mut *conn)
+        .await?;
+
+    let id = Uuid::new_v4();
+    sqlx::query("INSERT INTO action_tokens (id, user_id, purpose, expires_at) VALUES ($1, $2, $3, $4)")
+        .bind(id)
+        .bind(user_id)
+        .bind(purpose)
+        .bind(Utc::now() + lifetime)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(sign_token(id, purpose, secret))
+}
+
+/// Marks a token as used and returns its user. Tampered, expired, already
+/// used and unknown tokens all return `None`.
+pub async fn consume_token(
+    conn: &mut PgConnection,
+    token: &str,
+    purpose: &str,
+    secret: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let id = match parse_token(token, purpose, secret) {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+
+    let row = sqlx::query(
+        "UPDATE action_tokens SET used_at = NOW() WHERE id = $1 AND purpose = $2 AND used_at IS NULL AND expires_at > NOW() RETURNING user_id"
+    )
+    .bind(id)
+    .bind(purpose)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match row {
+        Some(row) => Ok(Some(row.try_get("user_id")?)),
+        None => Ok(None),
+    }
+}
+
+/// Queues a verification email for the user. Returns `false` if the address
+/// is already verified.
+pub async fn send_email_verification(
+    pool: &DbPool,
+    user_id: Uuid,
+    secret: &str,
+    app_base_url: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let row = sqlx::query("SELECT email, name FROM users WHERE id = $1 AND email_verified_at IS NULL FOR UPDATE")
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(false),
+    };
+    let email: String = row.try_get("email")?;
+    let name: String = row.try_get("name")?;
+
+    let token = issue_token(
+        &mut tx,
+        user_id,
+        PURPOSE_EMAIL_VERIFICATION,
+        Duration::hours(EMAIL_VERIFICATION_LIFETIME_HOURS),
+        secret,
+    )
+    .await?;
+
+    let body = format!(
+        "Hello {},\n\nPlease confirm your email address for Vaelix Bank:\n\n{}/verify-email?token={}\n\nThis link expires in {} hours.\n",
+        name, app_base_url, token, EMAIL_VERIFICATION_LIFETIME_HOURS
+    );
+    email_service::enqueue_email(&mut *tx, &email, "Confirm your email address", &body).await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn verify_email(
+    pool: &DbPool,
+    token: &str,
+    secret: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let user_id = match consume_token(&mut tx, token, PURPOSE_EMAIL_VERIFICATION, secret).await? {
+        Some(user_id) => user_id,
+        None => return Ok(false),
+    };
+
+    sqlx::query("UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Queues a reset link if the address belongs to a user. Unknown addresses
+/// are silently ignored so the caller can't tell them apart.
+pub async fn send_password_reset(
+    pool: &DbPool,
+    email: &str,
+    secret: &str,
+    app_base_url: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let row = sqlx::query("SELECT id, name FROM users WHERE email = $1")
+        .bind(email)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(()),
+    };
+    let user_id: Uuid = row.try_get("id")?;
+    let name: String = row.try_get("name")?;
+
+    let token = issue_token(
+        &mut tx,
+        user_id,
+        PURPOSE_PASSWORD_RESET,
+        Duration::minutes(PASSWORD_RESET_LIFETIME_MINUTES),
+        secret,
+    )
+    .await?;
+
+    let body = format!(
+        "Hello {},\n\nA password reset was requested for your Vaelix Bank account:\n\n{}/reset-password?token={}\n\nThis link expires in {} minutes. If you didn't ask for it, you can ignore this email.\n",
+        name, app_base_url, token, PASSWORD_RESET_LIFETIME_MINUTES
+    );
+    email_service::enqueue_email(&mut *tx, email, "Reset your password", &body).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Sets a new password from a reset token and revokes every session of the
+/// user. Returns the revoked session ids so the caller can evict them from
+/// the session cache, or `None` if the token was not accepted.
+pub async fn reset_password(
+    pool: &DbPool,
+    token: &str,
+    hashed_password: &str,
+    secret: &str,
+) -> Result<Option<Vec<Uuid>>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let user_id = match consume_token(&mut tx, token, PURPOSE_PASSWORD_RESET, secret).await? {
+        Some(user_id) => user_id,
+        None => return Ok(None),
+    };
+
+    // Receiving the reset email also proves ownership of the address, and
+    // a fresh password lifts any brute-force lockout
+    sqlx::query(
+        "UPDATE users SET hashed_password = $2, email_verified_at = COALESCE(email_verified_at, NOW()), failed_login_attempts = 0, locked_until = NULL, updated_at = NOW() WHERE id = $1"
+    )
+    .bind(user_id)
+    .bind(hashed_password)
+    .execute(&mut *tx)
+    .await?;
+
+    let rows = sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL RETURNING id")
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    let mut revoked = Vec::new();
+    for row in rows {
+        revoked.push(row.try_get("id")?);
+    }
+
+    tx.commit().await?;
+
+    Ok(Some(revoked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_signature_roundtrip() {
+        let id = Uuid::new_v4();
+        let token = sign_token(id, PURPOSE_PASSWORD_RESET, "secret");
+
+        assert_eq!(parse_token(&token, PURPOSE_PASSWORD_RESET, "secret"), Some(id));
+        assert_eq!(parse_token(&token, PURPOSE_EMAIL_VERIFICATION, "secret"), None);
+        assert_eq!(parse_token(&token, PURPOSE_PASSWORD_RESET, "other-secret"), None);
+
+        let forged = format!("{}.{}", Uuid::new_v4().simple(), token.split_once('.').unwrap().1);
+        assert_eq!(parse_token(&forged, PURPOSE_PASSWORD_RESET, "secret"), None);
+    }
+}
\ No newline at end of file
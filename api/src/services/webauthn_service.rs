@@ -0,0 +1,190 @@
+use crate::models::webauthn::WebauthnCredential;
+use crate::services::database::DbPool;
+use sqlx::Row;
+use uuid::Uuid;
+use chrono::{Duration, Utc};
+
+/// How long a client has to complete a ceremony, also sent as the `timeout` option.
+pub const CEREMONY_TIMEOUT_SECONDS: i64 = 300;
+
+pub const CEREMONY_REGISTRATION: &str = "registration";
+pub const CEREMONY_AUTHENTICATION: &str = "authentication";
+pub const CEREMONY_STEP_UP: &str = "step_up";
+
+pub async fn create_challenge(
+    pool: &DbPool,
+    user_id: Option<Uuid>,
+    ceremony: &str,
+    challenge: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let ceremony_id = Uuid::new_v4();
+    let expires_at = Utc::now() + Duration::seconds(CEREMONY_TIMEOUT_SECONDS);
+
+    // Opportunistically clear abandoned ceremonies
+    sqlx::query("DELETE FROM webauthn_challenges WHERE expires_at < NOW()")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        "INSERT INTO webauthn_challenges (id, user_id, ceremony, challenge, expires_at) VALUES ($1, $2, $3, $4, $5)"
+    )
+    .bind(ceremony_id)
+    .bind(user_id)
+    .bind(ceremony)
+    .bind(challenge)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(ceremony_id)
+}
+
+/// Consumes a pending challenge so each one can only be answered once.
+/// Returns the user the ceremony was started for (if any) and the challenge.
+pub async fn take_challenge(
+    pool: &DbPool,
+    ceremony_id: Uuid,
+    ceremony: &str,
+) -> Result<Option<(Option<Uuid>, String)>, sqlx::Error> {
+    let row = sqlx::query(
+        "DELETE FROM webauthn_challenges WHERE id = $1 AND ceremony = $2 AND expires_at > NOW() RETURNING user_id, challenge"
+    )
+    .bind(ceremony_id)
+    .bind(ceremony)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(row) => Ok(Some((row.try_get("user_id")?, row.try_get("challenge")?))),
+        None => Ok(None),
+    }
+}
+
+pub async fn add_credential(
+    pool: &DbPool,
+    user_id: Uuid,
+    credential_id: &str,
+    public_key: &[u8],
+    sign_count: i64,
+    name: &str,
+) -> Result<WebauthnCredential, sqlx::Error> {
+    let id = Uuid::new_v4();
+
+    sqlx::query(
+        "INSERT INTO webauthn_credentials (id, user_id, credential_id, public_key, sign_count, name) VALUES ($1, $2, $3, $4, $5, $6)"
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(credential_id)
+    .bind(public_key)
+    .bind(sign_count)
+    .bind(name)
+    .execute(pool)
+    .await?;
+
+    Ok(WebauthnCredential {
+        id,
+        user_id,
+        credential_id: credential_id.to_string(),
+        public_key: public_key.to_vec(),
+        sign_count,
+        name: name.to_string(),
+        created_at: Utc::now(),
+        last_used_at: None,
+    })
+}
+
+pub async fn get_credentials_by_user(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<Vec<WebauthnCredential>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, user_id, credential_id, public_key, sign_count, name, created_at, last_used_at FROM webauthn_credentials WHERE user_id = $1 ORDER BY created_at"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut credentials = Vec::new();
+    for row in rows {
+        credentials.push(credential_from_row(&row)?);
+    }
+
+    Ok(credentials)
+}
+
+pub async fn get_credential(
+    pool: &DbPool,
+    credential_id: &str,
+) -> Result<Option<WebauthnCredential>, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT id, user_id, credential_id, public_key, sign_count, name, created_at, last_used_at FROM webauthn_credentials WHERE credential_id = $1"
+    )
+    .bind(credential_id)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(row) => Ok(Some(credential_from_row(&row)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn has_credentials(
+    pool: &DbPool,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT EXISTS(SELECT 1 FROM webauthn_credentials WHERE user_id = $1) AS present")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    row.try_get("present")
+}
+
+/// Stores the new signature counter. The update only applies if the counter
+/// hasn't moved since it was read, so two concurrent assertions can't both win.
+pub async fn record_use(
+    pool: &DbPool,
+    id: Uuid,
+    previous_sign_count: i64,
+    sign_count: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE webauthn_credentials SET sign_count = $3, last_used_at = NOW() WHERE id = $1 AND sign_count = $2"
+    )
+    .bind(id)
+    .bind(previous_sign_count)
+    .bind(sign_count)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_credential(
+    pool: &DbPool,
+    id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM webauthn_credentials WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+fn credential_from_row(row: &sqlx::postgres::PgRow) -> Result<WebauthnCredential, sqlx::Error> {
+    Ok(WebauthnCredential {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        credential_id: row.try_get("credential_id")?,
+        public_key: row.try_get("public_key")?,
+        sign_count: row.try_get("sign_count")?,
+        name: row.try_get("name")?,
+        created_at: row.try_get("created_at")?,
+        last_used_at: row.try_get("last_used_at")?,
+    })
+}
\ No newline at end of file
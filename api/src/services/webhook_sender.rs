@@ -0,0 +1,210 @@
+use crate::config::app_config::AppConfig;
+use anyhow::bail;
+use async_trait::async_trait;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::CONTENT_TYPE;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use url::{Host, Url};
+
+/// A webhook request, with the headers added to the JSON content type.
+#[derive(Debug, Clone)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Posts webhook requests and returns the HTTP status answered. Errors are
+/// for requests that got no answer at all.
+#[async_trait]
+pub trait WebhookSender: Send + Sync {
+    async fn post(&self, request: &WebhookRequest) -> Result<u16, anyhow::Error>;
+}
+
+/// Writes every request to the application log and reports it delivered.
+pub struct LogWebhookSender;
+
+#[async_trait]
+impl WebhookSender for LogWebhookSender {
+    async fn post(&self, request: &WebhookRequest) -> Result<u16, anyhow::Error> {
+        tracing::info!(url = %request.url, "webhook:\n{}", String::from_utf8_lossy(&request.body));
+        Ok(200)
+    }
+}
+
+/// Sends over HTTP(S) with `reqwest`. Redirects are not followed. Unless
+/// allowed, hosts resolving only to loopback, private or link-local
+/// addresses are refused: the resolver hands the client public addresses
+/// only, so the address checked is the one connected to and DNS can't swap
+/// it in between.
+pub struct HttpWebhookSender {
+    client: reqwest::Client,
+    allow_private_targets: bool,
+}
+
+impl HttpWebhookSender {
+    pub fn new(timeout: Duration, allow_private_targets: bool) -> Self {
+        // A proxy would resolve the host itself, past the address check
+        let mut builder = reqwest::Client::builder()
+            .user_agent("Vaelix-Webhooks/1.0")
+            .redirect(reqwest::redirect::Policy::none())
+            .connect_timeout(timeout)
+            .timeout(timeout)
+            .no_proxy();
+        if !allow_private_targets {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+
+        let client = builder.build().expect("webhook HTTP client builds");
+        Self { client, allow_private_targets }
+    }
+}
+
+#[async_trait]
+impl WebhookSender for HttpWebhookSender {
+    async fn post(&self, request: &WebhookRequest) -> Result<u16, anyhow::Error> {
+        let url = Url::parse(&request.url)?;
+
+        // Addresses in the URL are connected to without being resolved
+        let literal: Option<IpAddr> = match url.host() {
+            Some(Host::Ipv4(ip)) => Some(ip.into()),
+            Some(Host::Ipv6(ip)) => Some(ip.into()),
+            _ => None,
+        };
+        if !self.allow_private_targets && literal.is_some_and(|ip| !is_public(ip)) {
+            bail!("{} has no public address", url.host_str().unwrap_or_default());
+        }
+
+        let mut builder = self.client.post(url).header(CONTENT_TYPE, "application/json");
+        for (name, value) in &request.headers {
+            builder = builder.header(*name, value);
+        }
+
+        let response = builder.body(request.body.clone()).send().await?;
+        Ok(response.status().as_u16())
+    }
+}
+
+/// Resolves with the system resolver, keeping the public addresses only.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|address| is_public(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether an address is reachable on the internet, rather than on the
+/// bank's own network or machine.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // Shared address space (carrier-grade NAT) and 0.0.0.0/8
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public(mapped.into()),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local fc00::/7 and link-local fe80::/10
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+pub fn webhook_sender_from_config(config: &AppConfig) -> Arc<dyn WebhookSender> {
+    match config.webhook_sender.as_str() {
+        "log" => Arc::new(LogWebhookSender),
+        _ => Arc::new(HttpWebhookSender::new(
+            Duration::from_secs(config.webhook_timeout_seconds),
+            config.webhook_allow_private_targets,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_public_addresses() {
+        for ip in ["8.8.8.8", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["127.0.0.1", "10.1.2.3", "192.168.0.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:10.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_sender_posts_and_refuses_private_targets() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let receiver = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for answer in ["202 Accepted", "307 Temporary Redirect\r\nLocation: http://example.com/"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut received = Vec::new();
+                let mut buffer = [0; 1024];
+                while !received.ends_with(b"\r\n\r\n{}") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    assert!(read > 0, "connection closed early");
+                    received.extend_from_slice(&buffer[..read]);
+                }
+                write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", answer).unwrap();
+                requests.push(String::from_utf8(received).unwrap());
+            }
+            requests
+        });
+
+        let request = |host: &str| WebhookRequest {
+            url: format!("http://{}:{}/hooks?source=test", host, port),
+            headers: vec![("Vaelix-Event-Id", "evt".to_string())],
+            body: b"{}".to_vec(),
+        };
+        let guarded = HttpWebhookSender::new(Duration::from_secs(5), false);
+        for host in ["127.0.0.1", "localhost"] {
+            let refused = guarded.post(&request(host)).await.unwrap_err();
+            assert!(format!("{:#}", refused).contains("no public address"), "{:#}", refused);
+        }
+
+        let sender = HttpWebhookSender::new(Duration::from_secs(5), true);
+        assert_eq!(sender.post(&request("127.0.0.1")).await.unwrap(), 202);
+        // Redirects are answers, not followed
+        assert_eq!(sender.post(&request("127.0.0.1")).await.unwrap(), 307);
+
+        let received = receiver.join().unwrap();
+        assert!(received[0].starts_with("POST /hooks?source=test HTTP/1.1\r\n"), "{}", received[0]);
+        assert!(received[0].to_lowercase().contains("vaelix-event-id: evt\r\n"));
+        assert!(received[0].to_lowercase().contains("content-type: application/json\r\n"));
+    }
+}
\ No newline at end of file
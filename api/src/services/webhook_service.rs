@@ -0,0 +1,650 @@
+use crate::models::domain_event::OutboxEvent;
+use crate::models::pagination::Page;
+use crate::models::webhook::{event_permission, CreateWebhookRequest, WebhookDelivery, WebhookEvent, WebhookSubscription};
+use crate::services::database::DbPool;
+use crate::services::email_service;
+use crate::services::encryption_service::EncryptionService;
+use crate::services::outbox_service::OutboxHandler;
+use crate::services::webhook_sender::{WebhookRequest, WebhookSender};
+use crate::utils::pagination::{into_page, push_keyset, time_key, Cursor, Order};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use sqlx::{QueryBuilder, Row};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Secrets look like `whsec_<secret>`; the whole string is the HMAC key.
+pub const SECRET_PREFIX: &str = "whsec";
+
+const SECRET_BYTES: usize = 32;
+
+/// Attempts of a delivery before it is marked failed.
+pub const MAX_ATTEMPTS: i32 = 10;
+
+/// Wait before the first retry, doubled after each failed attempt up to
+/// `MAX_RETRY_DELAY_SECONDS`; ten attempts span about sixteen hours.
+const FIRST_RETRY_DELAY_SECONDS: i64 = 60;
+const MAX_RETRY_DELAY_SECONDS: i64 = 6 * 3600;
+
+/// A subscription is disabled once this many attempts in a row failed,
+/// over at least `DISABLE_AFTER_HOURS`, so an endpoint down for a short
+/// while never gets disabled.
+pub const DISABLE_AFTER_FAILURES: i32 = 10;
+pub const DISABLE_AFTER_HOURS: i32 = 24;
+
+/// Claimed deliveries are not picked again by other workers for this long,
+/// which must outlast an attempt (at most three times the 60 second
+/// timeout allowed).
+const LEASE_SECONDS: i32 = 300;
+
+/// Longest error kept on a delivery.
+const MAX_ERROR_LENGTH: usize = 500;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error("secret encryption failed: {0}")]
+    Encryption(String),
+}
+
+const SELECT_SUBSCRIPTIONS: &str =
+    "SELECT id, url, description, event_types, client_id, consecutive_failures, disabled_at, disabled_reason, created_at FROM webhook_subscriptions";
+
+const SELECT_DELIVERIES: &str =
+    "SELECT id, event_id, (SELECT event_type FROM webhook_events e WHERE e.id = event_id) AS event_type, status, attempts, response_status, last_error, last_attempt_at, CASE WHEN status = 'pending' THEN next_attempt_at END AS next_attempt_at, redelivery_of, created_at, delivered_at FROM webhook_deliveries";
+
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    format!("{}_{}", SECRET_PREFIX, URL_SAFE_NO_PAD.encode(secret))
+}
+
+/// Hex HMAC-SHA256 of `<timestamp>.<body>`, as in `Vaelix-Signature`.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Wait before the attempt following the `attempts`-th.
+pub fn retry_delay(attempts: i32) -> chrono::Duration {
+    let doublings = (attempts - 1).clamp(0, 20) as u32;
+    chrono::Duration::seconds((FIRST_RETRY_DELAY_SECONDS << doublings).min(MAX_RETRY_DELAY_SECONDS))
+}
+
+/// Relays domain events to the webhooks: records each one for the
+/// subscriptions of its user under the id of the domain event, so an event
+/// relayed twice is queued once, and keeps that id in the webhook body.
+pub struct WebhookOutboxHandler;
+
+#[async_trait]
+impl OutboxHandler for WebhookOutboxHandler {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn handle(&self, pool: &DbPool, event: &OutboxEvent) -> Result<(), anyhow::Error> {
+        // Only the types offered to subscribers
+        if event_permission(&event.event_type).is_none() {
+            return Ok(());
+        }
+
+        sqlx::query("INSERT INTO webhook_events (id, user_id, event_type, data, created_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO NOTHING")
+            .bind(event.id)
+            .bind(event.user_id)
+            .bind(&event.event_type)
+            .bind(&event.data)
+            .bind(event.created_at)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Stores a subscription and returns it with its secret, which is only kept
+/// encrypted.
+pub async fn create_subscription(
+    pool: &DbPool,
+    encryption: &EncryptionService,
+    user_id: Uuid,
+    client_id: Option<&str>,
+    request: CreateWebhookRequest,
+) -> Result<(WebhookSubscription, String), WebhookError> {
+    let secret = generate_secret();
+    let encrypted_secret = encryption
+        .encrypt(&secret)
+        .map_err(|e| WebhookError::Encryption(e.to_string()))?;
+
+    let row = sqlx::query(
+        "INSERT INTO webhook_subscriptions (user_id, client_id, url, description, event_types, encrypted_secret) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id, url, description, event_types, client_id, consecutive_failures, disabled_at, disabled_reason, created_at"
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .bind(&request.url)
+    .bind(&request.description)
+    .bind(&request.event_types)
+    .bind(&encrypted_secret)
+    .fetch_one(pool)
+    .await?;
+
+    Ok((subscription_from_row(&row)?, secret))
+}
+
+/// Subscriptions of a user made by `client_id`, or by the bank's own apps
+/// and API keys when `None`.
+pub async fn get_subscriptions(
+    pool: &DbPool,
+    user_id: Uuid,
+    client_id: Option<&str>,
+) -> Result<Vec<WebhookSubscription>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "{} WHERE user_id = $1 AND client_id IS NOT DISTINCT FROM $2 ORDER BY created_at DESC",
+        SELECT_SUBSCRIPTIONS
+    ))
+    .bind(user_id)
+    .bind(client_id)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter().map(subscription_from_row).collect()
+}
+
+pub async fn get_subscription(
+    pool: &DbPool,
+    id: Uuid,
+    user_id: Uuid,
+    client_id: Option<&str>,
+) -> Result<Option<WebhookSubscription>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "{} WHERE id = $1 AND user_id = $2 AND client_id IS NOT DISTINCT FROM $3",
+        SELECT_SUBSCRIPTIONS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .bind(client_id)
+    .fetch_optional(pool)
+    .await?;
+
+    row.as_ref().map(subscription_from_row).transpose()
+}
+
+pub async fn delete_subscription(
+    pool: &DbPool,
+    id: Uuid,
+    user_id: Uuid,
+    client_id: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM webhook_subscriptions WHERE id = $1 AND user_id = $2 AND client_id IS NOT DISTINCT FROM $3")
+        .bind(id)
+        .bind(user_id)
+        .bind(client_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Re-enables a subscription with a clean failure count. Deliveries still
+/// pending from before it was disabled are sent again right away.
+pub async fn enable_subscription(
+    pool: &DbPool,
+    id: Uuid,
+    user_id: Uuid,
+    client_id: Option<&str>,
+) -> Result<Option<WebhookSubscription>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let row = sqlx::query(
+        "UPDATE webhook_subscriptions SET disabled_at = NULL, disabled_reason = NULL, consecutive_failures = 0, failing_since = NULL WHERE id = $1 AND user_id = $2 AND client_id IS NOT DISTINCT FROM $3 RETURNING id, url, description, event_types, client_id, consecutive_failures, disabled_at, disabled_reason, created_at"
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(client_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    sqlx::query("UPDATE webhook_deliveries SET next_attempt_at = NOW() WHERE subscription_id = $1 AND status = 'pending'")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(subscription_from_row(&row)?))
+}
+
+/// The page of deliveries of a subscription at `cursor`, newest first.
+pub async fn list_deliveries(
+    pool: &DbPool,
+    subscription_id: Uuid,
+    cursor: Option<&Cursor>,
+    limit: i64,
+) -> Result<Page<WebhookDelivery>, sqlx::Error> {
+    let mut select = QueryBuilder::new(SELECT_DELIVERIES);
+    select.push(" WHERE subscription_id = ").push_bind(subscription_id);
+    push_keyset(&mut select, Order::NEWEST, cursor, limit);
+
+    let rows = select.build().fetch_all(pool).await?;
+    let deliveries = rows.iter().map(delivery_from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok(into_page(deliveries, Order::NEWEST, cursor, limit, |delivery| (time_key(delivery.created_at), delivery.id)))
+}
+
+/// Queues the event of a delivery again, as a new delivery of the same
+/// subscription. It goes out once the subscription is enabled.
+pub async fn redeliver(
+    pool: &DbPool,
+    subscription_id: Uuid,
+    delivery_id: Uuid,
+) -> Result<Option<WebhookDelivery>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "WITH redelivery AS (INSERT INTO webhook_deliveries (subscription_id, event_id, redelivery_of) SELECT subscription_id, event_id, id FROM webhook_deliveries WHERE id = $1 AND subscription_id = $2 RETURNING *) {}redelivery",
+        SELECT_DELIVERIES.trim_end_matches("webhook_deliveries")
+    ))
+    .bind(delivery_id)
+    .bind(subscription_id)
+    .fetch_optional(pool)
+    .await?;
+
+    row.as_ref().map(delivery_from_row).transpose()
+}
+
+/// A delivery claimed by a worker, with what it takes to send it.
+struct DueDelivery {
+    id: Uuid,
+    subscription_id: Uuid,
+    attempts: i32,
+    url: String,
+    encrypted_secret: String,
+    event: WebhookEvent,
+}
+
+/// Sends up to `batch_size` due deliveries of enabled subscriptions, side by
+/// side, and returns how many were delivered. Deliveries are leased rather
+/// than locked for the length of the requests, so workers can share them.
+pub async fn dispatch_due(
+    pool: &DbPool,
+    sender: &Arc<dyn WebhookSender>,
+    encryption: &EncryptionService,
+    batch_size: i64,
+) -> Result<usize, WebhookError> {
+    let rows = sqlx::query(
+        "UPDATE webhook_deliveries d SET next_attempt_at = NOW() + make_interval(secs => $2) FROM webhook_subscriptions s, webhook_events e WHERE d.id IN (SELECT due.id FROM webhook_deliveries due JOIN webhook_subscriptions ds ON ds.id = due.subscription_id WHERE due.status = 'pending' AND due.next_attempt_at <= NOW() AND ds.disabled_at IS NULL ORDER BY due.next_attempt_at LIMIT $1 FOR UPDATE OF due SKIP LOCKED) AND s.id = d.subscription_id AND e.id = d.event_id RETURNING d.id, d.subscription_id, d.attempts, s.url, s.encrypted_secret, e.id AS event_id, e.event_type, e.data, e.created_at AS event_created_at"
+    )
+    .bind(batch_size)
+    .bind(LEASE_SECONDS)
+    .fetch_all(pool)
+    .await?;
+
+    let mut requests = JoinSet::new();
+    for row in rows {
+        let delivery = DueDelivery {
+            id: row.try_get("id")?,
+            subscription_id: row.try_get("subscription_id")?,
+            attempts: row.try_get::<i32, _>("attempts")? + 1,
+            url: row.try_get("url")?,
+            encrypted_secret: row.try_get("encrypted_secret")?,
+            event: WebhookEvent {
+                id: row.try_get("event_id")?,
+                event_type: row.try_get("event_type")?,
+                created_at: row.try_get("event_created_at")?,
+                data: row.try_get("data")?,
+            },
+        };
+        // A secret that can't be decrypted fails its own delivery only, so the
+        // rest of the batch is still sent and its leases released
+        let secret = match encryption.decrypt(&delivery.encrypted_secret).map_err(|e| e.to_string()) {
+            Ok(secret) => secret,
+            Err(e) => {
+                tracing::warn!(delivery_id = %delivery.id, subscription_id = %delivery.subscription_id, "webhook secret not decrypted: {}", e);
+                record_failure(pool, &delivery, None, "signing secret could not be decrypted").await?;
+                continue;
+            }
+        };
+        let request = signed_request(&delivery, &secret, Utc::now());
+
+        let sender = sender.clone();
+        requests.spawn(async move {
+            let outcome = sender.post(&request).await;
+            (delivery, outcome)
+        });
+    }
+
+    let mut delivered = 0;
+    while let Some(joined) = requests.join_next().await {
+        let Ok((delivery, outcome)) = joined else {
+            continue;
+        };
+        let (response_status, error) = match outcome {
+            Ok(status) if (200..300).contains(&status) => (Some(status), None),
+            Ok(status) => (Some(status), Some(format!("HTTP {}", status))),
+            Err(e) => (None, Some(format!("{:#}", e))),
+        };
+
+        match error {
+            None => {
+                record_success(pool, &delivery, response_status).await?;
+                delivered += 1;
+            }
+            Some(error) => record_failure(pool, &delivery, response_status, &error).await?,
+        }
+    }
+
+    Ok(delivered)
+}
+
+fn signed_request(delivery: &DueDelivery, secret: &str, now: DateTime<Utc>) -> WebhookRequest {
+    let body = serde_json::to_vec(&delivery.event).expect("events serialize to JSON");
+    let timestamp = now.timestamp();
+
+    WebhookRequest {
+        url: delivery.url.clone(),
+        headers: vec![
+            ("Vaelix-Event-Id", delivery.event.id.to_string()),
+            ("Vaelix-Event-Type", delivery.event.event_type.clone()),
+            ("Vaelix-Delivery-Id", delivery.id.to_string()),
+            ("Vaelix-Signature", format!("t={},v1={}", timestamp, sign(secret, timestamp, &body))),
+        ],
+        body,
+    }
+}
+
+async fn record_success(pool: &DbPool, delivery: &DueDelivery, response_status: Option<u16>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE webhook_deliveries SET status = 'delivered', attempts = $2, response_status = $3, last_error = NULL, last_attempt_at = NOW(), delivered_at = NOW() WHERE id = $1"
+    )
+    .bind(delivery.id)
+    .bind(delivery.attempts)
+    .bind(response_status.map(i32::from))
+    .execute(pool)
+    .await?;
+
+    sqlx::query("UPDATE webhook_subscriptions SET consecutive_failures = 0, failing_since = NULL WHERE id = $1")
+        .bind(delivery.subscription_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Schedules the next attempt, or gives up on the delivery, and disables the
+/// subscription once it kept failing for long enough, telling its owner.
+async fn record_failure(
+    pool: &DbPool,
+    delivery: &DueDelivery,
+    response_status: Option<u16>,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let status = if delivery.attempts >= MAX_ATTEMPTS { "failed" } else { "pending" };
+    let error: String = error.chars().take(MAX_ERROR_LENGTH).collect();
+
+    sqlx::query(
+        "UPDATE webhook_deliveries SET status = $2, attempts = $3, response_status = $4, last_error = $5, last_attempt_at = NOW(), next_attempt_at = NOW() + make_interval(secs => $6) WHERE id = $1"
+    )
+    .bind(delivery.id)
+    .bind(status)
+    .bind(delivery.attempts)
+    .bind(response_status.map(i32::from))
+    .bind(&error)
+    .bind(retry_delay(delivery.attempts).num_seconds() as f64)
+    .execute(pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    let disabled = sqlx::query(
+        "UPDATE webhook_subscriptions s SET consecutive_failures = s.consecutive_failures + 1, failing_since = COALESCE(s.failing_since, NOW()), disabled_at = NOW(), disabled_reason = $2 FROM users u WHERE s.id = $1 AND u.id = s.user_id AND s.disabled_at IS NULL AND s.consecutive_failures + 1 >= $3 AND s.failing_since <= NOW() - make_interval(hours => $4) RETURNING s.url, u.email"
+    )
+    .bind(delivery.subscription_id)
+    .bind(format!("{} failed attempts in a row, the last one: {}", DISABLE_AFTER_FAILURES, error))
+    .bind(DISABLE_AFTER_FAILURES)
+    .bind(DISABLE_AFTER_HOURS)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    match disabled {
+        Some(row) => {
+            let url: String = row.try_get("url")?;
+            let email: String = row.try_get("email")?;
+            tracing::warn!(subscription_id = %delivery.subscription_id, "webhook to {} disabled after repeated failures", url);
+            email_service::enqueue_email(
+                &mut *tx,
+                &email,
+                "Webhook disabled",
+                &format!(
+                    "Deliveries to your webhook {} failed for more than {} hours, so it has been disabled. Once the endpoint is fixed, enable it again; events received since will then be sent.",
+                    url, DISABLE_AFTER_HOURS
+                ),
+            )
+            .await?;
+        }
+        None => {
+            sqlx::query("UPDATE webhook_subscriptions SET consecutive_failures = consecutive_failures + 1, failing_since = COALESCE(failing_since, NOW()) WHERE id = $1")
+                .bind(delivery.subscription_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Background loop sending due deliveries every `interval`.
+pub fn spawn_delivery_worker(pool: DbPool, sender: Arc<dyn WebhookSender>, encryption: EncryptionService, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = dispatch_due(&pool, &sender, &encryption, 50).await {
+                tracing::warn!("webhook dispatch failed: {}", e);
+            }
+        }
+    });
+}
+
+fn subscription_from_row(row: &sqlx::postgres::PgRow) -> Result<WebhookSubscription, sqlx::Error> {
+    Ok(WebhookSubscription {
+        id: row.try_get("id")?,
+        url: row.try_get("url")?,
+        description: row.try_get("description")?,
+        event_types: row.try_get("event_types")?,
+        client_id: row.try_get("client_id")?,
+        consecutive_failures: row.try_get("consecutive_failures")?,
+        disabled_at: row.try_get("disabled_at")?,
+        disabled_reason: row.try_get("disabled_reason")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn delivery_from_row(row: &sqlx::postgres::PgRow) -> Result<WebhookDelivery, sqlx::Error> {
+    Ok(WebhookDelivery {
+        id: row.try_get("id")?,
+        event_id: row.try_get("event_id")?,
+        event_type: row.try_get("event_type")?,
+        status: row.try_get("status")?,
+        attempts: row.try_get("attempts")?,
+        response_status: row.try_get("response_status")?,
+        last_error: row.try_get("last_error")?,
+        last_attempt_at: row.try_get("last_attempt_at")?,
+        next_attempt_at: row.try_get("next_attempt_at")?,
+        redelivery_of: row.try_get("redelivery_of")?,
+        created_at: row.try_get("created_at")?,
+        delivered_at: row.try_get("delivered_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::domain_event::event_types;
+    use crate::models::webhook::DeliveryStatus;
+    use crate::test_support::{create_user, test_pool};
+
+    /// Answers every request with the same status.
+    struct StatusSender(u16);
+
+    #[async_trait]
+    impl WebhookSender for StatusSender {
+        async fn post(&self, _request: &WebhookRequest) -> Result<u16, anyhow::Error> {
+            Ok(self.0)
+        }
+    }
+
+    /// Relays an event to the webhooks as the outbox would.
+    async fn relay(pool: &DbPool, user_id: Uuid, event_type: &str, data: serde_json::Value) -> OutboxEvent {
+        let event = OutboxEvent { id: Uuid::new_v4(), user_id, event_type: event_type.to_string(), data, created_at: Utc::now(), attempts: 0 };
+        WebhookOutboxHandler.handle(pool, &event).await.unwrap();
+        event
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_retries_disable_and_redelivery() {
+        let pool = test_pool().await;
+        let encryption = EncryptionService::new(&"ab".repeat(32)).unwrap();
+        let failing: Arc<dyn WebhookSender> = Arc::new(StatusSender(503));
+        let working: Arc<dyn WebhookSender> = Arc::new(StatusSender(204));
+
+        let user = create_user(&pool, "Subscriber").await;
+        let request = CreateWebhookRequest {
+            url: "https://example.com/hooks".to_string(),
+            event_types: vec![event_types::BENEFICIARY_CREATED.to_string()],
+            description: None,
+        };
+        let (subscription, _) = create_subscription(&pool, &encryption, user.id, None, request).await.unwrap();
+
+        // Only the types asked for are queued
+        relay(&pool, user.id, event_types::CARD_CREATED, serde_json::json!({})).await;
+        let event = relay(&pool, user.id, event_types::BENEFICIARY_CREATED, serde_json::json!({ "id": 1 })).await;
+        let deliveries = list_deliveries(&pool, subscription.id, None, 10).await.unwrap().items;
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].event_id, event.id);
+
+        // Relayed again, the event isn't queued twice
+        WebhookOutboxHandler.handle(&pool, &event).await.unwrap();
+        assert_eq!(list_deliveries(&pool, subscription.id, None, 10).await.unwrap().items.len(), 1);
+
+        dispatch_due(&pool, &failing, &encryption, 50).await.unwrap();
+        let delivery = &list_deliveries(&pool, subscription.id, None, 10).await.unwrap().items[0];
+        assert_eq!((delivery.status, delivery.attempts, delivery.response_status), (DeliveryStatus::Pending, 1, Some(503)));
+        assert!(delivery.next_attempt_at.unwrap() > Utc::now() + chrono::Duration::seconds(30));
+
+        // Failing for a day: the next failure disables the subscription
+        sqlx::query("UPDATE webhook_subscriptions SET consecutive_failures = $2, failing_since = NOW() - INTERVAL '25 hours' WHERE id = $1")
+            .bind(subscription.id)
+            .bind(DISABLE_AFTER_FAILURES - 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE webhook_deliveries SET next_attempt_at = NOW() WHERE subscription_id = $1")
+            .bind(subscription.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        dispatch_due(&pool, &failing, &encryption, 50).await.unwrap();
+        let disabled = get_subscription(&pool, subscription.id, user.id, None).await.unwrap().unwrap();
+        assert!(disabled.disabled_at.is_some());
+        assert!(disabled.disabled_reason.unwrap().ends_with("HTTP 503"));
+
+        // Nothing is queued or sent while disabled, and the backlog goes once enabled
+        relay(&pool, user.id, event_types::BENEFICIARY_CREATED, serde_json::json!({ "id": 2 })).await;
+        assert_eq!(list_deliveries(&pool, subscription.id, None, 10).await.unwrap().items.len(), 1);
+        // Another client can't enable it
+        assert!(enable_subscription(&pool, subscription.id, user.id, Some("other")).await.unwrap().is_none());
+        let enabled = enable_subscription(&pool, subscription.id, user.id, None).await.unwrap().unwrap();
+        assert_eq!((enabled.disabled_at, enabled.consecutive_failures), (None, 0));
+        dispatch_due(&pool, &working, &encryption, 50).await.unwrap();
+        let delivered = &list_deliveries(&pool, subscription.id, None, 10).await.unwrap().items[0];
+        assert_eq!((delivered.status, delivered.attempts, delivered.next_attempt_at), (DeliveryStatus::Delivered, 3, None));
+
+        let again = redeliver(&pool, subscription.id, delivered.id).await.unwrap().unwrap();
+        assert_eq!((again.status, again.event_id, again.redelivery_of), (DeliveryStatus::Pending, delivered.event_id, Some(delivered.id)));
+        assert!(redeliver(&pool, Uuid::new_v4(), delivered.id).await.unwrap().is_none());
+
+        // The log walks like the other listings
+        let first = list_deliveries(&pool, subscription.id, None, 1).await.unwrap();
+        assert_eq!(first.items[0].id, again.id);
+        let next = Cursor::decode(first.next_cursor.as_deref().unwrap(), Order::NEWEST).unwrap();
+        let second = list_deliveries(&pool, subscription.id, Some(&next), 1).await.unwrap();
+        assert_eq!(second.items[0].id, delivered.id);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_undecryptable_secret_fails_only_its_delivery() {
+        let pool = test_pool().await;
+        let encryption = EncryptionService::new(&"ab".repeat(32)).unwrap();
+        let working: Arc<dyn WebhookSender> = Arc::new(StatusSender(204));
+
+        let user = create_user(&pool, "Subscriber").await;
+        let mut subscriptions = Vec::new();
+        for url in ["https://example.com/broken", "https://example.com/hooks"] {
+            let request = CreateWebhookRequest {
+                url: url.to_string(),
+                event_types: vec![event_types::BENEFICIARY_CREATED.to_string()],
+                description: None,
+            };
+            subscriptions.push(create_subscription(&pool, &encryption, user.id, None, request).await.unwrap().0.id);
+        }
+        sqlx::query("UPDATE webhook_subscriptions SET encrypted_secret = 'garbage' WHERE id = $1")
+            .bind(subscriptions[0])
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        relay(&pool, user.id, event_types::BENEFICIARY_CREATED, serde_json::json!({ "id": 1 })).await;
+        dispatch_due(&pool, &working, &encryption, 50).await.unwrap();
+
+        let broken = &list_deliveries(&pool, subscriptions[0], None, 10).await.unwrap().items[0];
+        assert_eq!((broken.status, broken.attempts, broken.response_status), (DeliveryStatus::Pending, 1, None));
+        let sent = &list_deliveries(&pool, subscriptions[1], None, 10).await.unwrap().items[0];
+        assert_eq!((sent.status, sent.attempts), (DeliveryStatus::Delivered, 1));
+    }
+
+    #[test]
+    fn test_signature_and_backoff() {
+        let delivery = DueDelivery {
+            id: Uuid::new_v4(),
+            subscription_id: Uuid::new_v4(),
+            attempts: 1,
+            url: "https://example.com/hooks".to_string(),
+            encrypted_secret: String::new(),
+            event: WebhookEvent {
+                id: Uuid::new_v4(),
+                event_type: "card.created".to_string(),
+                created_at: Utc::now(),
+                data: serde_json::json!({ "id": 1 }),
+            },
+        };
+        let now = DateTime::from_timestamp(1_730_000_000, 0).unwrap();
+        let request = signed_request(&delivery, "whsec_test", now);
+
+        // What a receiver recomputes from the raw body and the header
+        let (_, signature) = request.headers.iter().find(|(name, _)| *name == "Vaelix-Signature").unwrap();
+        let mut mac = HmacSha256::new_from_slice(b"whsec_test").unwrap();
+        mac.update(b"1730000000.");
+        mac.update(&request.body);
+        assert_eq!(signature, &format!("t=1730000000,v1={}", hex::encode(mac.finalize().into_bytes())));
+        assert!(String::from_utf8(request.body).unwrap().contains("\"type\":\"card.created\""));
+
+        assert_eq!(retry_delay(1).num_seconds(), 60);
+        assert_eq!(retry_delay(3).num_seconds(), 240);
+        assert_eq!(retry_delay(MAX_ATTEMPTS).num_seconds(), MAX_RETRY_DELAY_SECONDS);
+        assert!(generate_secret().starts_with("whsec_"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,40 @@
+//! Setup shared by the tests that run against a database. Those tests are
+//! `#[ignore]`d so they show as skipped without one; run them with
+//! `TEST_DATABASE_URL` set and `cargo test -- --include-ignored`.
+
+use crate::models::user::{CreateUserRequest, User, UserType};
+use crate::services::account_service::{self, AccountResponse, CreateAccountRequest};
+use crate::services::database::{self, DbPool};
+use crate::services::user_service;
+use uuid::Uuid;
+
+/// Connects to the database in `TEST_DATABASE_URL` and migrates it.
+pub async fn test_pool() -> DbPool {
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set to run the database tests");
+    let pool = DbPool::connect(&url).await.expect("test database unreachable");
+    database::run_migrations(&pool).await.expect("migrations failed");
+    pool
+}
+
+/// Creates a consumer with a unique email.
+pub async fn create_user(pool: &DbPool, name: &str) -> User {
+    let user = user_service::create_user(pool, CreateUserRequest {
+        email: format!("{}@vaelix.test", Uuid::new_v4()),
+        name: name.to_string(),
+        user_type: UserType::Consumer,
+    }, &argon2::Params::default())
+    .await
+    .unwrap();
+
+    user_service::get_user_by_id(pool, user.id).await.unwrap().unwrap()
+}
+
+/// Opens an account named `friendly_name` for `user_id`.
+pub async fn create_account(pool: &DbPool, user_id: Uuid, friendly_name: &str) -> AccountResponse {
+    account_service::create_account(pool, user_id, CreateAccountRequest {
+        profile_id: "profile".to_string(),
+        friendly_name: friendly_name.to_string(),
+    })
+    .await
+    .unwrap()
+}
\ No newline at end of file
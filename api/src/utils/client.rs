@@ -0,0 +1,21 @@
+use axum::http::{HeaderMap, HeaderName};
+
+/// Set by `client_ip_middleware` to the address the request came from,
+/// replacing whatever the client sent.
+pub const CLIENT_IP_HEADER: HeaderName = HeaderName::from_static("x-real-ip");
+
+/// The client IP resolved by `client_ip_middleware`, from the peer address
+/// and the hops added by trusted proxies only.
+pub fn client_ip(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(CLIENT_IP_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|ip| ip.to_string())
+}
+
+pub fn user_agent(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("user-agent")
+        .and_then(|h| h.to_str().ok())
+        .map(|ua| ua.to_string())
+}
\ No newline at end of file
@@ -1 +1,327 @@
-// TODO: Implement error handling utilities
\ No newline at end of file
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::time::Duration;
+use crate::middleware::request_id;
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Prefix of the `type` of every problem; the error code completes it.
+const PROBLEM_TYPE_BASE: &str = "https://vaelixbank.com/problems/";
+
+/// A field of the request that failed validation.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            code: code.into(),
+            message: Some(message.into()),
+        }
+    }
+}
+
+/// Errors returned to API clients, rendered as RFC 7807 problem details.
+/// Each variant has a stable `code` clients can match on; the `detail` is
+/// for humans and may change.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("the request has invalid fields")]
+    Validation(Vec<FieldError>),
+    #[error("authentication is required or has failed")]
+    Unauthorized,
+    #[error("not allowed to perform this action")]
+    Forbidden,
+    #[error("resource not found")]
+    NotFound,
+    #[error("method not allowed on this resource")]
+    MethodNotAllowed,
+    #[error("{0}")]
+    Conflict(String),
+    #[error("request body is too large")]
+    PayloadTooLarge,
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    #[error("account is temporarily locked")]
+    Locked { retry_after: Duration },
+    #[error("too many requests")]
+    TooManyRequests { retry_after: Option<Duration> },
+    #[error("service temporarily unavailable")]
+    ServiceUnavailable,
+    /// Details are logged, never sent to the client.
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+/// RFC 7807 body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    problem_type: String,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub fn internal(error: impl std::fmt::Display) -> Self {
+        ApiError::Internal(error.to_string())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden => StatusCode::FORBIDDEN,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::Locked { .. } => StatusCode::LOCKED,
+            ApiError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Forbidden => "forbidden",
+            ApiError::NotFound => "not_found",
+            ApiError::MethodNotAllowed => "method_not_allowed",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PayloadTooLarge => "payload_too_large",
+            ApiError::UnsupportedMediaType(_) => "unsupported_media_type",
+            ApiError::Locked { .. } => "account_locked",
+            ApiError::TooManyRequests { .. } => "rate_limited",
+            ApiError::ServiceUnavailable => "service_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ApiError::Locked { retry_after } => Some(*retry_after),
+            ApiError::TooManyRequests { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// The error for a bare status code, for responses produced without an
+    /// `ApiError` (extractor rejections, unknown routes).
+    pub fn from_status(status: StatusCode, detail: Option<String>) -> Self {
+        match status {
+            // The body didn't fit the expected shape
+            StatusCode::UNPROCESSABLE_ENTITY => ApiError::Validation(
+                detail.map(|detail| FieldError::new("body", "invalid", detail)).into_iter().collect(),
+            ),
+            StatusCode::UNAUTHORIZED => ApiError::Unauthorized,
+            StatusCode::FORBIDDEN => ApiError::Forbidden,
+            StatusCode::NOT_FOUND => ApiError::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => ApiError::MethodNotAllowed,
+            StatusCode::CONFLICT => ApiError::Conflict(detail.unwrap_or_else(|| "conflicting request".to_string())),
+            StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ApiError::UnsupportedMediaType(detail.unwrap_or_else(|| "unsupported content type".to_string())),
+            StatusCode::TOO_MANY_REQUESTS => ApiError::TooManyRequests { retry_after: None },
+            StatusCode::SERVICE_UNAVAILABLE => ApiError::ServiceUnavailable,
+            status if status.is_server_error() => ApiError::Internal(status.to_string()),
+            _ => ApiError::BadRequest(detail.unwrap_or_else(|| status.canonical_reason().unwrap_or("bad request").to_string())),
+        }
+    }
+
+    fn problem(&self, correlation_id: Option<String>) -> Problem {
+        let status = self.status();
+        let detail = match self {
+            // Never leak internals, the correlation id ties the response to the log
+            ApiError::Internal(_) => "an unexpected error occurred".to_string(),
+            other => other.to_string(),
+        };
+
+        Problem {
+            problem_type: format!("{}{}", PROBLEM_TYPE_BASE, self.code()),
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail,
+            code: self.code(),
+            correlation_id,
+            errors: match self {
+                ApiError::Validation(errors) => errors.clone(),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let correlation_id = request_id::current();
+        if let ApiError::Internal(message) = &self {
+            tracing::error!(correlation_id = correlation_id.as_deref().unwrap_or("-"), "{}", message);
+        }
+
+        let mut response = (self.status(), Json(self.problem(correlation_id))).into_response();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE));
+        if let Some(retry_after) = self.retry_after() {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after.as_secs().max(1)));
+        }
+        response
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(error: sqlx::Error) -> Self {
+        match &error {
+            sqlx::Error::RowNotFound => ApiError::NotFound,
+            sqlx::Error::Database(e) if e.is_unique_violation() => ApiError::Conflict("resource already exists".to_string()),
+            sqlx::Error::Database(e) if e.is_foreign_key_violation() => {
+                ApiError::Conflict("references a missing resource or is still referenced".to_string())
+            }
+            sqlx::Error::Database(e) if e.is_check_violation() => ApiError::BadRequest("value not allowed".to_string()),
+            sqlx::Error::PoolTimedOut => ApiError::ServiceUnavailable,
+            _ => ApiError::internal(error),
+        }
+    }
+}
+
+impl From<validator::ValidationErrors> for ApiError {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        let mut fields: Vec<FieldError> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| FieldError {
+                    field: field.to_string(),
+                    code: error.code.to_string(),
+                    message: error.message.as_ref().map(|message| message.to_string()).or_else(|| describe(error)),
+                })
+            })
+            .collect();
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+
+        ApiError::Validation(fields)
+    }
+}
+
+/// Message for the built-in validator rules, which come without one.
+fn describe(error: &validator::ValidationError) -> Option<String> {
+    let param = |name: &str| error.params.get(name).map(|value| value.to_string());
+
+    match (error.code.as_ref(), param("min"), param("max")) {
+        ("length", Some(min), Some(max)) => Some(format!("length must be between {} and {}", min, max)),
+        ("length", Some(min), None) => Some(format!("length must be at least {}", min)),
+        ("length", None, Some(max)) => Some(format!("length must be at most {}", max)),
+        ("range", Some(min), Some(max)) => Some(format!("must be between {} and {}", min, max)),
+        ("email", _, _) => Some("must be an email address".to_string()),
+        _ => None,
+    }
+}
+
+impl From<crate::services::auth_service::PasswordError> for ApiError {
+    fn from(error: crate::services::auth_service::PasswordError) -> Self {
+        ApiError::internal(error)
+    }
+}
+
+impl From<crate::services::api_key_service::ApiKeyError> for ApiError {
+    fn from(error: crate::services::api_key_service::ApiKeyError) -> Self {
+        match error {
+            crate::services::api_key_service::ApiKeyError::Database(e) => e.into(),
+            other => ApiError::internal(other),
+        }
+    }
+}
+
+impl From<crate::services::mfa_service::MfaError> for ApiError {
+    fn from(error: crate::services::mfa_service::MfaError) -> Self {
+        match error {
+            crate::services::mfa_service::MfaError::Database(e) => e.into(),
+            other => ApiError::internal(other),
+        }
+    }
+}
+
+impl From<crate::services::oauth_service::OAuthError> for ApiError {
+    fn from(error: crate::services::oauth_service::OAuthError) -> Self {
+        match error {
+            crate::services::oauth_service::OAuthError::Database(e) => e.into(),
+            other => ApiError::internal(other),
+        }
+    }
+}
+
+impl From<crate::services::audit_service::AuditError> for ApiError {
+    fn from(error: crate::services::audit_service::AuditError) -> Self {
+        match error {
+            crate::services::audit_service::AuditError::Database(e) => e.into(),
+            other => ApiError::internal(other),
+        }
+    }
+}
+
+impl From<crate::services::webhook_service::WebhookError> for ApiError {
+    fn from(error: crate::services::webhook_service::WebhookError) -> Self {
+        match error {
+            crate::services::webhook_service::WebhookError::Database(e) => e.into(),
+            other => ApiError::internal(other),
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for ApiError {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        ApiError::internal(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_problem_body() {
+        let response = request_id::CORRELATION_ID
+            .scope("abc-123".to_string(), async {
+                ApiError::Validation(vec![FieldError::new("currency", "currency", "must be an ISO 4217 code")]).into_response()
+            })
+            .await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_CONTENT_TYPE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "validation_failed");
+        assert_eq!(body["status"], 422);
+        assert_eq!(body["correlation_id"], "abc-123");
+        assert_eq!(body["errors"][0]["field"], "currency");
+
+        let response = ApiError::internal("connection reset").into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(!String::from_utf8_lossy(&body).contains("connection reset"));
+    }
+}
\ No newline at end of file
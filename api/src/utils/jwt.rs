@@ -1,16 +1,289 @@
-use jsonwebtoken::{encode, decode, Header, Algorithm, Validation, EncodingKey, DecodingKey, errors::Error};
-use serde::{Serialize, Deserialize};
+use jsonwebtoken::{encode, decode, decode_header, Header, Algorithm, Validation, EncodingKey, DecodingKey, errors::{Error, ErrorKind}};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
 use chrono::{Utc, Duration};
+use std::collections::HashMap;
+use std::sync::Arc;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey};
+use rand::RngCore;
+use uuid::Uuid;
+use rsa::traits::PublicKeyParts;
+use crate::models::role::UserAccess;
+use crate::utils::error::ApiError;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String, // user id
     pub email: String,
+    pub sid: String, // session id
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// OAuth client the token was issued to; absent for the bank's own apps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
     pub exp: usize, // expiration time
     pub iat: usize, // issued at
 }
 
-pub fn create_token(user_id: &str, email: &str, secret: &str, expires_in_hours: i64) -> Result<String, Error> {
+impl Claims {
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+
+    /// The authenticated user's id; a token without one is refused.
+    pub fn user_id(&self) -> Result<Uuid, ApiError> {
+        Uuid::parse_str(&self.sub).map_err(|_| ApiError::Unauthorized)
+    }
+}
+
+/// Short-lived proof that the password step of a login succeeded. It is only
+/// accepted by the MFA endpoints, never as an access token.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MfaChallengeClaims {
+    pub sub: String, // user id
+    pub jti: String, // challenge id, spent once answered
+    pub purpose: String,
+    pub exp: usize,
+    pub iat: usize,
+}
+
+/// Signed statement that the audit chain ended with `hash` at `seq`. It never
+/// expires, so old checkpoints stay verifiable while their key is published.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditCheckpointClaims {
+    pub seq: i64,
+    pub hash: String,
+    pub iat: usize,
+}
+
+const MFA_CHALLENGE_PURPOSE: &str = "mfa_challenge";
+pub const MFA_CHALLENGE_MINUTES: i64 = 5;
+
+// Every token is signed with the same key, so the audience tells them apart:
+// access tokens carry the configured audience, the others a suffixed one that
+// `verify_token` refuses.
+const REFRESH_AUDIENCE_SUFFIX: &str = "#refresh";
+const MFA_CHALLENGE_AUDIENCE_SUFFIX: &str = "#mfa_challenge";
+const AUDIT_CHECKPOINT_AUDIENCE_SUFFIX: &str = "#audit_checkpoint";
+
+#[derive(Debug, thiserror::Error)]
+pub enum JwtKeyError {
+    #[error("cannot read key directory {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("key {0} is not a PKCS#8 Ed25519 or RSA key in PEM format")]
+    UnsupportedKey(String),
+    #[error("signing key {0} not found, or only its public key is present")]
+    MissingSigningKey(String),
+    #[error("JWT_KEYS_DIR is required in production")]
+    NoKeys,
+}
+
+struct VerificationKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+struct KeySet {
+    signing_kid: String,
+    signing_algorithm: Algorithm,
+    signing_key: EncodingKey,
+    verification: HashMap<String, VerificationKey>,
+    jwks: Value,
+    issuer: String,
+    audience: String,
+}
+
+/// Signing key and verification keys for the tokens we issue.
+///
+/// Tokens are signed with one private key (EdDSA or RS256) and carry its id in
+/// the `kid` header. Every key in the set verifies tokens, so rotating means
+/// adding the new key, switching the signing kid, and dropping the old key
+/// once the tokens it signed have expired. Public keys are published as a JWKS.
+#[derive(Clone)]
+pub struct JwtKeys(Arc<KeySet>);
+
+impl std::fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut kids: Vec<&String> = self.0.verification.keys().collect();
+        kids.sort();
+        f.debug_struct("JwtKeys")
+            .field("signing_kid", &self.0.signing_kid)
+            .field("kids", &kids)
+            .field("issuer", &self.0.issuer)
+            .field("audience", &self.0.audience)
+            .finish()
+    }
+}
+
+/// A key parsed from PEM: the private half if present, and the public half.
+struct ParsedKey {
+    algorithm: Algorithm,
+    signing: Option<EncodingKey>,
+    verification: DecodingKey,
+    jwk: Value,
+}
+
+fn parse_pem(kid: &str, pem: &str) -> Result<ParsedKey, JwtKeyError> {
+    let unsupported = || JwtKeyError::UnsupportedKey(kid.to_string());
+
+    if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+        let x = URL_SAFE_NO_PAD.encode(key.verifying_key().as_bytes());
+        return Ok(ParsedKey {
+            algorithm: Algorithm::EdDSA,
+            signing: Some(EncodingKey::from_ed_pem(pem.as_bytes()).map_err(|_| unsupported())?),
+            verification: DecodingKey::from_ed_components(&x).map_err(|_| unsupported())?,
+            jwk: json!({ "kty": "OKP", "crv": "Ed25519", "x": x, "kid": kid, "alg": "EdDSA", "use": "sig" }),
+        });
+    }
+    if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+        let x = URL_SAFE_NO_PAD.encode(key.as_bytes());
+        return Ok(ParsedKey {
+            algorithm: Algorithm::EdDSA,
+            signing: None,
+            verification: DecodingKey::from_ed_components(&x).map_err(|_| unsupported())?,
+            jwk: json!({ "kty": "OKP", "crv": "Ed25519", "x": x, "kid": kid, "alg": "EdDSA", "use": "sig" }),
+        });
+    }
+
+    let (public, signing) = if let Ok(key) = rsa::RsaPrivateKey::from_pkcs8_pem(pem) {
+        let signing = EncodingKey::from_rsa_pem(pem.as_bytes()).map_err(|_| unsupported())?;
+        (key.to_public_key(), Some(signing))
+    } else if let Ok(key) = rsa::RsaPublicKey::from_public_key_pem(pem) {
+        (key, None)
+    } else {
+        return Err(unsupported());
+    };
+
+    let n = URL_SAFE_NO_PAD.encode(public.n().to_bytes_be());
+    let e = URL_SAFE_NO_PAD.encode(public.e().to_bytes_be());
+    Ok(ParsedKey {
+        algorithm: Algorithm::RS256,
+        signing,
+        verification: DecodingKey::from_rsa_components(&n, &e).map_err(|_| unsupported())?,
+        jwk: json!({ "kty": "RSA", "n": n, "e": e, "kid": kid, "alg": "RS256", "use": "sig" }),
+    })
+}
+
+impl JwtKeys {
+    /// Builds the set from `(kid, pem)` pairs. Keys may be private or public
+    /// (PKCS#8 / SPKI); the signing key must be private.
+    pub fn from_pems(pems: &[(String, String)], signing_kid: &str, issuer: &str, audience: &str) -> Result<Self, JwtKeyError> {
+        let mut signing = None;
+        let mut verification = HashMap::new();
+        let mut jwks = Vec::new();
+
+        for (kid, pem) in pems {
+            let parsed = parse_pem(kid, pem)?;
+            if kid == signing_kid {
+                signing = parsed.signing.map(|key| (parsed.algorithm, key));
+            }
+            verification.insert(kid.clone(), VerificationKey { algorithm: parsed.algorithm, key: parsed.verification });
+            jwks.push(parsed.jwk);
+        }
+
+        let (signing_algorithm, signing_key) = signing.ok_or_else(|| JwtKeyError::MissingSigningKey(signing_kid.to_string()))?;
+
+        Ok(Self(Arc::new(KeySet {
+            signing_kid: signing_kid.to_string(),
+            signing_algorithm,
+            signing_key,
+            verification,
+            jwks: json!({ "keys": jwks }),
+            issuer: issuer.to_string(),
+            audience: audience.to_string(),
+        })))
+    }
+
+    /// Loads every `<kid>.pem` in `dir`. Without a directory, development
+    /// gets a throwaway Ed25519 key, so tokens don't survive a restart.
+    pub fn load(dir: Option<&str>, signing_kid: Option<&str>, issuer: &str, audience: &str, allow_ephemeral: bool) -> Result<Self, JwtKeyError> {
+        let dir = match dir {
+            Some(dir) => dir,
+            None if allow_ephemeral => return Ok(Self::ephemeral(issuer, audience)),
+            None => return Err(JwtKeyError::NoKeys),
+        };
+
+        let io_error = |e| JwtKeyError::Io(dir.to_string(), e);
+        let mut pems = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("pem") {
+                continue;
+            }
+            let kid = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+            pems.push((kid, std::fs::read_to_string(&path).map_err(io_error)?));
+        }
+        pems.sort();
+
+        // Default to the last key by name, e.g. the latest `2024-12` style kid
+        let signing_kid = match signing_kid {
+            Some(kid) => kid.to_string(),
+            None => pems.last().map(|(kid, _)| kid.clone()).ok_or(JwtKeyError::NoKeys)?,
+        };
+
+        Self::from_pems(&pems, &signing_kid, issuer, audience)
+    }
+
+    pub fn ephemeral(issuer: &str, audience: &str) -> Self {
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut seed);
+        let pem = ed25519_dalek::SigningKey::from_bytes(&seed)
+            .to_pkcs8_pem(Default::default())
+            .expect("Ed25519 keys encode to PKCS#8");
+
+        let kid = format!("ephemeral-{}", Utc::now().timestamp());
+        Self::from_pems(&[(kid.clone(), pem.to_string())], &kid, issuer, audience)
+            .expect("generated key is valid")
+    }
+
+    /// The public keys as a JWK set, for `/.well-known/jwks.json`.
+    pub fn jwks(&self) -> &Value {
+        &self.0.jwks
+    }
+
+    fn sign<T: Serialize>(&self, claims: &T, audience: &str) -> Result<String, Error> {
+        #[derive(Serialize)]
+        struct Registered<'a, T> {
+            iss: &'a str,
+            aud: &'a str,
+            #[serde(flatten)]
+            claims: &'a T,
+        }
+
+        let mut header = Header::new(self.0.signing_algorithm);
+        header.kid = Some(self.0.signing_kid.clone());
+
+        let claims = Registered { iss: &self.0.issuer, aud: audience, claims };
+        encode(&header, &claims, &self.0.signing_key)
+    }
+
+    /// Checks the signature, issuer and audience, and the expiry if `expiring`.
+    fn verify<T: DeserializeOwned>(&self, token: &str, audience: &str, expiring: bool) -> Result<T, Error> {
+        let kid = decode_header(token)?.kid.ok_or(ErrorKind::InvalidToken)?;
+        let key = self.0.verification.get(&kid).ok_or(ErrorKind::InvalidToken)?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_issuer(&[&self.0.issuer]);
+        validation.set_audience(&[audience]);
+        if expiring {
+            validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        } else {
+            validation.validate_exp = false;
+            validation.set_required_spec_claims(&["iss", "aud"]);
+        }
+
+        Ok(decode::<T>(token, &key.key, &validation)?.claims)
+    }
+
+    fn suffixed_audience(&self, suffix: &str) -> String {
+        format!("{}{}", self.0.audience, suffix)
+    }
+}
+
+pub fn create_token(user_id: &str, email: &str, session_id: &str, access: &UserAccess, keys: &JwtKeys, audience: &str, expires_in_hours: i64) -> Result<String, Error> {
     let expiration = Utc::now()
         .checked_add_signed(Duration::hours(expires_in_hours))
         .expect("valid timestamp")
@@ -19,28 +292,122 @@ pub fn create_token(user_id: &str, email: &str, secret: &str, expires_in_hours:
     let claims = Claims {
         sub: user_id.to_owned(),
         email: email.to_owned(),
+        sid: session_id.to_owned(),
+        roles: access.roles.clone(),
+        permissions: access.permissions.clone(),
+        client_id: None,
         exp: expiration,
         iat: Utc::now().timestamp() as usize,
     };
 
-    let header = Header::new(Algorithm::HS256);
-    let encoding_key = EncodingKey::from_secret(secret.as_ref());
+    keys.sign(&claims, audience)
+}
 
-    encode(&header, &claims, &encoding_key)
+/// Verifies an access token: signature by a known key, expiry, issuer and audience.
+pub fn verify_token(token: &str, keys: &JwtKeys) -> Result<Claims, Error> {
+    keys.verify(token, &keys.0.audience, true)
 }
 
-pub fn verify_token(token: &str, secret: &str) -> Result<Claims, Error> {
-    let decoding_key = DecodingKey::from_secret(secret.as_ref());
-    let validation = Validation::new(Algorithm::HS256);
+pub fn create_access_token(user_id: &str, email: &str, session_id: &str, access: &UserAccess, keys: &JwtKeys) -> Result<String, Error> {
+    create_token(user_id, email, session_id, access, keys, &keys.0.audience, 1) // 1 hour
+}
 
-    let token_data = decode::<Claims>(token, &decoding_key, &validation)?;
-    Ok(token_data.claims)
+pub fn create_refresh_token(user_id: &str, email: &str, session_id: &str, keys: &JwtKeys) -> Result<String, Error> {
+    // Refresh tokens only identify the session and never grant permissions
+    let audience = keys.suffixed_audience(REFRESH_AUDIENCE_SUFFIX);
+    create_token(user_id, email, session_id, &UserAccess::default(), keys, &audience, 24 * 7) // 7 days
 }
 
-pub fn create_access_token(user_id: &str, email: &str, secret: &str) -> Result<String, Error> {
-    create_token(user_id, email, secret, 1) // 1 hour
+/// Access token for an OAuth client acting for a user, limited to the granted scopes.
+pub fn create_oauth_access_token(user_id: &str, email: &str, session_id: &str, scopes: &[String], client_id: &str, keys: &JwtKeys, expires_in_minutes: i64) -> Result<String, Error> {
+    let claims = Claims {
+        sub: user_id.to_owned(),
+        email: email.to_owned(),
+        sid: session_id.to_owned(),
+        roles: Vec::new(),
+        permissions: scopes.to_vec(),
+        client_id: Some(client_id.to_owned()),
+        exp: (Utc::now() + Duration::minutes(expires_in_minutes)).timestamp() as usize,
+        iat: Utc::now().timestamp() as usize,
+    };
+
+    keys.sign(&claims, &keys.0.audience)
 }
 
-pub fn create_refresh_token(user_id: &str, email: &str, secret: &str) -> Result<String, Error> {
-    create_token(user_id, email, secret, 24 * 7) // 7 days
+pub fn create_mfa_challenge_token(user_id: &str, challenge_id: &str, keys: &JwtKeys) -> Result<String, Error> {
+    let claims = MfaChallengeClaims {
+        sub: user_id.to_owned(),
+        jti: challenge_id.to_owned(),
+        purpose: MFA_CHALLENGE_PURPOSE.to_owned(),
+        exp: (Utc::now() + Duration::minutes(MFA_CHALLENGE_MINUTES)).timestamp() as usize,
+        iat: Utc::now().timestamp() as usize,
+    };
+
+    keys.sign(&claims, &keys.suffixed_audience(MFA_CHALLENGE_AUDIENCE_SUFFIX))
+}
+
+pub fn verify_mfa_challenge_token(token: &str, keys: &JwtKeys) -> Result<MfaChallengeClaims, Error> {
+    let claims: MfaChallengeClaims = keys.verify(token, &keys.suffixed_audience(MFA_CHALLENGE_AUDIENCE_SUFFIX), true)?;
+    if claims.purpose != MFA_CHALLENGE_PURPOSE {
+        return Err(ErrorKind::InvalidToken.into());
+    }
+
+    Ok(claims)
+}
+
+pub fn sign_audit_checkpoint(sequence: i64, hash: &str, keys: &JwtKeys) -> Result<String, Error> {
+    let claims = AuditCheckpointClaims {
+        seq: sequence,
+        hash: hash.to_owned(),
+        iat: Utc::now().timestamp() as usize,
+    };
+
+    keys.sign(&claims, &keys.suffixed_audience(AUDIT_CHECKPOINT_AUDIENCE_SUFFIX))
+}
+
+/// Verifies a checkpoint signature, or returns `None` if its key is no longer
+/// published and it can't be checked.
+pub fn verify_audit_checkpoint(token: &str, keys: &JwtKeys) -> Result<Option<AuditCheckpointClaims>, Error> {
+    let kid = decode_header(token)?.kid.ok_or(ErrorKind::InvalidToken)?;
+    if !keys.0.verification.contains_key(&kid) {
+        return Ok(None);
+    }
+
+    keys.verify(token, &keys.suffixed_audience(AUDIT_CHECKPOINT_AUDIENCE_SUFFIX), false).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ed25519_pem() -> String {
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut seed);
+        ed25519_dalek::SigningKey::from_bytes(&seed).to_pkcs8_pem(Default::default()).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let old = ("2024-11".to_string(), ed25519_pem());
+        let new = ("2024-12".to_string(), ed25519_pem());
+
+        let before = JwtKeys::from_pems(std::slice::from_ref(&old), "2024-11", "vaelix-bank", "vaelix-api").unwrap();
+        let after = JwtKeys::from_pems(&[old.clone(), new.clone()], "2024-12", "vaelix-bank", "vaelix-api").unwrap();
+        let access = UserAccess::default();
+
+        // Tokens signed before the rotation stay valid while the old key is kept
+        let old_token = create_access_token("user", "a@b.c", "sid", &access, &before).unwrap();
+        assert!(verify_token(&old_token, &after).is_ok());
+
+        let new_token = create_access_token("user", "a@b.c", "sid", &access, &after).unwrap();
+        assert_eq!(decode_header(&new_token).unwrap().kid.as_deref(), Some("2024-12"));
+        assert!(verify_token(&new_token, &before).is_err());
+        assert_eq!(after.jwks()["keys"].as_array().unwrap().len(), 2);
+
+        // Other token kinds, issuers and audiences are not access tokens
+        let refresh = create_refresh_token("user", "a@b.c", "sid", &after).unwrap();
+        assert!(verify_token(&refresh, &after).is_err());
+        let elsewhere = JwtKeys::from_pems(&[old, new], "2024-12", "vaelix-bank", "other-api").unwrap();
+        assert!(verify_token(&new_token, &elsewhere).is_err());
+    }
 }
\ No newline at end of file
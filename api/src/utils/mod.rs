@@ -1,4 +1,8 @@
 // Utility functions
 pub mod jwt;
 pub mod validation;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod client;
+pub mod totp;
+pub mod webauthn;
+pub mod pagination;
\ No newline at end of file
@@ -0,0 +1,192 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+use crate::models::pagination::Page;
+use crate::utils::error::ApiError;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// Order of a listing by one column, ties broken by `id` the same way so
+/// every row has a single place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Order {
+    pub column: &'static str,
+    /// Postgres type of the column, to read cursor keys back as
+    pub sql_type: &'static str,
+    pub descending: bool,
+}
+
+impl Order {
+    pub const NEWEST: Order = Order { column: "created_at", sql_type: "TIMESTAMPTZ", descending: true };
+    pub const OLDEST: Order = Order { column: "created_at", sql_type: "TIMESTAMPTZ", descending: false };
+
+    fn tag(&self) -> String {
+        format!("{}{}", if self.descending { '-' } else { '+' }, self.column)
+    }
+}
+
+/// Cursor key of a `created_at` column, to the microsecond Postgres keeps.
+pub fn time_key(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Which side of the cursor's item a page lies on. `After` walks the way of
+/// the order, towards the end of the listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    After,
+    Before,
+}
+
+/// Position in a listing: the order column of an item, as text, and its id.
+/// Clients get it encoded and send it back as is; the encoding isn't part of
+/// the API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub key: String,
+    pub id: Uuid,
+    pub direction: Direction,
+}
+
+impl Cursor {
+    /// Encoded with the order it was made for, so that it can't be used to
+    /// walk a listing sorted differently.
+    pub fn encode(&self, order: Order) -> String {
+        let direction = match self.direction {
+            Direction::After => 'a',
+            Direction::Before => 'b',
+        };
+        URL_SAFE_NO_PAD.encode(format!("{}:{}:{}:{}", direction, order.tag(), self.id, self.key))
+    }
+
+    pub fn decode(value: &str, order: Order) -> Result<Self, ApiError> {
+        let invalid = || ApiError::BadRequest("invalid cursor for this listing and sort".to_string());
+        let decoded = URL_SAFE_NO_PAD.decode(value).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+
+        // The key comes last, as it may hold colons itself
+        let mut fields = decoded.splitn(4, ':');
+        let direction = match fields.next() {
+            Some("a") => Direction::After,
+            Some("b") => Direction::Before,
+            _ => return Err(invalid()),
+        };
+        if fields.next() != Some(order.tag().as_str()) {
+            return Err(invalid());
+        }
+        let id = fields.next().and_then(|id| id.parse().ok()).ok_or_else(invalid)?;
+        let key = fields.next().ok_or_else(invalid)?.to_string();
+        // The key is bound as text and cast by Postgres; keep it to what
+        // timestamps and numbers are written with
+        if key.is_empty() || !key.bytes().all(|b| b.is_ascii_alphanumeric() || b"-+:.".contains(&b)) {
+            return Err(invalid());
+        }
+
+        Ok(Cursor { key, id, direction })
+    }
+}
+
+/// The `limit` of a listing request, within bounds.
+pub fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Ends a query of rows with `id` and the order column, already filtered
+/// with a `WHERE`, to fetch the page at `cursor`. One row more than `limit`
+/// is fetched to tell whether another page follows.
+pub fn push_keyset(builder: &mut QueryBuilder<'_, Postgres>, order: Order, cursor: Option<&Cursor>, limit: i64) {
+    // Before the cursor, rows are fetched in reverse and turned back by into_page
+    let descending = match cursor {
+        Some(cursor) if cursor.direction == Direction::Before => !order.descending,
+        _ => order.descending,
+    };
+
+    if let Some(cursor) = cursor {
+        builder
+            .push(format!(" AND ({}, id) {} (CAST(", order.column, if descending { "<" } else { ">" }))
+            .push_bind(cursor.key.clone())
+            .push(format!(" AS {}), ", order.sql_type))
+            .push_bind(cursor.id)
+            .push(")");
+    }
+    let direction = if descending { "DESC" } else { "ASC" };
+    builder
+        .push(format!(" ORDER BY {} {}, id {}", order.column, direction, direction))
+        .push(" LIMIT ")
+        .push_bind(limit + 1);
+}
+
+/// The page of `rows`, fetched in the order of `push_keyset`, with the
+/// cursors of its neighbours. `key` gives the order column and id of a row.
+pub fn into_page<T>(
+    mut rows: Vec<T>,
+    order: Order,
+    cursor: Option<&Cursor>,
+    limit: i64,
+    key: impl Fn(&T) -> (String, Uuid),
+) -> Page<T> {
+    let more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+
+    let backwards = cursor.is_some_and(|cursor| cursor.direction == Direction::Before);
+    if backwards {
+        rows.reverse();
+    }
+    // Walking one way, the page we came from is always there the other way
+    let (following, preceding) = match backwards {
+        false => (more, cursor.is_some()),
+        true => (true, more),
+    };
+
+    let at = |item: Option<&T>, direction| {
+        item.map(|item| {
+            let (key, id) = key(item);
+            Cursor { key, id, direction }.encode(order)
+        })
+    };
+    Page {
+        next_cursor: if following { at(rows.last(), Direction::After) } else { None },
+        prev_cursor: if preceding { at(rows.first(), Direction::Before) } else { None },
+        items: rows,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip_and_neighbours() {
+        let cursor = Cursor {
+            key: time_key(DateTime::from_timestamp_micros(1_730_000_000_123_456).unwrap()),
+            id: Uuid::new_v4(),
+            direction: Direction::Before,
+        };
+        assert_eq!(cursor.key, "2024-10-27T03:33:20.123456Z");
+        assert_eq!(Cursor::decode(&cursor.encode(Order::NEWEST), Order::NEWEST).unwrap(), cursor);
+        // Not from another sort, and nothing but a key Postgres can cast
+        assert!(Cursor::decode(&cursor.encode(Order::NEWEST), Order::OLDEST).is_err());
+        assert!(Cursor::decode("not a cursor", Order::NEWEST).is_err());
+        let injected = format!("a:-created_at:{}:1'; DROP TABLE x", cursor.id);
+        assert!(Cursor::decode(&URL_SAFE_NO_PAD.encode(injected), Order::NEWEST).is_err());
+
+        let item = |seconds| (DateTime::from_timestamp(seconds, 0).unwrap(), Uuid::new_v4());
+        let key = |item: &(DateTime<Utc>, Uuid)| (time_key(item.0), item.1);
+
+        // First page, newest first, with more to come
+        let page = into_page(vec![item(3), item(2), item(1)], Order::NEWEST, None, 2, key);
+        assert_eq!(page.items.len(), 2);
+        assert!(page.prev_cursor.is_none());
+        let next = Cursor::decode(page.next_cursor.as_deref().unwrap(), Order::NEWEST).unwrap();
+        assert_eq!((next.key, next.id, next.direction), (time_key(page.items[1].0), page.items[1].1, Direction::After));
+
+        // Walking back from there: fetched oldest first, served newest first
+        let back = Cursor { key: time_key(page.items[0].0), id: page.items[0].1, direction: Direction::Before };
+        let page = into_page(vec![item(4), item(5)], Order::NEWEST, Some(&back), 2, key);
+        assert_eq!(page.items[0].0.timestamp(), 5);
+        assert!(page.prev_cursor.is_none());
+        assert!(page.next_cursor.is_some());
+    }
+}
\ No newline at end of file
@@ -1 +1,135 @@
-// TODO: Implement validation utilities
\ No newline at end of file
+use axum::{
+    async_trait,
+    extract::{FromRequest, Request},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+use validator::{Validate, ValidationError};
+use crate::utils::error::ApiError;
+
+/// Largest amount that fits the `DECIMAL(15,2)` amount columns.
+pub const MAX_AMOUNT: f64 = 9_999_999_999_999.99;
+
+/// Active ISO 4217 currency codes.
+const CURRENCIES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT", "BGN", "BHD", "BIF",
+    "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD", "CDF", "CHF", "CLP", "CNY", "COP", "CRC",
+    "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD", "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS",
+    "GIP", "GMD", "GNF", "GTQ", "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD",
+    "JOD", "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR", "LRD", "LSL",
+    "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR", "MWK", "MXN", "MYR", "MZN", "NAD",
+    "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN", "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD",
+    "RUB", "RWF", "SAR", "SBD", "SCR", "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SVC", "SYP",
+    "SZL", "THB", "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS", "VED",
+    "VES", "VND", "VUV", "WST", "XAF", "XCD", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWG",
+];
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::Borrowed(message));
+    error
+}
+
+pub fn validate_currency(currency: &str) -> Result<(), ValidationError> {
+    if CURRENCIES.contains(&currency) {
+        Ok(())
+    } else {
+        Err(error("currency", "must be an ISO 4217 currency code in upper case"))
+    }
+}
+
+/// Amounts are positive, with at most two decimals.
+pub fn validate_amount(amount: f64) -> Result<(), ValidationError> {
+    if !amount.is_finite() || amount <= 0.0 || amount > MAX_AMOUNT {
+        return Err(error("amount", "must be positive and fit the amount columns"));
+    }
+
+    // The shortest decimal that reads back as the same float, so 12.34 is
+    // seen as written rather than as its binary approximation
+    if amount.to_string().split_once('.').is_some_and(|(_, decimals)| decimals.len() > 2) {
+        return Err(error("amount_precision", "must have at most two decimals"));
+    }
+
+    Ok(())
+}
+
+/// Checks the format and the ISO 13616 check digits of an IBAN, written
+/// without spaces.
+pub fn validate_iban(iban: &str) -> Result<(), ValidationError> {
+    let invalid = || error("iban", "must be a valid IBAN, without spaces");
+    let bytes = iban.as_bytes();
+
+    if !(15..=34).contains(&bytes.len())
+        || !bytes[..2].iter().all(u8::is_ascii_uppercase)
+        || !bytes[2..4].iter().all(u8::is_ascii_digit)
+        || !bytes[4..].iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+
+    // Move the country code and check digits to the end, read letters as
+    // 10..35, and the remainder modulo 97 must be 1
+    let remainder = bytes[4..].iter().chain(&bytes[..4]).fold(0u32, |remainder, &b| {
+        if b.is_ascii_digit() {
+            (remainder * 10 + (b - b'0') as u32) % 97
+        } else {
+            (remainder * 100 + (b - b'A' + 10) as u32) % 97
+        }
+    });
+
+    if remainder == 1 {
+        Ok(())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// JSON body that is deserialized then validated, answering 422 with the
+/// failing fields instead of reaching the handler. Malformed JSON gets a 400,
+/// and a missing content type a 415.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| ApiError::from_status(rejection.status(), Some(rejection.body_text())))?;
+
+        value.validate()?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_rules() {
+        assert!(validate_iban("GB29NWBK60161331926819").is_ok());
+        assert!(validate_iban("FR1420041010050500013M02606").is_ok());
+        assert!(validate_iban("GB29NWBK60161331926818").is_err());
+        assert!(validate_iban("GB29 NWBK 6016 1331 9268 19").is_err());
+        assert!(validate_iban("gb29nwbk60161331926819").is_err());
+
+        assert!(validate_amount(12.34).is_ok());
+        assert!(validate_amount(0.01).is_ok());
+        assert!(validate_amount(0.0).is_err());
+        assert!(validate_amount(-5.0).is_err());
+        assert!(validate_amount(1.005).is_err());
+        assert!(validate_amount(f64::NAN).is_err());
+
+        assert!(validate_currency("EUR").is_ok());
+        assert!(validate_currency("eur").is_err());
+        assert!(validate_currency("XYZ").is_err());
+    }
+}
\ No newline at end of file
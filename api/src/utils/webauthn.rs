@@ -0,0 +1,476 @@
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use ciborium::value::Value;
+use ed25519_dalek::{Signature as Ed25519Signature, VerifyingKey as Ed25519VerifyingKey};
+use p256::ecdsa::{signature::Verifier, Signature as P256Signature, VerifyingKey as P256VerifyingKey};
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+// COSE algorithm identifiers we accept, in order of preference
+pub const ALG_ES256: i64 = -7;
+pub const ALG_EDDSA: i64 = -8;
+pub const SUPPORTED_ALGORITHMS: [i64; 2] = [ALG_ES256, ALG_EDDSA];
+
+const CHALLENGE_BYTES: usize = 32;
+
+// Authenticator data flags (WebAuthn §6.1)
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebauthnError {
+    #[error("malformed {0}")]
+    Malformed(&'static str),
+    #[error("client data type, challenge or origin does not match")]
+    ClientDataMismatch,
+    #[error("relying party id does not match")]
+    RpIdMismatch,
+    #[error("user presence or verification was not asserted")]
+    UserNotVerified,
+    #[error("unsupported attestation format `{0}`")]
+    UnsupportedAttestation(String),
+    #[error("unsupported public key algorithm")]
+    UnsupportedAlgorithm,
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("signature counter did not increase, the authenticator may be cloned")]
+    CounterRegression,
+}
+
+/// Relying party settings every ceremony is checked against.
+pub struct RelyingParty<'a> {
+    pub id: &'a str,
+    pub origins: &'a [String],
+}
+
+/// Result of a successful registration ceremony, ready to be stored.
+#[derive(Debug)]
+pub struct RegisteredCredential {
+    pub credential_id: Vec<u8>,
+    /// COSE_Key exactly as sent by the authenticator
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony_type: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+pub fn generate_challenge() -> String {
+    let mut challenge = [0u8; CHALLENGE_BYTES];
+    rand::thread_rng().fill_bytes(&mut challenge);
+    URL_SAFE_NO_PAD.encode(challenge)
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn decode(value: &str) -> Result<Vec<u8>, WebauthnError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| WebauthnError::Malformed("base64url value"))
+}
+
+/// Verifies a `navigator.credentials.create()` response. Only the "none"
+/// attestation format is accepted since we never ask for attestation.
+pub fn verify_registration(
+    rp: &RelyingParty,
+    expected_challenge: &str,
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+    require_user_verification: bool,
+) -> Result<RegisteredCredential, WebauthnError> {
+    verify_client_data(rp, "webauthn.create", expected_challenge, client_data_json)?;
+
+    let attestation: Value = ciborium::de::from_reader(attestation_object)
+        .map_err(|_| WebauthnError::Malformed("attestation object"))?;
+    let fields = attestation.as_map().ok_or(WebauthnError::Malformed("attestation object"))?;
+
+    let fmt = map_get_text(fields, "fmt")
+        .and_then(Value::as_text)
+        .ok_or(WebauthnError::Malformed("attestation format"))?;
+    if fmt != "none" {
+        return Err(WebauthnError::UnsupportedAttestation(fmt.to_string()));
+    }
+
+    let auth_data = map_get_text(fields, "authData")
+        .and_then(Value::as_bytes)
+        .ok_or(WebauthnError::Malformed("authenticator data"))?;
+    let auth_data = parse_authenticator_data(auth_data)?;
+    verify_authenticator_data(rp, &auth_data, require_user_verification)?;
+
+    let (credential_id, public_key) = auth_data
+        .attested_credential
+        .ok_or(WebauthnError::Malformed("attested credential data"))?;
+
+    // Refuse keys we won't be able to verify assertions with later
+    parse_public_key(&public_key)?;
+
+    Ok(RegisteredCredential {
+        credential_id,
+        public_key,
+        sign_count: auth_data.sign_count,
+    })
+}
+
+/// Verifies a `navigator.credentials.get()` response against a stored
+/// credential and returns the authenticator's new signature counter.
+#[allow(clippy::too_many_arguments)]
+pub fn verify_assertion(
+    rp: &RelyingParty,
+    expected_challenge: &str,
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+    stored_sign_count: u32,
+    require_user_verification: bool,
+) -> Result<u32, WebauthnError> {
+    verify_client_data(rp, "webauthn.get", expected_challenge, client_data_json)?;
+
+    let auth_data = parse_authenticator_data(authenticator_data)?;
+    verify_authenticator_data(rp, &auth_data, require_user_verification)?;
+
+    let mut signed = authenticator_data.to_vec();
+    signed.extend_from_slice(&Sha256::digest(client_data_json));
+
+    match parse_public_key(public_key)? {
+        PublicKey::Es256(key) => {
+            let signature = P256Signature::from_der(signature)
+                .map_err(|_| WebauthnError::InvalidSignature)?;
+            key.verify(&signed, &signature).map_err(|_| WebauthnError::InvalidSignature)?;
+        }
+        PublicKey::EdDsa(key) => {
+            let signature = Ed25519Signature::from_slice(signature)
+                .map_err(|_| WebauthnError::InvalidSignature)?;
+            key.verify(&signed, &signature).map_err(|_| WebauthnError::InvalidSignature)?;
+        }
+    }
+
+    // Authenticators without a counter (most synced passkeys) always report 0
+    if (auth_data.sign_count != 0 || stored_sign_count != 0) && auth_data.sign_count <= stored_sign_count {
+        return Err(WebauthnError::CounterRegression);
+    }
+
+    Ok(auth_data.sign_count)
+}
+
+fn verify_client_data(
+    rp: &RelyingParty,
+    ceremony_type: &str,
+    expected_challenge: &str,
+    client_data_json: &[u8],
+) -> Result<(), WebauthnError> {
+    let client_data: ClientData = serde_json::from_slice(client_data_json)
+        .map_err(|_| WebauthnError::Malformed("client data"))?;
+
+    if client_data.ceremony_type != ceremony_type
+        || client_data.challenge.trim_end_matches('=') != expected_challenge
+        || !rp.origins.iter().any(|origin| origin == &client_data.origin)
+    {
+        return Err(WebauthnError::ClientDataMismatch);
+    }
+
+    Ok(())
+}
+
+fn verify_authenticator_data(
+    rp: &RelyingParty,
+    auth_data: &AuthenticatorData,
+    require_user_verification: bool,
+) -> Result<(), WebauthnError> {
+    if auth_data.rp_id_hash[..] != Sha256::digest(rp.id.as_bytes())[..] {
+        return Err(WebauthnError::RpIdMismatch);
+    }
+
+    if auth_data.flags & FLAG_USER_PRESENT == 0
+        || (require_user_verification && auth_data.flags & FLAG_USER_VERIFIED == 0)
+    {
+        return Err(WebauthnError::UserNotVerified);
+    }
+
+    Ok(())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData, WebauthnError> {
+    // rpIdHash (32) | flags (1) | signCount (4) | attestedCredentialData? | extensions?
+    if data.len() < 37 {
+        return Err(WebauthnError::Malformed("authenticator data"));
+    }
+
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        // aaguid (16) | credentialIdLength (2) | credentialId | credentialPublicKey
+        let rest = &data[37..];
+        if rest.len() < 18 {
+            return Err(WebauthnError::Malformed("attested credential data"));
+        }
+
+        let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+        let rest = &rest[18..];
+        if rest.len() < id_len {
+            return Err(WebauthnError::Malformed("credential id"));
+        }
+
+        let (credential_id, mut key_bytes) = rest.split_at(id_len);
+        let key_start = key_bytes;
+
+        // The COSE key is followed by optional extensions, so read exactly one CBOR item
+        let _: Value = ciborium::de::from_reader(&mut key_bytes)
+            .map_err(|_| WebauthnError::Malformed("credential public key"))?;
+        let key_len = key_start.len() - key_bytes.len();
+
+        Some((credential_id.to_vec(), key_start[..key_len].to_vec()))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+enum PublicKey {
+    Es256(P256VerifyingKey),
+    EdDsa(Ed25519VerifyingKey),
+}
+
+fn parse_public_key(cose_key: &[u8]) -> Result<PublicKey, WebauthnError> {
+    let key: Value = ciborium::de::from_reader(cose_key)
+        .map_err(|_| WebauthnError::Malformed("credential public key"))?;
+    let fields = key.as_map().ok_or(WebauthnError::Malformed("credential public key"))?;
+
+    // COSE_Key labels: 1 = kty, 3 = alg, -1 = crv, -2 = x, -3 = y
+    let alg = map_get_int(fields, 3)
+        .and_then(Value::as_integer)
+        .and_then(|alg| i64::try_from(alg).ok())
+        .ok_or(WebauthnError::Malformed("credential public key"))?;
+    let x = map_get_int(fields, -2)
+        .and_then(Value::as_bytes)
+        .ok_or(WebauthnError::Malformed("credential public key"))?;
+
+    match alg {
+        ALG_ES256 => {
+            let y = map_get_int(fields, -3)
+                .and_then(Value::as_bytes)
+                .ok_or(WebauthnError::Malformed("credential public key"))?;
+
+            let mut point = vec![0x04];
+            point.extend_from_slice(x);
+            point.extend_from_slice(y);
+
+            P256VerifyingKey::from_sec1_bytes(&point)
+                .map(PublicKey::Es256)
+                .map_err(|_| WebauthnError::Malformed("credential public key"))
+        }
+        ALG_EDDSA => {
+            let x: [u8; 32] = x
+                .as_slice()
+                .try_into()
+                .map_err(|_| WebauthnError::Malformed("credential public key"))?;
+
+            Ed25519VerifyingKey::from_bytes(&x)
+                .map(PublicKey::EdDsa)
+                .map_err(|_| WebauthnError::Malformed("credential public key"))
+        }
+        _ => Err(WebauthnError::UnsupportedAlgorithm),
+    }
+}
+
+fn map_get_text<'a>(fields: &'a [(Value, Value)], key: &str) -> Option<&'a Value> {
+    fields
+        .iter()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn map_get_int(fields: &[(Value, Value)], key: i64) -> Option<&Value> {
+    fields
+        .iter()
+        .find(|(k, _)| k.as_integer().and_then(|k| i64::try_from(k).ok()) == Some(key))
+        .map(|(_, v)| v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::{signature::Signer, SigningKey};
+    use p256::elliptic_curve::rand_core::OsRng;
+
+    const RP_ID: &str = "localhost";
+    const ORIGIN: &str = "http://localhost:3000";
+
+    fn cose_key(signing_key: &SigningKey) -> Vec<u8> {
+        let point = signing_key.verifying_key().to_encoded_point(false);
+        let key = Value::Map(vec![
+            (Value::from(1), Value::from(2)),
+            (Value::from(3), Value::from(ALG_ES256)),
+            (Value::from(-1), Value::from(1)),
+            (Value::from(-2), Value::Bytes(point.x().unwrap().to_vec())),
+            (Value::from(-3), Value::Bytes(point.y().unwrap().to_vec())),
+        ]);
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&key, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn authenticator_data(flags: u8, sign_count: u32, attested: Option<(&[u8], &[u8])>) -> Vec<u8> {
+        let mut data = Sha256::digest(RP_ID.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+
+        if let Some((credential_id, public_key)) = attested {
+            data.extend_from_slice(&[0u8; 16]);
+            data.extend_from_slice(&(credential_id.len() as u16).to_be_bytes());
+            data.extend_from_slice(credential_id);
+            data.extend_from_slice(public_key);
+        }
+
+        data
+    }
+
+    fn client_data(ceremony_type: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::json!({ "type": ceremony_type, "challenge": challenge, "origin": origin })
+            .to_string()
+            .into_bytes()
+    }
+
+    fn attestation_object(auth_data: Vec<u8>) -> Vec<u8> {
+        let object = Value::Map(vec![
+            (Value::from("fmt"), Value::from("none")),
+            (Value::from("attStmt"), Value::Map(vec![])),
+            (Value::from("authData"), Value::Bytes(auth_data)),
+        ]);
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&object, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_registration_and_assertion() {
+        let origins = vec![ORIGIN.to_string()];
+        let rp = RelyingParty { id: RP_ID, origins: &origins };
+        let signing_key = SigningKey::random(&mut OsRng);
+        let credential_id = b"credential-1".to_vec();
+        let public_key = cose_key(&signing_key);
+
+        // Registration
+        let challenge = generate_challenge();
+        let auth_data = authenticator_data(
+            FLAG_USER_PRESENT | FLAG_USER_VERIFIED | FLAG_ATTESTED_CREDENTIAL,
+            0,
+            Some((&credential_id, &public_key)),
+        );
+        let registered = verify_registration(
+            &rp,
+            &challenge,
+            &client_data("webauthn.create", &challenge, ORIGIN),
+            &attestation_object(auth_data),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(registered.credential_id, credential_id);
+        assert_eq!(registered.public_key, public_key);
+
+        // Authentication
+        let challenge = generate_challenge();
+        let auth_data = authenticator_data(FLAG_USER_PRESENT | FLAG_USER_VERIFIED, 5, None);
+        let client_data_json = client_data("webauthn.get", &challenge, ORIGIN);
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data_json));
+        let signature: P256Signature = signing_key.sign(&signed);
+        let signature = signature.to_der();
+
+        let sign_count = verify_assertion(
+            &rp,
+            &challenge,
+            &client_data_json,
+            &auth_data,
+            signature.as_bytes(),
+            &registered.public_key,
+            0,
+            true,
+        )
+        .unwrap();
+        assert_eq!(sign_count, 5);
+
+        // A counter that doesn't move forward means a cloned authenticator
+        let replay = verify_assertion(
+            &rp,
+            &challenge,
+            &client_data_json,
+            &auth_data,
+            signature.as_bytes(),
+            &registered.public_key,
+            5,
+            true,
+        );
+        assert!(matches!(replay, Err(WebauthnError::CounterRegression)));
+
+        // Tampered authenticator data no longer matches the signature
+        let mut tampered = auth_data.clone();
+        tampered[36] = 6;
+        let tampered = verify_assertion(
+            &rp,
+            &challenge,
+            &client_data_json,
+            &tampered,
+            signature.as_bytes(),
+            &registered.public_key,
+            0,
+            true,
+        );
+        assert!(matches!(tampered, Err(WebauthnError::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_registration_rejects_wrong_origin_and_missing_verification() {
+        let origins = vec![ORIGIN.to_string()];
+        let rp = RelyingParty { id: RP_ID, origins: &origins };
+        let signing_key = SigningKey::random(&mut OsRng);
+        let public_key = cose_key(&signing_key);
+        let challenge = generate_challenge();
+
+        let auth_data = authenticator_data(FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL, 0, Some((b"id", &public_key)));
+        let object = attestation_object(auth_data);
+
+        let wrong_origin = verify_registration(
+            &rp,
+            &challenge,
+            &client_data("webauthn.create", &challenge, "https://evil.example"),
+            &object,
+            false,
+        );
+        assert!(matches!(wrong_origin, Err(WebauthnError::ClientDataMismatch)));
+
+        let unverified = verify_registration(
+            &rp,
+            &challenge,
+            &client_data("webauthn.create", &challenge, ORIGIN),
+            &object,
+            true,
+        );
+        assert!(matches!(unverified, Err(WebauthnError::UserNotVerified)));
+    }
+}
\ No newline at end of file